@@ -4,6 +4,7 @@
 #include <assert.h>
 #include <stdlib.h>
 #include <unistd.h>
+#include <sys/ioctl.h>
 
 int main(int argc, char **args) {
     if (argc == 1) {
@@ -42,6 +43,20 @@ int main(int argc, char **args) {
         getline(&line, &line_size, stdin);
         fprintf(stdout, "%s", line);
         fflush(stdout);
+    } else if (strcmp("isatty", arg) == 0) {
+        struct winsize size = {0};
+        ioctl(STDOUT_FILENO, TIOCGWINSZ, &size);
+        printf("\x1b[31m%s\x1b[0m %dx%d\n", isatty(STDOUT_FILENO) ? "tty" : "pipe", size.ws_row,
+               size.ws_col);
+    } else if (strcmp("repl", arg) == 0) {
+        char *line = NULL;
+        size_t line_size;
+        printf("> ");
+        fflush(stdout);
+        while (getline(&line, &line_size, stdin) != -1) {
+            printf("echo: %s> ", line);
+            fflush(stdout);
+        }
     }
     return 0;
 }
@@ -1,8 +1,20 @@
-use std::path::{Path, PathBuf};
+use std::{
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::{anyhow, Result};
+use nix::{errno::Errno, fcntl::OFlag};
 use std::result::Result as StdResult;
 use thiserror::Error;
+use tokio::{
+    fs::{read_dir, OpenOptions},
+    io::AsyncReadExt,
+    time::timeout,
+};
+use tracing::warn;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -11,11 +23,212 @@ pub enum Error {
 
     #[error("TestId not recognized")]
     UnknownTestId,
+
+    #[error("Invalid glob pattern: {0}")]
+    InvalidPattern(String),
+
+    #[error("Path leads outside of its root directory: {0}")]
+    EscapesRoot(String),
+
+    #[error("Not a regular file: {0}")]
+    NotRegularFile(String),
+
+    #[error("File is {size} bytes, over the limit of {limit} bytes: {file}")]
+    TooLarge { file: String, size: u64, limit: u64 },
+
+    #[error("Timed out reading file: {0}")]
+    ReadTimedOut(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
 }
 
 /// Responsible for locating a file resource for a test given a filename,
 pub trait ResourceLocator: Send + Sync {
     fn find(&self, name: &String) -> StdResult<PathBuf, Error>;
+
+    /// Locates a directory resource. Locators which can only give files don't have any.
+    fn find_dir(&self, _name: &String) -> StdResult<PathBuf, Error> {
+        Err(Error::NotFound)
+    }
+
+    /// Locates every file resource matching a glob pattern, along with the name each was found
+    /// under. Locators which can only give files by name don't have any.
+    fn find_matching(&self, _pattern: &str) -> StdResult<Vec<(String, PathBuf)>, Error> {
+        Err(Error::NotFound)
+    }
+}
+
+/// Lets a locator be shared between several owners, ex: as a source of more than one
+/// MultiSourceFinder.
+impl<L: ResourceLocator + ?Sized> ResourceLocator for Arc<L> {
+    fn find(&self, name: &String) -> StdResult<PathBuf, Error> {
+        self.as_ref().find(name)
+    }
+
+    fn find_dir(&self, name: &String) -> StdResult<PathBuf, Error> {
+        self.as_ref().find_dir(name)
+    }
+
+    fn find_matching(&self, pattern: &str) -> StdResult<Vec<(String, PathBuf)>, Error> {
+        self.as_ref().find_matching(pattern)
+    }
+}
+
+/// Resolves a relative path, which came from a config or from student code, inside of the root
+/// dir. Absolute paths, `..` which leaves the root and symlinks which lead outside of the root are
+/// all rejected, so the returned path is always inside of the root. The path doesn't need to
+/// exist, but the root does.
+pub fn resolve_in<P: AsRef<Path>>(root: &Path, name: P) -> StdResult<PathBuf, Error> {
+    let name = name.as_ref();
+    let escapes = || Error::EscapesRoot(name.display().to_string());
+
+    // `..` is applied to the path before it's used, so it can't be used to leave a symlinked dir
+    let mut relative = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !relative.pop() {
+                    return Err(escapes());
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return Err(escapes()),
+        }
+    }
+
+    let canonical_root = root.canonicalize().map_err(|_| Error::NotFound)?;
+    let resolved = root.join(&relative);
+
+    // canonicalizing the deepest part of the path which exists resolves every symlink on the way
+    // to it. A dangling symlink can't be canonicalized, and is rejected since writing to it would
+    // create its target.
+    for ancestor in resolved.ancestors() {
+        if ancestor.symlink_metadata().is_err() {
+            continue;
+        }
+        match ancestor.canonicalize() {
+            Ok(canonical) if canonical.starts_with(&canonical_root) => break,
+            _ => return Err(escapes()),
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// The limits on reading a file made by student code.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadLimits {
+    pub max_size: u64,
+    pub timeout: Duration,
+}
+
+/// Reads a file made by student code, which can be anything the student code was able to create.
+/// Only a regular file within the size limit is read. Symlinks aren't followed, a FIFO or device
+/// is rejected instead of blocking, and the read gives up once the timeout passes.
+pub async fn read_untrusted(path: &Path, limits: ReadLimits) -> StdResult<Vec<u8>, Error> {
+    let name = || path.display().to_string();
+
+    let read = async {
+        // opening a FIFO blocks until something opens the other end, unless it's non blocking
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags((OFlag::O_NOFOLLOW | OFlag::O_NONBLOCK).bits())
+            .open(path)
+            .await
+            .map_err(|e| match e.raw_os_error() {
+                Some(code) if code == Errno::ELOOP as i32 => Error::NotRegularFile(name()),
+                _ if e.kind() == ErrorKind::NotFound => Error::NotFound,
+                _ => e.into(),
+            })?;
+
+        // checked on the opened file, so the path can't be swapped out after the check
+        let metadata = file.metadata().await?;
+        if !metadata.is_file() {
+            return Err(Error::NotRegularFile(name()));
+        }
+
+        let too_large = |size| Error::TooLarge {
+            file: name(),
+            size,
+            limit: limits.max_size,
+        };
+        if metadata.len() > limits.max_size {
+            return Err(too_large(metadata.len()));
+        }
+
+        // the file can still grow while it's read if something the student started is writing to it
+        let mut contents = Vec::new();
+        file.take(limits.max_size + 1)
+            .read_to_end(&mut contents)
+            .await?;
+        if contents.len() as u64 > limits.max_size {
+            return Err(too_large(contents.len() as u64));
+        }
+
+        Ok(contents)
+    };
+
+    match timeout(limits.timeout, read).await {
+        Ok(res) => res,
+        Err(_) => Err(Error::ReadTimedOut(name())),
+    }
+}
+
+/// find_dir for a locator backed by a directory on disk
+pub fn find_dir_in(dir: &Path, name: &String) -> StdResult<PathBuf, Error> {
+    let found = resolve_in(dir, name)?;
+    match found.is_dir() {
+        true => Ok(found),
+        false => Err(Error::NotFound),
+    }
+}
+
+/// find_matching for a locator backed by a directory on disk. Only files match, and it's not an
+/// error for nothing to match.
+pub fn find_matching_in(dir: &Path, pattern: &str) -> StdResult<Vec<(String, PathBuf)>, Error> {
+    let full_pattern = resolve_in(dir, pattern)?;
+    let paths = glob::glob(&full_pattern.to_string_lossy())
+        .map_err(|e| Error::InvalidPattern(format!("{}: {}", pattern, e)))?;
+
+    let mut found = Vec::new();
+    for path in paths.filter_map(|path| path.ok()) {
+        if !path.is_file() {
+            continue;
+        }
+        let Ok(name) = path.strip_prefix(dir) else {
+            continue;
+        };
+        match resolve_in(dir, name) {
+            Ok(_) => found.push((name.to_string_lossy().to_string(), path.clone())),
+            Err(_) => warn!(file=?path, "skipping file which leads outside of its dir"),
+        }
+    }
+
+    Ok(found)
+}
+
+/// Returns the path of every regular file in the dir from the root of the dir, recursively.
+/// Symlinks are skipped since they could point anywhere on the system.
+pub async fn files_in(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+
+    while let Some(relative) = dirs.pop() {
+        let mut entries = read_dir(dir.join(&relative)).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = relative.join(entry.file_name());
+            match entry.file_type().await? {
+                t if t.is_dir() => dirs.push(path),
+                t if t.is_file() => files.push(path),
+                _ => warn!(file=?dir.join(&path), "skipping file which isn't a regular file"),
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
 }
 
 /// can create a resource locator based on the ws
@@ -49,3 +262,97 @@ pub fn filename<'a>(file: &'a Path) -> Result<&'a str> {
             file.display()
         ))?)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::MockDir;
+
+    use super::*;
+
+    #[test]
+    fn resolves_paths_inside_root() {
+        let dir = MockDir::new().dir("sub", MockDir::new().file(("file", "")));
+        let root = dir.root.path();
+
+        assert_eq!(resolve_in(root, "sub/file").unwrap(), root.join("sub/file"));
+        assert_eq!(resolve_in(root, "./sub/../new").unwrap(), root.join("new"));
+        assert_eq!(
+            resolve_in(root, "sub/missing/file").unwrap(),
+            root.join("sub/missing/file")
+        );
+    }
+
+    #[test]
+    fn rejects_paths_escaping_root() {
+        let outside = MockDir::new().file(("secret", ""));
+        let dir = MockDir::new().dir("sub", MockDir::new());
+        let root = dir.root.path();
+        std::os::unix::fs::symlink(outside.root.path(), root.join("link")).unwrap();
+        std::os::unix::fs::symlink(root.join("sub"), root.join("inside_link")).unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), root.join("dangling")).unwrap();
+
+        for name in [
+            "../secret",
+            "sub/../../secret",
+            "/etc/passwd",
+            "link/secret",
+            "link/new_file",
+            "dangling",
+        ] {
+            assert!(
+                matches!(resolve_in(root, name), Err(Error::EscapesRoot(_))),
+                "{} wasn't rejected",
+                name
+            );
+        }
+
+        assert!(resolve_in(root, "inside_link/new_file").is_ok());
+        assert!(matches!(
+            resolve_in(&root.join("missing"), "file"),
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[test]
+    fn matching_skips_links_outside_dir() {
+        let outside = MockDir::new().file(("secret.txt", ""));
+        let dir = MockDir::new().file(("a.txt", ""));
+        std::os::unix::fs::symlink(
+            outside.path_from_root("secret.txt"),
+            dir.path_from_root("b.txt"),
+        )
+        .unwrap();
+
+        let found = find_matching_in(dir.root.path(), "*.txt").unwrap();
+        assert_eq!(found, [("a.txt".to_string(), dir.path_from_root("a.txt"))]);
+        assert!(find_matching_in(dir.root.path(), "../*.txt").is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_only_regular_files_within_limits() {
+        let dir = MockDir::new()
+            .file(("small", "small"))
+            .file(("large", "a".repeat(100)));
+        std::os::unix::fs::symlink(dir.path_from_root("small"), dir.path_from_root("link"))
+            .unwrap();
+        nix::unistd::mkfifo(&dir.path_from_root("fifo"), nix::sys::stat::Mode::S_IRWXU).unwrap();
+
+        let limits = ReadLimits {
+            max_size: 10,
+            timeout: Duration::from_secs(1),
+        };
+        let read = |name| {
+            let path = dir.path_from_root(name);
+            async move { read_untrusted(&path, limits).await }
+        };
+
+        assert_eq!(read("small").await.unwrap(), b"small");
+        assert!(matches!(
+            read("large").await,
+            Err(Error::TooLarge { size: 100, .. })
+        ));
+        assert!(matches!(read("link").await, Err(Error::NotRegularFile(_))));
+        assert!(matches!(read("fifo").await, Err(Error::NotRegularFile(_))));
+        assert!(matches!(read("missing").await, Err(Error::NotFound)));
+    }
+}
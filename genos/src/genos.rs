@@ -6,7 +6,7 @@ use std::{
 use crate::{
     gs::Description,
     output::Output,
-    test::{Test, TestResult},
+    test::{Test, TestResult, TestStatus},
     writer::{ResultsWriter, TestOutput},
 };
 use anyhow::{anyhow, Context, Error, Result};
@@ -63,14 +63,14 @@ impl Genos {
     async fn run_all_tests(&self) -> Vec<Arc<RunResult>> {
         let mut results = Vec::new();
 
-        // first, run the setup test cases serially
+        // first, run the setup test cases serially. The tests can't be run if the setup failed.
         for setup_test in &self.setup {
             let res = run_test_and_process_result(self.workspace.clone(), setup_test.clone()).await;
-            let is_err = res.err.is_some();
+            let failed = res.err.is_some() || matches!(res.res.status, TestStatus::Fail(_));
 
             results.push(Arc::new(res));
 
-            if is_err {
+            if failed {
                 return results;
             }
         }
@@ -199,9 +199,9 @@ impl GenosBuilder {
         self
     }
 
-    /// Setup are special tests which are run before any of the actual tests. Failing these tests
-    /// results will cause Genos to skip running any additional unit tests. These are commonly used
-    /// to setup the testing environment, validate the submission, etc.
+    /// Setup are special tests which are run before any of the actual tests. Failing one of these
+    /// tests, or a system error in one, will cause Genos to skip running any additional tests.
+    /// These are commonly used to setup the testing environment, validate the submission, etc.
     pub fn setup<T: TestRequest + 'static>(mut self, setup: T) -> Self {
         self.genos.setup.push(Arc::new(setup));
         self
@@ -428,6 +428,30 @@ mod tests {
         assert_eq!(results.len(), 2);
     }
 
+    #[tokio::test]
+    async fn stops_at_setup_failure() {
+        let mut failed = TestResult::new(Points::new(1));
+        failed.lose_full_points();
+        let setup_tests = get_tests_with_results([Ok(failed), Ok(TestResult::new(Points::new(1)))]);
+        let real_tests = get_tests_with_results([Ok(TestResult::new(Points::new(1)))]);
+
+        let results = Arc::new(Mutex::new(None));
+        let writer = MockWriter {
+            results: results.clone(),
+        };
+
+        let genos = Genos::builder()
+            .setups(setup_tests)
+            .tests(real_tests)
+            .writer(writer)
+            .build();
+
+        let test_results = genos.run().await.unwrap();
+
+        assert_eq!(test_results.len(), 1);
+        assert_eq!(results.lock().unwrap().take().unwrap().len(), 1);
+    }
+
     #[tokio::test]
     async fn multiple_system_errors_in_tests() {
         let real_tests = get_tests_with_results([
@@ -1,10 +1,11 @@
 use std::{fmt::Display, path::PathBuf, sync::Arc};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{points::Points, test, tid::TestId};
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema, Serialize)]
 pub enum Visibility {
     Hidden,
     Visible,
@@ -37,7 +38,7 @@ pub trait Description {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema, Serialize)]
 pub struct TestDescription {
     pub name: String,
     pub description: String,
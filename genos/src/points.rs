@@ -3,6 +3,11 @@ use std::{
     ops::{Add, AddAssign, Sub, SubAssign},
 };
 
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, NumberValidation, Schema, SchemaObject},
+    JsonSchema,
+};
 use serde::{de, Deserialize, Serialize, Serializer};
 use thiserror::Error;
 
@@ -205,9 +210,33 @@ impl Serialize for Points {
     }
 }
 
+// the schema has the same constraints as deserializing, so editors can check points in configs
+impl JsonSchema for Points {
+    fn schema_name() -> String {
+        "Points".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::Number.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some("Points must be a multiple of 0.25".to_string()),
+                ..Default::default()
+            })),
+            number: Some(Box::new(NumberValidation {
+                multiple_of: Some(0.25),
+                minimum: Some(0.0),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 /// Points type is a convenience enum for use in configuration files which could be used to
 /// indicate if a stage in a test is worth partial or full points.
-#[derive(Debug, Eq, PartialEq, Clone, Copy, Deserialize)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Deserialize, JsonSchema)]
 pub enum PointQuantity {
     FullPoints,
     Partial(Points),
@@ -6,26 +6,53 @@ use std::{
     collections::HashMap,
     env,
     fmt::Display,
-    fs,
-    os::unix::process::ExitStatusExt,
-    path::PathBuf,
+    fs, iter,
+    os::{
+        fd::{AsRawFd, OwnedFd, RawFd},
+        unix::process::ExitStatusExt,
+    },
+    path::{Path, PathBuf},
     process::{ExitStatus as StdExitStatus, Stdio},
     sync::Arc,
     time::Duration,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
-use futures::future::join_all;
+use futures::future::{join, join_all};
+use nix::{
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    sys::signal::{killpg, Signal},
+    unistd::{setpgid, Pid},
+};
 use tokio::{
     fs::File,
-    io::{copy, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader},
+    io::{copy, split, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader},
     process::{Child, ChildStdin, Command as TokioCommand},
-    sync::Mutex,
+    sync::{mpsc, Mutex},
     task::JoinHandle,
     time::timeout,
 };
-use tracing::info;
+use tracing::{debug, info};
+
+pub mod interaction;
+pub mod isolate;
+pub mod pty;
+pub mod seccomp;
+pub mod signal;
+
+pub use interaction::{Interaction, Transcript};
+pub use isolate::IsolatedExecutor;
+pub use pty::PtyConfig;
+pub use seccomp::SyscallPolicy;
+pub use signal::{ScheduledSignal, SentSignal, SignalName};
+
+use pty::{kill_session, Pty};
+use seccomp::SyscallFilter;
+use signal::SignalScheduler;
+
+// how long to wait for the output of a pty after the process exits
+const PTY_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Command is a struct which acts similar to a builder and wraps an instance of a tokio async
 /// command. Once built it can be run multiple times on any given executor. An executor is a struct
@@ -46,6 +73,19 @@ pub struct Command {
     pub stderr: Option<PathBuf>,
     pub stdout: Option<PathBuf>,
     pub timeout: Option<Duration>,
+    pub pty: Option<PtyConfig>,
+    /// commands which the stdout of this command is piped through, in order
+    pub pipeline: Vec<Command>,
+    /// signals sent to the process while it runs, see ScheduledSignal
+    pub signals: Vec<ScheduledSignal>,
+    /// the syscalls the process is allowed to make, see SyscallPolicy
+    pub seccomp: Option<SyscallPolicy>,
+    /// run the process in its own process group, which is killed once the process exits or the
+    /// run is dropped, so nothing the process started is left running
+    pub process_group: bool,
+    /// fds which the process inherits under the same numbers. They're closed on exec in every
+    /// other process, so only this one gets them.
+    pub inherited_fds: Vec<Arc<OwnedFd>>,
 }
 
 impl Command {
@@ -130,13 +170,83 @@ impl Command {
         self.timeout = Some(timeout.into());
     }
 
+    /// Run the command attached to a pseudo terminal, see PtyConfig
+    pub fn pty(mut self, cfg: PtyConfig) -> Self {
+        self.pty = Some(cfg);
+        self
+    }
+
+    pub fn set_pty(&mut self, cfg: PtyConfig) {
+        self.pty = Some(cfg);
+    }
+
+    /// Pipe the stdout of the pipeline into next, like `self | next`. The stdin, stdout, stderr
+    /// and timeout of this command apply to the whole pipeline, so they are ignored on next.
+    pub fn pipe(mut self, next: Command) -> Self {
+        self.add_pipe(next);
+        self
+    }
+
+    pub fn add_pipe(&mut self, mut next: Command) {
+        // flatten so that piping into a pipeline keeps every command in order
+        let rest = std::mem::take(&mut next.pipeline);
+        self.pipeline.push(next);
+        self.pipeline.extend(rest);
+    }
+
+    /// Send a signal to the process while it runs. Signals are sent in the order they're added.
+    pub fn signal(mut self, signal: ScheduledSignal) -> Self {
+        self.signals.push(signal);
+        self
+    }
+
+    pub fn add_signal(&mut self, signal: ScheduledSignal) {
+        self.signals.push(signal);
+    }
+
+    /// Restrict the syscalls the process can make, see SyscallPolicy
+    pub fn seccomp(mut self, policy: SyscallPolicy) -> Self {
+        self.seccomp = Some(policy);
+        self
+    }
+
+    pub fn set_seccomp(&mut self, policy: SyscallPolicy) {
+        self.seccomp = Some(policy);
+    }
+
+    pub fn process_group(mut self) -> Self {
+        self.process_group = true;
+        self
+    }
+
+    pub fn inherit_fd<T: Into<OwnedFd>>(mut self, fd: T) -> Self {
+        self.inherited_fds.push(Arc::new(fd.into()));
+        self
+    }
+
     pub async fn run_with<E: ProcessExecutor>(&self, executor: &E) -> Result<Output> {
         executor.run(self).await
     }
 }
 
-impl Display for Command {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Command {
+    // relative io files are relative to the cwd of the command, like redirections in a shell
+    fn io_path(&self, path: &Path) -> PathBuf {
+        match &self.cwd {
+            Some(cwd) => cwd.join(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    fn resolve_stdin(&self, stdin: &StdinPipe) -> StdinPipe {
+        match stdin {
+            StdinPipe::Path(path) => StdinPipe::Path(self.io_path(path)),
+            stdin => stdin.clone(),
+        }
+    }
+
+    // the env, program and args of the command, without any of the redirections
+    fn invocation(&self) -> Vec<String> {
         let mut output = Vec::new();
 
         output.extend(
@@ -154,10 +264,23 @@ impl Display for Command {
             }
         }));
 
+        output
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut output = self.invocation();
+
         if let Some(stdin) = &self.stdin {
             output.extend(["<".to_string(), format!("{}", stdin)].into_iter());
         }
 
+        for next in &self.pipeline {
+            output.push("|".to_string());
+            output.extend(next.invocation());
+        }
+
         output.extend(
             [(&self.stdout, ">"), (&self.stderr, "2>")]
                 .into_iter()
@@ -178,6 +301,8 @@ pub enum StdinPipe {
     String(String),
     Path(PathBuf),
     File(Arc<Mutex<File>>),
+    /// have a turn by turn conversation with the process, see Interaction
+    Interaction(Interaction),
 }
 
 impl Display for StdinPipe {
@@ -186,6 +311,7 @@ impl Display for StdinPipe {
             Self::String(s) => write!(f, "{:?}", s),
             Self::Path(path) => write!(f, "{}", path.display()),
             Self::File(_file) => write!(f, "[file pointer]"),
+            Self::Interaction(_) => write!(f, "[interaction]"),
         }
     }
 }
@@ -197,11 +323,21 @@ impl Display for StdinPipe {
 ///
 /// ProcessExitStatus contains the exit code. Negative exit codes are wrapped around 256, so if a
 /// program exits -10, then the resulting exit code will be 246
+///
+/// The transcript is only set when the command was run with an interaction.
+///
+/// For a pipeline, status is the status of the last command the same way it is in a shell, and
+/// the status of every command is in pipeline_statuses. The stderr of every command in the
+/// pipeline is captured in order.
 #[derive(Clone)]
 pub struct Output {
     pub status: ExitStatus,
     pub stdout: String,
     pub stderr: String,
+    pub transcript: Option<Transcript>,
+    pub pipeline_statuses: Vec<ExitStatus>,
+    /// the scheduled signals which were sent before the process exited
+    pub signals_sent: Vec<SentSignal>,
 }
 
 impl Output {
@@ -210,14 +346,25 @@ impl Output {
             status,
             stdout: stdout.as_ref().to_string(),
             stderr: stderr.as_ref().to_string(),
+            transcript: None,
+            pipeline_statuses: Vec::new(),
+            signals_sent: Vec::new(),
         }
     }
 
+    pub fn with_transcript(mut self, transcript: Transcript) -> Self {
+        self.transcript = Some(transcript);
+        self
+    }
+
     pub fn from_exit_status(status: ExitStatus) -> Self {
         Self {
             status,
             stdout: "".to_string(),
             stderr: "".to_string(),
+            transcript: None,
+            pipeline_statuses: Vec::new(),
+            signals_sent: Vec::new(),
         }
     }
 }
@@ -228,6 +375,9 @@ impl From<(StdExitStatus, Vec<u8>, Vec<u8>)> for Output {
             status: ExitStatus::from(status),
             stdout: String::from_utf8_lossy(&stdout).to_string(),
             stderr: String::from_utf8_lossy(&stderr).to_string(),
+            transcript: None,
+            pipeline_statuses: Vec::new(),
+            signals_sent: Vec::new(),
         }
     }
 }
@@ -244,6 +394,36 @@ pub trait ProcessExecutor: Send + Sync + Clone {
 pub struct ShellExecutor;
 
 impl ShellExecutor {
+    fn make_process(cmd: &Command) -> TokioCommand {
+        let mut process = TokioCommand::new(cmd.program.clone());
+        process
+            .kill_on_drop(true) // dropping the child results in sending sigkill to the process
+            .args(cmd.args.clone())
+            .env_clear() // the child process should have a fresh env
+            .envs(cmd.envs.clone())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        if let Some(cwd) = &cmd.cwd {
+            process.current_dir(cwd.clone());
+        }
+
+        if !cmd.inherited_fds.is_empty() {
+            let fds: Vec<RawFd> = cmd.inherited_fds.iter().map(|fd| fd.as_raw_fd()).collect();
+            // only async signal safe calls are allowed between fork and exec
+            unsafe {
+                process.pre_exec(move || {
+                    for fd in &fds {
+                        fcntl(*fd, FcntlArg::F_SETFD(FdFlag::empty()))?;
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        process
+    }
+
     fn attach_pipes(cmd: &Command, process: &mut TokioCommand) {
         if cmd.stdin.is_some() {
             process.stdin(Stdio::piped());
@@ -259,34 +439,69 @@ impl ShellExecutor {
     }
 
     fn spawn_stdin_task(stdin: StdinPipe, mut pipe: ChildStdin) -> JoinHandle<Result<()>> {
-        let handle = tokio::spawn(async move {
-            match &stdin {
-                StdinPipe::String(string) => {
-                    let mut reader = string.as_bytes();
-                    copy(&mut reader, &mut pipe).await?;
-                }
-                StdinPipe::Path(path) => {
-                    let mut reader = File::open(path).await?;
-                    copy(&mut reader, &mut pipe).await?;
-                }
-                StdinPipe::File(file) => {
-                    let mut file = &mut *file.lock().await;
-                    // rewind the curser to the beginning of the file. This is to prevent any
-                    // issued where a user writes to a file and then expects that write to show up
-                    // when we pipe it to the process If we didn't rewind, then the cursor for the
-                    // file would remain at the same place as when the write completed, which means
-                    // no content would be piped.
-                    file.rewind().await?;
-                    copy(&mut file, &mut pipe).await?;
-                }
+        tokio::spawn(async move { Self::write_stdin(&stdin, &mut pipe).await })
+    }
+
+    async fn write_stdin<W: AsyncWrite + Unpin>(stdin: &StdinPipe, pipe: &mut W) -> Result<()> {
+        match stdin {
+            StdinPipe::String(string) => {
+                let mut reader = string.as_bytes();
+                copy(&mut reader, pipe).await?;
             }
-            Ok(())
-        });
+            StdinPipe::Path(path) => {
+                let mut reader = File::open(path).await?;
+                copy(&mut reader, pipe).await?;
+            }
+            StdinPipe::File(file) => {
+                let mut file = &mut *file.lock().await;
+                // rewind the curser to the beginning of the file. This is to prevent any
+                // issued where a user writes to a file and then expects that write to show up
+                // when we pipe it to the process If we didn't rewind, then the cursor for the
+                // file would remain at the same place as when the write completed, which means
+                // no content would be piped.
+                file.rewind().await?;
+                copy(&mut file, pipe).await?;
+            }
+            StdinPipe::Interaction(_) => {
+                unreachable!("interactions are run separately from the other stdin pipes")
+            }
+        }
+        Ok(())
+    }
+
+    // Waits for the child while the scheduler sends its signals. The scheduler is only polled while
+    // the child hasn't been reaped, so a signal can't be sent to a reused pid.
+    async fn wait_with_signals(
+        child: &mut Child,
+        scheduler: Option<&mut (SignalScheduler, mpsc::UnboundedReceiver<Vec<u8>>)>,
+    ) -> Result<StdExitStatus> {
+        let Some((scheduler, rx)) = scheduler else {
+            return Ok(child.wait().await?);
+        };
 
-        handle
+        let rx = std::mem::replace(rx, mpsc::unbounded_channel().1);
+        let signals = scheduler.run(rx);
+        tokio::pin!(signals);
+        let mut signals_done = false;
+
+        loop {
+            tokio::select! {
+                status = child.wait() => return Ok(status?),
+                // the child is killed on drop if the signals couldn't be sent
+                res = &mut signals, if !signals_done => {
+                    res?;
+                    signals_done = true;
+                }
+            }
+        }
     }
 
-    fn spawn_io(cmd: &Command, child: &mut Child) -> Result<ProcessIo> {
+    // stdout is also forwarded to `stdout_tx` as it is read, if given
+    fn spawn_io(
+        cmd: &Command,
+        child: &mut Child,
+        stdout_tx: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    ) -> Result<ProcessIo> {
         let mut io = ProcessIo::default();
 
         if let Some(stdin) = &cmd.stdin {
@@ -294,7 +509,7 @@ impl ShellExecutor {
                 .stdin
                 .take()
                 .context("expected spawned child to have a stdin pipe")?;
-            io.stdin = Some(Self::spawn_stdin_task(stdin.clone(), pipe));
+            io.stdin = Some(Self::spawn_stdin_task(cmd.resolve_stdin(stdin), pipe));
         }
 
         let pipe = child
@@ -304,7 +519,21 @@ impl ShellExecutor {
         io.stdout = Some(tokio::spawn(async move {
             let mut reader = BufReader::new(pipe);
             let mut buffer = Vec::new();
-            reader.read_to_end(&mut buffer).await?;
+            let Some(tx) = stdout_tx else {
+                reader.read_to_end(&mut buffer).await?;
+                return Ok(buffer);
+            };
+
+            let mut chunk = [0; 4096];
+            loop {
+                let n = reader.read(&mut chunk).await?;
+                if n == 0 {
+                    break;
+                }
+                buffer.extend_from_slice(&chunk[..n]);
+                // the scheduler stops listening once every signal is sent
+                let _ = tx.send(chunk[..n].to_vec());
+            }
             Ok(buffer)
         }));
 
@@ -327,24 +556,288 @@ impl ShellExecutor {
         stdout: &Option<Vec<u8>>,
         stderr: &Option<Vec<u8>>,
     ) -> Result<()> {
-        join_all(
-            [(cmd.stdout.clone(), stdout), (cmd.stderr.clone(), stderr)]
-                .iter()
-                .map(|(cmd, output)| async move {
-                    if let Some(path) = cmd {
-                        let output = output.as_ref().unwrap();
-                        let mut file = File::create(path).await?;
-                        file.write_all(output).await?;
-                    }
-                    Ok(())
-                }),
-        )
+        join_all([(&cmd.stdout, stdout), (&cmd.stderr, stderr)].iter().map(
+            |(path, output)| async move {
+                if let Some(path) = path {
+                    let output = output.as_ref().unwrap();
+                    let mut file = File::create(cmd.io_path(path)).await?;
+                    file.write_all(output).await?;
+                    // a tokio file finishes writing in the background unless it's flushed
+                    file.flush().await?;
+                }
+                Ok(())
+            },
+        ))
         .await
         .into_iter()
         .collect()
     }
 }
 
+impl ShellExecutor {
+    // an interaction needs to read stdout while it writes to stdin, so it drives both pipes
+    // itself. The process is killed if it runs past the timeout, which also ends the interaction
+    // since stdout is closed.
+    async fn run_interaction(
+        cmd: &Command,
+        interaction: &Interaction,
+        mut child: Child,
+    ) -> Result<Output> {
+        let stdin = child
+            .stdin
+            .take()
+            .context("expected spawned child to have a stdin pipe")?;
+        let stdout = child
+            .stdout
+            .take()
+            .context("expected spawned child to have stdout pipe")?;
+        let stderr = child
+            .stderr
+            .take()
+            .context("expected spawned child to have stderr pipe")?;
+
+        let interaction = interaction.clone();
+        let driver = tokio::spawn(async move { interaction.drive(stdin, stdout).await });
+        let stderr: JoinHandle<Result<Vec<u8>>> = tokio::spawn(async move {
+            let mut reader = BufReader::new(stderr);
+            let mut buffer = Vec::new();
+            reader.read_to_end(&mut buffer).await?;
+            Ok(buffer)
+        });
+
+        let status = match cmd.timeout {
+            Some(duration) => match timeout(duration, child.wait()).await {
+                Ok(status) => ExitStatus::from(status?),
+                Err(_) => {
+                    child.kill().await?;
+                    ExitStatus::Timeout(duration)
+                }
+            },
+            None => ExitStatus::from(child.wait().await?),
+        };
+
+        let (transcript, stdout) = driver.await??;
+        let stderr = stderr.await??;
+        let (stdout, stderr) = (Some(stdout), Some(stderr));
+
+        Self::write_results_to_file(cmd, &stdout, &stderr).await?;
+
+        Ok(Output::new(
+            status,
+            String::from_utf8_lossy(&stdout.unwrap()),
+            String::from_utf8_lossy(&stderr.unwrap()),
+        )
+        .with_transcript(transcript))
+    }
+}
+
+impl ShellExecutor {
+    // in a pty stdin and stdout are the same file, so everything is read and written through the
+    // master side of the terminal.
+    async fn run_pty(
+        cmd: &Command,
+        config: &PtyConfig,
+        mut process: TokioCommand,
+    ) -> Result<Output> {
+        let pty = Pty::open(config)?;
+        pty.attach(&mut process)?;
+        let mut child = process.spawn()?;
+
+        // the child has its own copies of the slave side, the ones held here need to be closed
+        // for reads of the master to end once the child exits.
+        drop(process);
+        let Pty { master, slave } = pty;
+        drop(slave);
+
+        let session = child.id();
+        let (mut reader, mut writer) = split(master);
+        let stdin = cmd.stdin.clone();
+        let io: JoinHandle<Result<(Vec<u8>, Option<Transcript>)>> = tokio::spawn(async move {
+            if let Some(StdinPipe::Interaction(interaction)) = &stdin {
+                let (transcript, output) = interaction.drive(writer, reader).await?;
+                return Ok((output, Some(transcript)));
+            }
+
+            let write = async {
+                if let Some(stdin) = &stdin {
+                    Self::write_stdin(stdin, &mut writer).await?;
+                    writer.shutdown().await?;
+                }
+                Ok::<_, anyhow::Error>(())
+            };
+            let read = async {
+                let mut buffer = Vec::new();
+                reader.read_to_end(&mut buffer).await?;
+                Ok::<_, anyhow::Error>(buffer)
+            };
+
+            // write and read at the same time, otherwise the terminal echoing the input could
+            // fill up and block the write
+            let (write, read) = join(write, read).await;
+            if let Err(e) = write {
+                debug!("error writing stdin to pty: {}", e);
+            }
+
+            Ok((read?, None))
+        });
+
+        let status = match cmd.timeout {
+            Some(duration) => match timeout(duration, child.wait()).await {
+                Ok(status) => ExitStatus::from(status?),
+                Err(_) => ExitStatus::Timeout(duration),
+            },
+            None => ExitStatus::from(child.wait().await?),
+        };
+
+        // anything left running in the session, including the child itself after a timeout,
+        // would keep the terminal open.
+        if let Some(session) = session {
+            kill_session(session);
+        }
+        child.wait().await?;
+
+        let (stdout, transcript) = timeout(PTY_DRAIN_TIMEOUT, io)
+            .await
+            .context("pty was held open after the program exited")???;
+
+        let stdout = match config.strip_ansi {
+            true => strip_ansi_escapes::strip(&stdout),
+            false => stdout,
+        };
+
+        let (stdout, stderr) = (Some(stdout), Some(Vec::new()));
+        Self::write_results_to_file(cmd, &stdout, &stderr).await?;
+
+        let mut output = Output::new(status, String::from_utf8_lossy(&stdout.unwrap()), "");
+        output.transcript = transcript;
+        Ok(output)
+    }
+}
+
+impl ShellExecutor {
+    // each command's stdout is connected directly to the next command's stdin with an os pipe, so
+    // the data never passes through the grader.
+    async fn run_pipeline(cmd: &Command) -> Result<Output> {
+        if cmd.pty.is_some() || matches!(cmd.stdin, Some(StdinPipe::Interaction(_))) {
+            return Err(anyhow!(
+                "ptys and interactions can't be used with a pipeline: {}",
+                cmd
+            ));
+        }
+
+        if cmd.process_group {
+            return Err(anyhow!(
+                "process groups can't be used with a pipeline: {}",
+                cmd
+            ));
+        }
+
+        let commands = iter::once(cmd)
+            .chain(cmd.pipeline.iter())
+            .collect::<Vec<_>>();
+        if commands
+            .iter()
+            .any(|stage| !stage.signals.is_empty() || stage.seccomp.is_some())
+        {
+            return Err(anyhow!(
+                "signals and seccomp policies can't be used with a pipeline: {}",
+                cmd
+            ));
+        }
+
+        let mut children = Vec::new();
+        let mut stderrs = Vec::new();
+        let mut io = ProcessIo::default();
+        let mut next_stdin = None;
+
+        for (i, stage) in commands.iter().enumerate() {
+            let mut process = Self::make_process(stage);
+
+            match next_stdin.take() {
+                Some(pipe) => {
+                    process.stdin(Stdio::from(pipe));
+                }
+                None if cmd.stdin.is_some() => {
+                    process.stdin(Stdio::piped());
+                }
+                None => (),
+            }
+
+            if i + 1 < commands.len() {
+                let (reader, writer) = std::io::pipe()?;
+                process.stdout(Stdio::from(writer));
+                next_stdin = Some(reader);
+            }
+
+            let mut child = process.spawn()?;
+            // the process holds the copy of the pipe given to the child, it needs to be closed so
+            // that the next command sees eof once this one exits.
+            drop(process);
+
+            if let (0, Some(stdin)) = (i, &cmd.stdin) {
+                let pipe = child
+                    .stdin
+                    .take()
+                    .context("expected spawned child to have a stdin pipe")?;
+                io.stdin = Some(Self::spawn_stdin_task(cmd.resolve_stdin(stdin), pipe));
+            }
+
+            if let Some(pipe) = child.stdout.take() {
+                io.stdout = Some(tokio::spawn(async move {
+                    let mut buffer = Vec::new();
+                    BufReader::new(pipe).read_to_end(&mut buffer).await?;
+                    Ok(buffer)
+                }));
+            }
+
+            let pipe = child
+                .stderr
+                .take()
+                .context("expected spawned child to have stderr pipe")?;
+            stderrs.push(tokio::spawn(async move {
+                let mut buffer = Vec::new();
+                BufReader::new(pipe).read_to_end(&mut buffer).await?;
+                Ok::<_, anyhow::Error>(buffer)
+            }));
+
+            children.push(child);
+        }
+
+        let wait_all = async {
+            let mut statuses = Vec::new();
+            for child in children.iter_mut() {
+                statuses.push(ExitStatus::from(child.wait().await?));
+            }
+            Ok::<_, std::io::Error>(statuses)
+        };
+
+        let statuses = match cmd.timeout {
+            Some(duration) => match timeout(duration, wait_all).await {
+                Ok(statuses) => statuses?,
+                Err(_) => return Ok(Output::from_exit_status(ExitStatus::Timeout(duration))),
+            },
+            None => wait_all.await?,
+        };
+
+        let (stdout, _) = io.join_all().await?;
+        let mut stderr = Vec::new();
+        for pipe in stderrs {
+            stderr.extend(pipe.await??);
+        }
+        let stdout = stdout.unwrap_or_default();
+
+        Self::write_results_to_file(cmd, &Some(stdout.clone()), &Some(stderr.clone())).await?;
+
+        let mut output = Output::new(
+            statuses.last().cloned().unwrap(),
+            String::from_utf8_lossy(&stdout),
+            String::from_utf8_lossy(&stderr),
+        );
+        output.pipeline_statuses = statuses;
+        Ok(output)
+    }
+}
+
 #[derive(Default)]
 struct ProcessIo {
     stdin: Option<JoinHandle<Result<()>>>,
@@ -377,34 +870,117 @@ impl ProcessExecutor for ShellExecutor {
     async fn run(&self, cmd: &Command) -> Result<Output> {
         info!("running {}", cmd);
 
-        let mut process = TokioCommand::new(cmd.program.clone());
-        process
-            .kill_on_drop(true) // dropping the child results in sending sigkill to the process
-            .args(cmd.args.clone())
-            .env_clear() // the child process should have a fresh env
-            .envs(cmd.envs.clone())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+        if !cmd.pipeline.is_empty() {
+            return Self::run_pipeline(cmd).await;
+        }
 
-        if let Some(cwd) = &cmd.cwd {
-            process.current_dir(cwd.clone());
+        let interactive = cmd.pty.is_some() || matches!(cmd.stdin, Some(StdinPipe::Interaction(_)));
+        if interactive && !cmd.signals.is_empty() {
+            return Err(anyhow!(
+                "signals can't be used with ptys or interactions: {}",
+                cmd
+            ));
+        }
+
+        if cmd.pty.is_some() && cmd.seccomp.is_some() {
+            return Err(anyhow!(
+                "seccomp policies can't be used with a pty: {}",
+                cmd
+            ));
+        }
+
+        // a pty already runs the process in its own session, which is killed the same way
+        if cmd.pty.is_some() && cmd.process_group {
+            return Err(anyhow!("process groups can't be used with a pty: {}", cmd));
+        }
+
+        let mut process = Self::make_process(cmd);
+
+        if let Some(pty) = &cmd.pty {
+            return Self::run_pty(cmd, pty, process).await;
         }
 
         Self::attach_pipes(cmd, &mut process);
+        if cmd.process_group {
+            // only async signal safe calls are allowed between fork and exec
+            unsafe {
+                process.pre_exec(|| {
+                    setpgid(Pid::from_raw(0), Pid::from_raw(0))?;
+                    Ok(())
+                });
+            }
+        }
 
-        let mut child = process.spawn()?;
+        let filter = match &cmd.seccomp {
+            Some(policy) => Some(SyscallFilter::attach(policy, cmd, &mut process)?),
+            None => None,
+        };
+
+        let child = process.spawn()?;
+        // held until the run returns or is dropped
+        let _group = child.id().filter(|_| cmd.process_group).map(ProcessGroup);
+        // the child's end of the filter's socket is owned by the process, it needs to be closed so
+        // the filter sees the child exit if it fails before sending the listener
+        drop(process);
+        let watcher = filter
+            .zip(child.id())
+            .map(|(filter, pid)| filter.watch(pid));
+
+        let mut output = match &cmd.stdin {
+            Some(StdinPipe::Interaction(interaction)) => {
+                Self::run_interaction(cmd, interaction, child).await?
+            }
+            _ => Self::wait_for_output(cmd, child).await?,
+        };
+
+        if let Some((violation, handle)) = watcher {
+            handle.abort();
+            if let Some(syscall) = violation.lock().unwrap().take() {
+                output.status = ExitStatus::ForbiddenSyscall(syscall);
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+// kills every process left in the group when dropped. The group is named after the process which
+// leads it, so it is only valid while the leader or one of its children is still running.
+struct ProcessGroup(u32);
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Err(e) = killpg(Pid::from_raw(self.0 as i32), Signal::SIGKILL) {
+            debug!("unable to kill process group {}: {}", self.0, e);
+        }
+    }
+}
+
+impl ShellExecutor {
+    async fn wait_for_output(cmd: &Command, mut child: Child) -> Result<Output> {
+        let (stdout_tx, stdout_rx) = match cmd.signals.is_empty() {
+            true => (None, None),
+            false => {
+                let (tx, rx) = mpsc::unbounded_channel();
+                (Some(tx), Some(rx))
+            }
+        };
+        let io = Self::spawn_io(cmd, &mut child, stdout_tx)?;
 
-        let io = Self::spawn_io(cmd, &mut child)?;
+        let mut scheduler = stdout_rx
+            .zip(child.id())
+            .map(|(rx, pid)| (SignalScheduler::new(pid, cmd.signals.clone()), rx));
+        let wait = Self::wait_with_signals(&mut child, scheduler.as_mut());
 
         let res = match cmd.timeout {
             Some(duration) => {
-                let res = timeout(duration, child.wait()).await;
+                let res = timeout(duration, wait).await;
                 if let Err(_) = &res {
                     return Ok(Output::from_exit_status(ExitStatus::Timeout(duration)));
                 }
                 res.unwrap()
             }
-            None => child.wait().await,
+            None => wait.await,
         };
 
         let status = res?;
@@ -414,12 +990,16 @@ impl ProcessExecutor for ShellExecutor {
         // if command had a stdout/err configured, then write that result to the file
         Self::write_results_to_file(cmd, &stdout, &stderr).await?;
 
-        Ok((
+        let mut output: Output = (
             status,
             stdout.unwrap_or(Vec::new()),
             stderr.unwrap_or(Vec::new()),
         )
-            .into())
+            .into();
+        if let Some((scheduler, _)) = scheduler {
+            output.signals_sent = scheduler.sent().to_vec();
+        }
+        Ok(output)
     }
 }
 
@@ -429,6 +1009,8 @@ pub enum ExitStatus {
     Failure(i32),
     Timeout(Duration),
     Signal(SignalType),
+    /// killed for making a syscall its seccomp policy doesn't allow
+    ForbiddenSyscall(String),
 }
 
 impl ExitStatus {
@@ -453,6 +1035,7 @@ impl ExitStatus {
             Self::Failure(rc) => Some(*rc),
             Self::Timeout(_) => None,
             Self::Signal(signal) => Some(signal.into()),
+            Self::ForbiddenSyscall(_) => None,
         }
     }
 }
@@ -479,6 +1062,8 @@ impl From<StdExitStatus> for ExitStatus {
 pub enum SignalType {
     SegFault,
     Abort,
+    /// any other signal, ex. one sent by a ScheduledSignal
+    Other(i32),
 }
 
 impl From<i32> for SignalType {
@@ -486,7 +1071,7 @@ impl From<i32> for SignalType {
         match value {
             11 => Self::SegFault,
             6 => Self::Abort,
-            _ => panic!("unexpected signal {}", value),
+            _ => Self::Other(value),
         }
     }
 }
@@ -496,6 +1081,20 @@ impl From<&SignalType> for i32 {
         match *value {
             SignalType::SegFault => 11,
             SignalType::Abort => 6,
+            SignalType::Other(signal) => signal,
+        }
+    }
+}
+
+impl Display for SignalType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SegFault => write!(f, "SIGSEGV"),
+            Self::Abort => write!(f, "SIGABRT"),
+            Self::Other(signal) => match nix::sys::signal::Signal::try_from(*signal) {
+                Ok(signal) => write!(f, "{}", signal),
+                Err(_) => write!(f, "signal {}", signal),
+            },
         }
     }
 }
@@ -514,9 +1113,8 @@ pub fn is_program_in_path(program: &str) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
 
-    use super::*;
+    use super::{interaction::ExpectPattern, *};
     use tempfile::{tempdir, TempDir};
     use tokio::{fs::OpenOptions, io::AsyncWriteExt};
 
@@ -590,6 +1188,25 @@ mod tests {
         assert_eq!(&res.stderr, "");
     }
 
+    #[tokio::test]
+    async fn relative_io_files_are_in_cwd() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("in.txt"), "Hello there\n").unwrap();
+
+        Command::new("cat")
+            .cwd(dir.path())
+            .stdin(StdinPipe::Path("in.txt".into()))
+            .stdout("out.txt")
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("out.txt")).unwrap(),
+            "Hello there\n"
+        );
+    }
+
     #[tokio::test]
     async fn captures_stderr() {
         let program = compile_and_get_testing_main().await;
@@ -650,6 +1267,54 @@ mod tests {
         assert!(matches!(res.status, ExitStatus::Timeout(_)));
     }
 
+    #[tokio::test]
+    async fn kills_process_group() {
+        let res = Command::new("sh")
+            .args(["-c", "sleep 30 > /dev/null 2>&1 & echo $!"])
+            .process_group()
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        // the orphaned sleep is killed, though it may stay a zombie until something reaps it
+        let stat = format!("/proc/{}/stat", res.stdout.trim());
+        let mut running = true;
+        for _ in 0..50 {
+            running = match fs::read_to_string(&stat) {
+                Ok(stat) => !stat.rsplit_once(')').unwrap().1.trim().starts_with('Z'),
+                Err(_) => false,
+            };
+            if !running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!running);
+    }
+
+    #[tokio::test]
+    async fn passes_inherited_fds() {
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        let fd = reader.as_raw_fd();
+        std::io::Write::write_all(&mut writer, b"through the fd").unwrap();
+        drop(writer);
+
+        let cmd = Command::new("sh")
+            .args(["-c", &format!("cat <&{}", fd)])
+            .inherit_fd(reader);
+        let res = cmd.run_with(&ShellExecutor).await.unwrap();
+        assert_eq!(res.stdout, "through the fd");
+
+        // any other process doesn't get it, even while the fd is open
+        let res = Command::new("sh")
+            .args(["-c", &format!("cat <&{}", fd)])
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+        assert_ne!(res.status, ExitStatus::Ok);
+        drop(cmd);
+    }
+
     #[tokio::test]
     async fn catches_segfault() {
         let program = compile_and_get_testing_main().await;
@@ -735,4 +1400,268 @@ mod tests {
         assert_eq!(&contents, "ERR: write me\n");
         assert_eq!(&res.stderr, "ERR: write me\n");
     }
+
+    #[tokio::test]
+    async fn runs_interaction() {
+        let program = compile_and_get_testing_main().await;
+        let interaction = Interaction::new()
+            .expect_text("> ")
+            .send("first")
+            .expect_regex("^echo: first$")
+            .send("second")
+            .expect(
+                ExpectPattern::Text("echo: third".to_string()),
+                Some(Duration::from_secs(1)),
+            );
+
+        let res = Command::new(program.path.to_str().unwrap())
+            .args(["repl"])
+            .stdin(StdinPipe::Interaction(interaction))
+            .timeout(Duration::from_secs(10))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        assert!(res.status.is_ok());
+        assert_eq!(&res.stdout, "> echo: first\n> echo: second\n> ");
+
+        let transcript = res.transcript.unwrap();
+        assert_eq!(transcript.divergence().unwrap().step, 4);
+        assert!(transcript.to_string().starts_with("> first\necho: first"));
+    }
+
+    #[tokio::test]
+    async fn runs_in_pty() {
+        let program = compile_and_get_testing_main().await;
+        let res = Command::new(program.path.to_str().unwrap())
+            .args(["isatty"])
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+        assert_eq!(&res.stdout, "\x1b[31mpipe\x1b[0m 0x0\n");
+
+        let res = Command::new(program.path.to_str().unwrap())
+            .args(["isatty"])
+            .pty(PtyConfig::new(30, 100))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+        assert!(res.status.is_ok());
+        assert_eq!(&res.stdout, "\x1b[31mtty\x1b[0m 30x100\r\n");
+
+        let res = Command::new(program.path.to_str().unwrap())
+            .args(["isatty"])
+            .pty(PtyConfig::new(30, 100).strip_ansi(true))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+        assert_eq!(&res.stdout, "tty 30x100\n");
+    }
+
+    #[tokio::test]
+    async fn pty_stdin_is_echoed() {
+        let program = compile_and_get_testing_main().await;
+        let res = Command::new(program.path.to_str().unwrap())
+            .args(["read_line_from_stdin"])
+            .stdin(StdinPipe::String("typed line\n".to_string()))
+            .pty(PtyConfig::default())
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        assert!(res.status.is_ok());
+        assert_eq!(&res.stdout, "typed line\r\ntyped line\r\n");
+    }
+
+    #[tokio::test]
+    async fn pty_kills_background_processes() {
+        let res = Command::new("sh")
+            .args(["-c", "sleep 60 & echo started"])
+            .pty(PtyConfig::default())
+            .timeout(Duration::from_secs(10))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        assert!(res.status.is_ok());
+        assert_eq!(&res.stdout, "started\r\n");
+    }
+
+    #[tokio::test]
+    async fn runs_pipeline() {
+        let res = Command::new("sh")
+            .args(["-c", "echo hello world; echo first >&2"])
+            .pipe(Command::new("tr").args(["a-z", "A-Z"]))
+            .pipe(Command::new("sh").args(["-c", "rev; echo last >&2; exit 3"]))
+            .pipe(Command::new("cat"))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        assert_eq!(&res.stdout, "DLROW OLLEH\n");
+        assert_eq!(&res.stderr, "first\nlast\n");
+        assert_eq!(res.status, ExitStatus::Ok);
+        assert_eq!(
+            res.pipeline_statuses,
+            vec![
+                ExitStatus::Ok,
+                ExitStatus::Ok,
+                ExitStatus::Failure(3),
+                ExitStatus::Ok
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_signal_on_output() {
+        let res = Command::new("sh")
+            .args([
+                "-c",
+                "trap 'echo caught; exit 3' INT; echo ready; while true; do sleep 0.05; done",
+            ])
+            .signal(ScheduledSignal::on_output(SignalName::SIGINT, "^ready$"))
+            .timeout(Duration::from_secs(5))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, ExitStatus::Failure(3));
+        assert_eq!(&res.stdout, "ready\ncaught\n");
+        assert_eq!(res.signals_sent.len(), 1);
+        assert_eq!(res.signals_sent[0].signal, SignalName::SIGINT);
+    }
+
+    #[tokio::test]
+    async fn sends_signal_after_delay() {
+        let res = Command::new("sleep")
+            .arg("5")
+            .signal(ScheduledSignal::after(
+                SignalName::SIGTERM,
+                Duration::from_millis(100),
+            ))
+            // never sent, the process is gone by then
+            .signal(ScheduledSignal::after(
+                SignalName::SIGKILL,
+                Duration::from_millis(100),
+            ))
+            .timeout(Duration::from_secs(5))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, ExitStatus::Signal(SignalType::Other(15)));
+        assert_eq!(SignalType::Other(15).to_string(), "SIGTERM");
+        assert_eq!(res.signals_sent.len(), 1);
+        assert!(res.signals_sent[0].elapsed >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn seccomp_names_forbidden_syscall() {
+        let policy = SyscallPolicy::Deny(vec!["execve".to_string(), "socket".to_string()]);
+
+        let res = Command::new("sh")
+            .args(["-c", "echo before; exec true"])
+            .seccomp(policy.clone())
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        // the exec of sh itself is allowed, only the one it makes isn't
+        assert_eq!(&res.stdout, "before\n");
+        assert_eq!(
+            res.status,
+            ExitStatus::ForbiddenSyscall("execve".to_string())
+        );
+
+        let res = Command::new("sh")
+            .args(["-c", "echo allowed"])
+            .seccomp(policy)
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, ExitStatus::Ok);
+        assert_eq!(&res.stdout, "allowed\n");
+    }
+
+    #[tokio::test]
+    async fn seccomp_handoff_fd_cant_be_opened() {
+        let res = Command::new("sh")
+            .args(["-c", "ulimit -Sn; ulimit -Hn"])
+            .seccomp(SyscallPolicy::Deny(vec!["sendmsg".to_string()]))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        // the sendmsg the filter allows is on the fd at the limit, which can't be opened
+        let mut limit = nix::libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        assert_eq!(
+            unsafe { nix::libc::getrlimit(nix::libc::RLIMIT_NOFILE, &mut limit) },
+            0
+        );
+        let handoff = limit.rlim_cur - 1;
+        assert_eq!(res.status, ExitStatus::Ok);
+        assert_eq!(res.stdout, format!("{}\n{}\n", handoff, handoff));
+    }
+
+    #[tokio::test]
+    async fn seccomp_allow_list() {
+        let program = compile_and_get_testing_main().await;
+
+        let res = Command::new(program.path.to_str().unwrap())
+            .args(["stdouterr", "msg"])
+            .seccomp(SyscallPolicy::Allow(Vec::new()))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, ExitStatus::Ok);
+        assert_eq!(&res.stdout, "OUT: msg\n");
+
+        let res = Command::new("sh")
+            .args(["-c", "true; true"])
+            .seccomp(SyscallPolicy::Allow(Vec::new()))
+            .timeout(Duration::from_secs(5))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+
+        assert!(matches!(res.status, ExitStatus::ForbiddenSyscall(_)));
+    }
+
+    #[tokio::test]
+    async fn pipeline_stdin_and_timeout() {
+        let res = Command::new("cat")
+            .stdin(StdinPipe::String("b\na\n".to_string()))
+            .pipe(Command::new("sort"))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+        assert_eq!(&res.stdout, "a\nb\n");
+
+        let res = Command::new("echo")
+            .pipe(Command::new("sleep").arg("5"))
+            .timeout(Duration::from_millis(100))
+            .run_with(&ShellExecutor)
+            .await
+            .unwrap();
+        assert!(matches!(res.status, ExitStatus::Timeout(_)));
+    }
+
+    #[test]
+    fn display_pipeline() {
+        let cmd = Command::new("./filter")
+            .arg("-n")
+            .stdin(StdinPipe::Path("input.txt".into()))
+            .pipe(Command::new("sort").pipe(Command::new("uniq").arg("-c")))
+            .stdout("out.txt");
+
+        assert_eq!(
+            cmd.to_string(),
+            "./filter -n < input.txt | sort | uniq -c > out.txt"
+        );
+    }
 }
@@ -0,0 +1,568 @@
+use std::{fmt::Display, io::ErrorKind, time::Duration};
+
+use anyhow::{Context, Result};
+use regex::bytes::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::{timeout, timeout_at, Instant},
+};
+use tracing::debug;
+
+// give a default timeout of 5 seconds for each expectation. Prompts should show up right away.
+const DEFAULT_EXPECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// a process which never reads its stdin fills the pipe, so a send gives up after this long
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+// once the interaction stops, how long to keep reading the rest of stdout. A process which keeps
+// writing, or leaves stdout open in a child, can't hold up the test.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// the most output kept from a process, an expectation which hasn't matched by then fails
+const MAX_OUTPUT: u64 = 16 * 1024 * 1024;
+
+// how far back into output which was already searched a match can start, see resume_point
+const MAX_LOOKBACK: usize = 4096;
+
+/// Interaction is a script for a turn by turn conversation with a process through its stdin and
+/// stdout. Steps are run in order, and the interaction stops at the first expectation which isn't
+/// met. Either way stdin is closed once the interaction stops, and the rest of stdout is read until
+/// the process exits, for at most a few seconds.
+/// Ex:
+/// - !Expect { text: "$ " }
+/// - !Send "echo hi"
+/// - !Expect { regex: "^hi$", timeout_sec: 1 }
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Interaction {
+    pub steps: Vec<InteractionStep>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub enum InteractionStep {
+    /// send a line to stdin, the newline is added automatically
+    Send(String),
+    /// wait for stdout to match
+    Expect(Expectation),
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct Expectation {
+    #[serde(flatten)]
+    pub pattern: ExpectPattern,
+    /// can be fractional, since prompts are usually expected well within a second
+    pub timeout_sec: Option<f64>,
+}
+
+impl Expectation {
+    pub fn timeout(&self) -> Duration {
+        self.timeout_sec
+            .map(Duration::from_secs_f64)
+            .unwrap_or(DEFAULT_EXPECT_TIMEOUT)
+    }
+}
+
+/// What to look for in the output which hasn't been matched by a previous expectation. Regex
+/// patterns are multiline, so `^` and `$` match at the start and end of lines. A match can only
+/// span as many lines as the pattern has `\n`s in it.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpectPattern {
+    Text(String),
+    Regex(String),
+}
+
+impl ExpectPattern {
+    fn to_regex(&self) -> Result<Regex> {
+        let pattern = match self {
+            Self::Text(text) => regex::escape(text),
+            Self::Regex(regex) => format!("(?m){}", regex),
+        };
+
+        Regex::new(&pattern).context(format!("Invalid expect pattern {}", self))
+    }
+
+    // the number of line breaks a match can span
+    fn newlines(&self) -> usize {
+        match self {
+            Self::Text(text) => text.matches('\n').count(),
+            Self::Regex(regex) => regex_newlines(regex),
+        }
+    }
+}
+
+// the number of line breaks a match of the regex can span
+pub(super) fn regex_newlines(pattern: &str) -> usize {
+    pattern.matches('\n').count() + pattern.matches("\\n").count()
+}
+
+impl Display for ExpectPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text(text) => write!(f, "{:?}", text),
+            Self::Regex(regex) => write!(f, "/{}/", regex),
+        }
+    }
+}
+
+impl Interaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send<T: Into<String>>(mut self, line: T) -> Self {
+        self.steps.push(InteractionStep::Send(line.into()));
+        self
+    }
+
+    pub fn expect_text<T: Into<String>>(self, text: T) -> Self {
+        self.expect(ExpectPattern::Text(text.into()), None)
+    }
+
+    pub fn expect_regex<T: Into<String>>(self, regex: T) -> Self {
+        self.expect(ExpectPattern::Regex(regex.into()), None)
+    }
+
+    pub fn expect(mut self, pattern: ExpectPattern, timeout: Option<Duration>) -> Self {
+        self.steps.push(InteractionStep::Expect(Expectation {
+            pattern,
+            timeout_sec: timeout.map(|timeout| timeout.as_secs_f64()),
+        }));
+        self
+    }
+
+    /// Runs the interaction against the given stdin and stdout of a process, returning the
+    /// transcript and everything which was read from stdout. An error is only returned when the
+    /// interaction itself is invalid or the pipes fail, a process which doesn't respond as expected
+    /// is recorded in the transcript.
+    pub async fn drive<W, R>(&self, stdin: W, mut stdout: R) -> Result<(Transcript, Vec<u8>)>
+    where
+        W: AsyncWrite + Unpin,
+        R: AsyncRead + Unpin,
+    {
+        let mut transcript = Transcript::default();
+        let mut output = Vec::new();
+        // start of the output which hasn't been matched yet
+        let mut cursor = 0;
+        let mut stdin = Some(stdin);
+
+        for (index, step) in self.steps.iter().enumerate() {
+            let reason = match step {
+                InteractionStep::Send(line) => {
+                    let pipe = stdin.as_mut().unwrap();
+                    match timeout(SEND_TIMEOUT, send_line(pipe, line)).await {
+                        Ok(Ok(())) => {
+                            transcript.entries.push(TranscriptEntry::Sent(line.clone()));
+                            continue;
+                        }
+                        Ok(Err(e)) if e.kind() == ErrorKind::BrokenPipe => DivergenceReason::Exited,
+                        Ok(Err(e)) => return Err(e.into()),
+                        Err(_) => DivergenceReason::Timeout(SEND_TIMEOUT),
+                    }
+                }
+                InteractionStep::Expect(expectation) => {
+                    let regex = expectation.pattern.to_regex()?;
+                    let deadline = Instant::now() + expectation.timeout();
+                    let newlines = expectation.pattern.newlines();
+                    let res = expect(&regex, newlines, &mut stdout, &mut output, cursor, deadline);
+                    match res.await? {
+                        Ok(end) => {
+                            transcript.push_received(&output[cursor..end]);
+                            cursor = end;
+                            continue;
+                        }
+                        Err(reason) => reason.with_timeout(expectation.timeout()),
+                    }
+                }
+            };
+
+            transcript
+                .entries
+                .push(TranscriptEntry::Diverged(Divergence {
+                    step: index,
+                    expected: step.clone(),
+                    reason,
+                }));
+            break;
+        }
+
+        // close stdin so the process knows there is no more input coming. The process may have
+        // already exited, in which case there is nobody left to tell.
+        if let Some(mut stdin) = stdin.take() {
+            let _ = stdin.shutdown().await;
+        }
+        let limit = MAX_OUTPUT.saturating_sub(output.len() as u64);
+        let mut rest = (&mut stdout).take(limit);
+        match timeout(DRAIN_TIMEOUT, rest.read_to_end(&mut output)).await {
+            Ok(read) => {
+                read?;
+            }
+            Err(_) => debug!(
+                "stdout was still open {:?} after the interaction",
+                DRAIN_TIMEOUT
+            ),
+        }
+        transcript.push_received(&output[cursor..]);
+
+        Ok((transcript, output))
+    }
+}
+
+async fn send_line<W: AsyncWrite + Unpin>(pipe: &mut W, line: &str) -> std::io::Result<()> {
+    pipe.write_all(line.as_bytes()).await?;
+    pipe.write_all(b"\n").await?;
+    pipe.flush().await
+}
+
+// reads from stdout until the regex matches the unmatched output, returning the end of the match.
+// Only the part of the output a new match could start in is searched after each read.
+async fn expect<R: AsyncRead + Unpin>(
+    regex: &Regex,
+    newlines: usize,
+    stdout: &mut R,
+    output: &mut Vec<u8>,
+    cursor: usize,
+    deadline: Instant,
+) -> Result<Result<usize, DivergenceReason>> {
+    let mut chunk = [0; 4096];
+    let mut search_from = 0;
+    loop {
+        let unmatched = &output[cursor..];
+        if let Some(found) = regex.find_at(unmatched, search_from) {
+            return Ok(Ok(cursor + found.end()));
+        }
+        if output.len() as u64 >= MAX_OUTPUT {
+            return Ok(Err(DivergenceReason::TooMuchOutput));
+        }
+        search_from = resume_point(unmatched, newlines);
+
+        let limit = (MAX_OUTPUT - output.len() as u64).min(chunk.len() as u64) as usize;
+        match timeout_at(deadline, stdout.read(&mut chunk[..limit])).await {
+            Err(_) => return Ok(Err(DivergenceReason::Timeout(Duration::ZERO))),
+            Ok(Ok(0)) => return Ok(Err(DivergenceReason::Exited)),
+            Ok(Ok(n)) => output.extend_from_slice(&chunk[..n]),
+            Ok(Err(e)) => return Err(e.into()),
+        }
+    }
+}
+
+// where to search for a match once more output is read. A match which wasn't found yet has to end
+// in the new output, so it starts in one of the last lines which were already searched, as many as
+// the pattern can span. Lines longer than MAX_LOOKBACK are only searched from that far back, so
+// every read is searched about once.
+pub(super) fn resume_point(searched: &[u8], newlines: usize) -> usize {
+    let floor = searched.len().saturating_sub(MAX_LOOKBACK);
+    let mut start = searched.len();
+    for _ in 0..=newlines {
+        match searched[floor..start].iter().rposition(|&b| b == b'\n') {
+            Some(i) => start = floor + i,
+            None => return floor,
+        }
+    }
+    start + 1
+}
+
+/// The record of an interaction, in the order things were sent to and received from the process.
+#[derive(Debug, Default, Clone)]
+pub struct Transcript {
+    pub entries: Vec<TranscriptEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub enum TranscriptEntry {
+    Sent(String),
+    Received(String),
+    /// the point where the interaction stopped going as expected
+    Diverged(Divergence),
+}
+
+impl Transcript {
+    fn push_received(&mut self, output: &[u8]) {
+        if !output.is_empty() {
+            let output = String::from_utf8_lossy(output).to_string();
+            self.entries.push(TranscriptEntry::Received(output));
+        }
+    }
+
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.entries.iter().find_map(|entry| match entry {
+            TranscriptEntry::Diverged(divergence) => Some(divergence),
+            _ => None,
+        })
+    }
+}
+
+/// Formats the transcript the way it would look in a terminal, with the input echoed after the
+/// prompt and a marker where the interaction diverged.
+impl Display for Transcript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut at_line_start = true;
+        for entry in &self.entries {
+            match entry {
+                TranscriptEntry::Sent(line) => {
+                    writeln!(f, "{}", line)?;
+                    at_line_start = true;
+                }
+                TranscriptEntry::Received(output) => {
+                    write!(f, "{}", output)?;
+                    at_line_start = output.ends_with('\n');
+                }
+                TranscriptEntry::Diverged(divergence) => {
+                    if !at_line_start {
+                        writeln!(f)?;
+                    }
+                    writeln!(f, ">>> {}", divergence)?;
+                    at_line_start = true;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// index of the step in the interaction
+    pub step: usize,
+    pub expected: InteractionStep,
+    pub reason: DivergenceReason,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DivergenceReason {
+    /// the expected output didn't show up in time
+    Timeout(Duration),
+    /// the process closed stdout or stdin before the step could finish
+    Exited,
+    /// the process wrote more output than is kept before the expected output showed up
+    TooMuchOutput,
+}
+
+impl DivergenceReason {
+    fn with_timeout(self, duration: Duration) -> Self {
+        match self {
+            Self::Timeout(_) => Self::Timeout(duration),
+            reason => reason,
+        }
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.expected, &self.reason) {
+            (InteractionStep::Expect(expectation), DivergenceReason::Timeout(duration)) => write!(
+                f,
+                "expected output matching {}, but it didn't show up after {:?}",
+                expectation.pattern, duration
+            ),
+            (InteractionStep::Expect(expectation), DivergenceReason::Exited) => write!(
+                f,
+                "expected output matching {}, but the program stopped writing output",
+                expectation.pattern
+            ),
+            (InteractionStep::Expect(expectation), DivergenceReason::TooMuchOutput) => write!(
+                f,
+                "expected output matching {}, but the program wrote over {} MiB of output \
+                 without it showing up",
+                expectation.pattern,
+                MAX_OUTPUT / (1024 * 1024)
+            ),
+            (InteractionStep::Send(line), DivergenceReason::Timeout(duration)) => write!(
+                f,
+                "the program didn't read {:?} from its input after {:?}",
+                line, duration
+            ),
+            (InteractionStep::Send(line), _) => write!(
+                f,
+                "the program exited before {:?} could be sent to it",
+                line
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, AsyncBufReadExt, BufReader};
+
+    use super::*;
+
+    // a tiny repl which answers every line with its length until it reads quit
+    fn spawn_repl() -> (tokio::io::DuplexStream, tokio::io::DuplexStream) {
+        let (stdin, program_stdin) = duplex(1024);
+        let (mut program_stdout, stdout) = duplex(1024);
+
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(program_stdin).lines();
+            program_stdout.write_all(b"> ").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == "quit" {
+                    program_stdout.write_all(b"bye\n").await.unwrap();
+                    return;
+                }
+                let reply = format!("{}\n> ", line.len());
+                program_stdout.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        (stdin, stdout)
+    }
+
+    #[tokio::test]
+    async fn interaction_completes() {
+        let (stdin, stdout) = spawn_repl();
+        let interaction = Interaction::new()
+            .expect_text("> ")
+            .send("hello")
+            .expect_regex("^5$")
+            .expect_text("> ")
+            .send("quit");
+
+        let (transcript, output) = interaction.drive(stdin, stdout).await.unwrap();
+        assert!(transcript.divergence().is_none());
+        assert_eq!(&output, b"> 5\n> bye\n");
+        assert_eq!(transcript.to_string(), "> hello\n5\n> quit\nbye\n");
+    }
+
+    #[tokio::test]
+    async fn interaction_diverges_on_wrong_output() {
+        let (stdin, stdout) = spawn_repl();
+        let interaction = Interaction::new()
+            .expect_text("> ")
+            .send("hello")
+            .expect(
+                ExpectPattern::Regex("^6$".to_string()),
+                Some(Duration::from_secs(1)),
+            )
+            .send("quit");
+
+        let (transcript, _) = interaction.drive(stdin, stdout).await.unwrap();
+        let divergence = transcript.divergence().unwrap();
+        assert_eq!(divergence.step, 2);
+        assert_eq!(
+            divergence.reason,
+            DivergenceReason::Timeout(Duration::from_secs(1))
+        );
+
+        // closing stdin ends the repl, and the rest of its output is still in the transcript
+        assert_eq!(
+            transcript.to_string(),
+            "> hello\n>>> expected output matching /^6$/, but it didn't show up after 1s\n5\n> "
+        );
+    }
+
+    #[tokio::test]
+    async fn interaction_diverges_when_program_exits() {
+        let (stdin, stdout) = spawn_repl();
+        let interaction = Interaction::new()
+            .expect_text("> ")
+            .send("quit")
+            .expect_text("> ");
+
+        let (transcript, _) = interaction.drive(stdin, stdout).await.unwrap();
+        let divergence = transcript.divergence().unwrap();
+        assert_eq!(divergence.step, 2);
+        assert_eq!(divergence.reason, DivergenceReason::Exited);
+        assert!(transcript
+            .to_string()
+            .contains("but the program stopped writing output"));
+    }
+
+    #[tokio::test]
+    async fn expectation_timeouts_can_be_fractional() {
+        let (stdin, stdout) = spawn_repl();
+        let interaction = Interaction::new().expect(
+            ExpectPattern::Text("never".to_string()),
+            Some(Duration::from_millis(200)),
+        );
+
+        let (transcript, _) = interaction.drive(stdin, stdout).await.unwrap();
+        assert_eq!(
+            transcript.divergence().unwrap().reason,
+            DivergenceReason::Timeout(Duration::from_millis(200))
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_reading_stdout_held_open() {
+        let (stdin, _program_stdin) = duplex(1024);
+        let (mut program_stdout, stdout) = duplex(1024);
+        program_stdout.write_all(b"> ").await.unwrap();
+
+        // program_stdout is never closed, so only the drain timeout ends the interaction
+        let interaction = Interaction::new().expect_text("> ");
+        let (transcript, output) = interaction.drive(stdin, stdout).await.unwrap();
+        assert!(transcript.divergence().is_none());
+        assert_eq!(&output, b"> ");
+    }
+
+    #[tokio::test]
+    async fn matches_across_reads() {
+        let (stdin, _program_stdin) = duplex(1024);
+        let (mut program_stdout, stdout) = duplex(1024);
+        tokio::spawn(async move {
+            for part in [
+                "first li",
+                "ne\nsec",
+                "ond line\n",
+                "x".repeat(5000).as_str(),
+                "end",
+            ] {
+                program_stdout.write_all(part.as_bytes()).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+
+        let interaction = Interaction::new()
+            .expect_regex("^first line$")
+            .expect_text("second line\nxxx")
+            .expect_regex("x+end");
+        let (transcript, _) = interaction.drive(stdin, stdout).await.unwrap();
+        assert!(transcript.divergence().is_none(), "{}", transcript);
+    }
+
+    #[tokio::test]
+    async fn stops_expecting_after_too_much_output() {
+        let (stdin, _program_stdin) = duplex(1024);
+        let (mut program_stdout, stdout) = duplex(64 * 1024);
+        tokio::spawn(async move {
+            let flood = [b'x'; 64 * 1024];
+            while program_stdout.write_all(&flood).await.is_ok() {}
+        });
+
+        let interaction = Interaction::new().expect(
+            ExpectPattern::Text("never".to_string()),
+            Some(Duration::from_secs(60)),
+        );
+        let (transcript, output) = interaction.drive(stdin, stdout).await.unwrap();
+        assert_eq!(
+            transcript.divergence().unwrap().reason,
+            DivergenceReason::TooMuchOutput
+        );
+        assert_eq!(output.len() as u64, MAX_OUTPUT);
+    }
+
+    #[tokio::test]
+    async fn send_times_out_when_input_isnt_read() {
+        let (stdin, _program_stdin) = duplex(16);
+        let (_, stdout) = duplex(1024);
+
+        let interaction = Interaction::new().send("more than the pipe holds");
+        let (transcript, _) = interaction.drive(stdin, stdout).await.unwrap();
+        assert_eq!(
+            transcript.divergence().unwrap().reason,
+            DivergenceReason::Timeout(SEND_TIMEOUT)
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_regex_is_error() {
+        let (stdin, stdout) = spawn_repl();
+        let interaction = Interaction::new().expect_regex("(");
+
+        interaction.drive(stdin, stdout).await.unwrap_err();
+    }
+}
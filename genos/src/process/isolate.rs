@@ -0,0 +1,280 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use nix::sys::signal::Signal;
+
+use super::{Command, ExitStatus, Output, ProcessExecutor};
+
+const UNSHARE: &str = "unshare";
+// exit code and message of the wrapper when the namespace couldn't be set up
+const SETUP_FAILED_CODE: i32 = 125;
+const SETUP_FAILED_MESSAGE: &str = "genos: unable to set up isolation";
+
+// Runs as pid 1 of the new namespaces. Every mount is made read only, then the workspace (the
+// first arg) is mounted back as writable. The kernel escapes spaces and other special characters
+// in mount points as octal, which printf turns back. The program is run as a child instead of
+// exec'd since pid 1 ignores any signal it doesn't handle, which would change how the program
+// behaves. It's run without any capabilities, so it can't undo the mounts.
+const WRAPPER: &str = r#"
+setup_failed() {
+    echo "$SETUP_FAILED_MESSAGE" >&2
+    exit $SETUP_FAILED_CODE
+}
+ws="$1"
+shift
+command -v setpriv >/dev/null || setup_failed
+while read -r _ target _; do
+    target=$(printf '%b' "$(printf '%s' "$target" | sed 's/\\\([0-7]\)/\\0\1/g')")
+    mount -o remount,bind,ro "$target" || setup_failed
+done < /proc/self/mounts
+if [ -n "$ws" ]; then
+    if ! mount --bind "$ws" "$ws" || ! mount -o remount,bind,rw "$ws"; then
+        setup_failed
+    fi
+    # the cwd is still on the read only mount underneath the new one
+    cd "$ws" || setup_failed
+fi
+setpriv --inh-caps=-all --bounding-set=-all --no-new-privs -- "$@"
+status=$?
+exit $status
+"#;
+
+/// IsolatedExecutor runs commands on another executor inside of new user, mount, network and pid
+/// namespaces, using `unshare` from util-linux. The process has no network access, and everything
+/// but its cwd is read only. It runs under its own pid 1, so anything it leaves running is killed
+/// along with it. The program has no capabilities in the namespaces, so it can't change the mounts
+/// back, and `setpriv` from util-linux needs to be installed as well.
+///
+/// The program isn't pid 1, the shell which reaps it is. Since the shell reports a program killed
+/// by a signal as exit code 128 + the signal, those exit codes are reported as the signal. The
+/// grader can't send signals through the shell, so commands with scheduled signals are an error,
+/// and so are seccomp policies since they would apply to the shell.
+#[derive(Clone)]
+pub struct IsolatedExecutor<E> {
+    inner: E,
+}
+
+impl<E: ProcessExecutor> IsolatedExecutor<E> {
+    pub fn new(inner: E) -> Self {
+        Self { inner }
+    }
+
+    fn isolate(cmd: &Command) -> Command {
+        let ws = cmd
+            .cwd
+            .as_ref()
+            .map(|cwd| cwd.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut isolated = cmd.clone();
+        isolated.program = UNSHARE.to_string();
+        isolated.args = vec![
+            "--user",
+            "--map-root-user",
+            "--mount",
+            "--net",
+            "--pid",
+            "--fork",
+            "--kill-child",
+            "--mount-proc",
+            "--",
+            "sh",
+            "-c",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        let wrapper = WRAPPER
+            .replace("$SETUP_FAILED_MESSAGE", SETUP_FAILED_MESSAGE)
+            .replace("$SETUP_FAILED_CODE", &SETUP_FAILED_CODE.to_string());
+        isolated
+            .args
+            .extend([wrapper, "sh".to_string(), ws, cmd.program.clone()]);
+        isolated.args.extend(cmd.args.iter().cloned());
+        isolated.pipeline = cmd.pipeline.iter().map(Self::isolate).collect();
+
+        isolated
+    }
+}
+
+// the shell gives 128 + the signal for a program killed by a signal
+fn unwrap_status(status: ExitStatus) -> ExitStatus {
+    match status {
+        ExitStatus::Failure(rc) if rc > 128 && Signal::try_from(rc - 128).is_ok() => {
+            ExitStatus::Signal((rc - 128).into())
+        }
+        status => status,
+    }
+}
+
+#[async_trait]
+impl<E: ProcessExecutor> ProcessExecutor for IsolatedExecutor<E> {
+    async fn run(&self, cmd: &Command) -> Result<Output> {
+        // both would apply to the wrapper instead of the program
+        if !cmd.signals.is_empty() || cmd.seccomp.is_some() {
+            return Err(anyhow!(
+                "signals and seccomp policies can't be used with an isolated process: {}",
+                cmd
+            ));
+        }
+
+        let mut output = self.inner.run(&Self::isolate(cmd)).await?;
+
+        if output.status == ExitStatus::Failure(SETUP_FAILED_CODE)
+            && output.stderr.contains(SETUP_FAILED_MESSAGE)
+        {
+            return Err(anyhow!("{}: {}", SETUP_FAILED_MESSAGE, output.stderr));
+        }
+
+        output.status = unwrap_status(output.status);
+        output.pipeline_statuses = output
+            .pipeline_statuses
+            .into_iter()
+            .map(unwrap_status)
+            .collect();
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::process::{is_program_in_path, ShellExecutor, SignalType};
+
+    fn executor() -> Option<IsolatedExecutor<ShellExecutor>> {
+        // unprivileged namespaces aren't available everywhere
+        let supported = is_program_in_path(UNSHARE)
+            && std::process::Command::new(UNSHARE)
+                .args(["--user", "--map-root-user", "true"])
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false);
+
+        match supported {
+            true => Some(IsolatedExecutor::new(ShellExecutor)),
+            false => None,
+        }
+    }
+
+    #[test]
+    fn isolate_wraps_pipeline() {
+        let cmd = Command::new("./prog")
+            .arg("arg")
+            .cwd("/ws")
+            .pipe(Command::new("sort"));
+
+        let isolated = IsolatedExecutor::<ShellExecutor>::isolate(&cmd);
+
+        assert_eq!(isolated.program, UNSHARE);
+        assert_eq!(
+            isolated.args[isolated.args.len() - 4..],
+            ["sh", "/ws", "./prog", "arg"]
+        );
+        assert_eq!(isolated.pipeline[0].program, UNSHARE);
+        assert_eq!(isolated.pipeline[0].args.last().unwrap(), "sort");
+    }
+
+    #[tokio::test]
+    async fn only_cwd_is_writable() {
+        let Some(executor) = executor() else {
+            return;
+        };
+        let ws = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+
+        let res = Command::new("sh")
+            .args([
+                "-c",
+                &format!(
+                    "echo ws > ws.txt && echo ok; echo outside > {}/out.txt || echo read only",
+                    outside.path().display()
+                ),
+            ])
+            .cwd(ws.path())
+            .run_with(&executor)
+            .await
+            .unwrap();
+
+        assert!(res.status.is_ok());
+        assert_eq!(&res.stdout, "ok\nread only\n");
+        assert!(ws.path().join("ws.txt").exists());
+        assert!(!outside.path().join("out.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn mounts_cant_be_made_writable() {
+        let Some(executor) = executor() else {
+            return;
+        };
+        let ws = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+
+        let res = Command::new("sh")
+            .args([
+                "-c",
+                &format!(
+                    "mount -o remount,bind,rw / && echo remounted; \
+                     mount -o remount,bind,rw {0} && echo remounted; \
+                     echo outside > {0}/out.txt; grep CapEff /proc/self/status",
+                    outside.path().display()
+                ),
+            ])
+            .cwd(ws.path())
+            .run_with(&executor)
+            .await
+            .unwrap();
+
+        assert!(!res.stdout.contains("remounted"), "{}", res.stdout);
+        assert!(
+            res.stdout.contains("CapEff:\t0000000000000000"),
+            "{}",
+            res.stdout
+        );
+        assert!(!outside.path().join("out.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn has_own_pid_and_no_network() {
+        let Some(executor) = executor() else {
+            return;
+        };
+
+        let res = Command::new("sh")
+            .args(["-c", "echo $PPID; cat /proc/net/dev | tail -n +3 | wc -l"])
+            .run_with(&executor)
+            .await
+            .unwrap();
+
+        // the parent is the wrapper shell, and the only interface is loopback
+        assert_eq!(&res.stdout, "1\n1\n");
+    }
+
+    #[tokio::test]
+    async fn reports_signals_and_kills_stragglers() {
+        let Some(executor) = executor() else {
+            return;
+        };
+
+        let res = Command::new("sh")
+            .args(["-c", "sleep 30 & kill -SEGV $$"])
+            .timeout(Duration::from_secs(5))
+            .run_with(&executor)
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, ExitStatus::Signal(SignalType::SegFault));
+
+        let res = Command::new("sh")
+            .args(["-c", "exit 3"])
+            .run_with(&executor)
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, ExitStatus::Failure(3));
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    fs, io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    pin::Pin,
+    process::Stdio,
+    task::{ready, Context as TaskContext, Poll},
+};
+
+use anyhow::{Context, Result};
+use nix::{
+    fcntl::{fcntl, FcntlArg, OFlag},
+    libc,
+    pty::{openpty, Winsize},
+    sys::signal::{kill, Signal},
+    unistd::{self, setsid, Pid},
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tokio::{
+    io::{unix::AsyncFd, AsyncRead, AsyncWrite, ReadBuf},
+    process::Command as TokioCommand,
+};
+use tracing::debug;
+
+// the character the terminal treats as end of input, aka ctrl-d
+const EOF_CHAR: u8 = 0x04;
+
+/// Runs the process attached to a pseudo terminal instead of pipes, so the process sees a tty on
+/// stdin, stdout and stderr. The process is made the leader of a new session with the terminal as
+/// its controlling terminal, which is what a shell needs for job control.
+///
+/// Everything the process writes to stdout and stderr ends up in stdout of the Output, exactly the
+/// way a terminal would show it. That means lines end with `\r\n` and any input sent to the
+/// process is echoed back.
+/// Ex:
+/// pty:
+///     rows: 24
+///     cols: 80
+///     strip_ansi: true
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, JsonSchema)]
+pub struct PtyConfig {
+    #[serde(default = "default_rows")]
+    pub rows: u16,
+    #[serde(default = "default_cols")]
+    pub cols: u16,
+    /// remove colors, cursor movement and other escape sequences from the captured output. This
+    /// also removes the carriage returns, so lines end with `\n`.
+    #[serde(default)]
+    pub strip_ansi: bool,
+}
+
+fn default_rows() -> u16 {
+    24
+}
+
+fn default_cols() -> u16 {
+    80
+}
+
+impl Default for PtyConfig {
+    fn default() -> Self {
+        Self {
+            rows: default_rows(),
+            cols: default_cols(),
+            strip_ansi: false,
+        }
+    }
+}
+
+impl PtyConfig {
+    pub fn new(rows: u16, cols: u16) -> Self {
+        Self {
+            rows,
+            cols,
+            strip_ansi: false,
+        }
+    }
+
+    pub fn strip_ansi(mut self, strip_ansi: bool) -> Self {
+        self.strip_ansi = strip_ansi;
+        self
+    }
+}
+
+pub(crate) struct Pty {
+    pub master: PtyMaster,
+    pub slave: OwnedFd,
+}
+
+impl Pty {
+    pub fn open(config: &PtyConfig) -> Result<Self> {
+        let size = Winsize {
+            ws_row: config.rows,
+            ws_col: config.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let pty = openpty(&size, None).context("Error opening pty")?;
+        // openpty hands back ownership of both fds
+        let (master, slave) = unsafe {
+            (
+                OwnedFd::from_raw_fd(pty.master),
+                OwnedFd::from_raw_fd(pty.slave),
+            )
+        };
+
+        Ok(Self {
+            master: PtyMaster::new(master)?,
+            slave,
+        })
+    }
+
+    /// Attach the slave side of the terminal as the stdin, stdout and stderr of the command, and
+    /// make it the controlling terminal of the process.
+    pub fn attach(&self, process: &mut TokioCommand) -> Result<()> {
+        process
+            .stdin(Stdio::from(self.slave.try_clone()?))
+            .stdout(Stdio::from(self.slave.try_clone()?))
+            .stderr(Stdio::from(self.slave.try_clone()?));
+
+        // only async signal safe calls are allowed between fork and exec
+        unsafe {
+            process.pre_exec(|| {
+                setsid()?;
+                if libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The side of the terminal the grader reads and writes. Reads end once the process and everything
+/// else holding the other side of the terminal has exited. Shutting down sends end of input to
+/// the process.
+pub struct PtyMaster {
+    fd: AsyncFd<OwnedFd>,
+    // end of input only works at the start of a line, otherwise it just sends the partial line
+    at_line_start: bool,
+    // set once shutdown starts, so a shutdown which has to wait doesn't send extra eofs
+    eofs_remaining: Option<u8>,
+}
+
+impl PtyMaster {
+    fn new(fd: OwnedFd) -> Result<Self> {
+        fcntl(fd.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+            at_line_start: true,
+            eofs_remaining: None,
+        })
+    }
+}
+
+impl AsyncRead for PtyMaster {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|fd| Ok(unistd::read(fd.as_raw_fd(), unfilled)?)) {
+                Ok(Ok(n)) => {
+                    buf.advance(n);
+                    return Poll::Ready(Ok(()));
+                }
+                // linux gives EIO once every copy of the slave is closed, which is the pty version
+                // of eof
+                Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => return Poll::Ready(Ok(())),
+                Ok(Err(e)) => return Poll::Ready(Err(e)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for PtyMaster {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            let mut guard = ready!(this.fd.poll_write_ready(cx))?;
+            match guard.try_io(|fd| Ok(unistd::write(fd.as_raw_fd(), buf)?)) {
+                Ok(Ok(n)) => {
+                    if n > 0 {
+                        this.at_line_start = buf[n - 1] == b'\n';
+                    }
+                    return Poll::Ready(Ok(n));
+                }
+                Ok(Err(e)) => return Poll::Ready(Err(e)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        // the first eof flushes the partial line, the second one ends the input
+        let eofs = if self.at_line_start { 1 } else { 2 };
+        let mut remaining = *self.eofs_remaining.get_or_insert(eofs);
+
+        while remaining > 0 {
+            ready!(self.as_mut().poll_write(cx, &[EOF_CHAR]))?;
+            remaining -= 1;
+            self.eofs_remaining = Some(remaining);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Kill every process left in the session. Background jobs and anything else started by the
+/// process keep the terminal open after the process itself exits.
+pub(crate) fn kill_session(session: u32) {
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("unable to read /proc to kill session {}: {}", session, e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let pid = match entry.file_name().to_str().map(str::parse::<i32>) {
+            Some(Ok(pid)) => pid,
+            _ => continue,
+        };
+
+        // the command in stat can contain spaces, so the fields are counted from the end of it.
+        // The fields after it are state, ppid, pgrp and session.
+        let stat = match fs::read_to_string(entry.path().join("stat")) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+        let sid = stat
+            .rsplit_once(')')
+            .and_then(|(_, fields)| fields.split_whitespace().nth(3))
+            .and_then(|sid| sid.parse::<u32>().ok());
+
+        if sid == Some(session) {
+            debug!(pid, session, "killing process left in session");
+            let _ = kill(Pid::from_raw(pid), Signal::SIGKILL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn shutdown_sends_eof() {
+        let config = PtyConfig::default();
+        let pty = Pty::open(&config).unwrap();
+
+        let mut process = TokioCommand::new("cat");
+        pty.attach(&mut process).unwrap();
+        let mut child = process.spawn().unwrap();
+        drop(process);
+
+        let Pty { mut master, slave } = pty;
+        drop(slave);
+
+        // no newline at the end, so two eofs are needed for cat to see the end of input
+        master.write_all(b"partial").await.unwrap();
+        master.shutdown().await.unwrap();
+
+        let mut output = Vec::new();
+        master.read_to_end(&mut output).await.unwrap();
+        assert!(child.wait().await.unwrap().success());
+        assert!(String::from_utf8_lossy(&output).contains("partialpartial"));
+    }
+}
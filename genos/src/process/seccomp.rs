@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[cfg(target_arch = "x86_64")]
+mod filter;
+#[cfg(target_arch = "x86_64")]
+mod syscalls;
+
+#[cfg(target_arch = "x86_64")]
+use filter::syscall_nr;
+#[cfg(target_arch = "x86_64")]
+pub(crate) use filter::SyscallFilter;
+#[cfg(not(target_arch = "x86_64"))]
+use unsupported::syscall_nr;
+#[cfg(not(target_arch = "x86_64"))]
+pub(crate) use unsupported::SyscallFilter;
+
+/// The syscalls a program is allowed to make. A program which makes a syscall the policy doesn't
+/// allow is killed, and the run ends with ExitStatus::ForbiddenSyscall naming the call. The
+/// policy is inherited by every process the program starts. Only supported on x86_64, any policy
+/// is invalid on other architectures.
+/// Ex:
+/// seccomp: !Deny [fork, vfork, clone, socket]
+/// seccomp: !Allow [clock_gettime]
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, JsonSchema)]
+pub enum SyscallPolicy {
+    /// every syscall other than these is allowed
+    Deny(Vec<String>),
+    /// only these syscalls are allowed, along with the ones the C runtime needs to start and exit
+    Allow(Vec<String>),
+}
+
+impl SyscallPolicy {
+    /// Check that every syscall in the policy exists.
+    pub fn validate(&self) -> Result<()> {
+        let (SyscallPolicy::Deny(names) | SyscallPolicy::Allow(names)) = self;
+        for name in names {
+            syscall_nr(name).context("Invalid seccomp policy")?;
+        }
+        Ok(())
+    }
+}
+
+// Other architectures can't have a filter, so every policy is invalid and can't be attached.
+#[cfg(not(target_arch = "x86_64"))]
+mod unsupported {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::{anyhow, Result};
+    use tokio::{process::Command as TokioCommand, task::JoinHandle};
+
+    use super::SyscallPolicy;
+    use crate::process::Command;
+
+    const UNSUPPORTED: &str = "seccomp policies are only supported on x86_64";
+
+    pub(super) fn syscall_nr(_name: &str) -> Result<i64> {
+        Err(anyhow!(UNSUPPORTED))
+    }
+
+    pub(crate) enum SyscallFilter {}
+
+    impl SyscallFilter {
+        pub fn attach(
+            _policy: &SyscallPolicy,
+            _cmd: &Command,
+            _process: &mut TokioCommand,
+        ) -> Result<Self> {
+            Err(anyhow!(UNSUPPORTED))
+        }
+
+        pub fn watch(self, _pid: u32) -> (Arc<Mutex<Option<String>>>, JoinHandle<Result<()>>) {
+            match self {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Config {
+        seccomp: SyscallPolicy,
+    }
+
+    #[test]
+    fn deserialize_policy() {
+        let config: Config = toml::from_str(r#"seccomp = { Deny = ["fork", "socket"] }"#).unwrap();
+        assert_eq!(
+            config.seccomp,
+            SyscallPolicy::Deny(vec!["fork".to_string(), "socket".to_string()])
+        );
+    }
+}
@@ -0,0 +1,466 @@
+// The seccomp filter for x86_64, the only architecture the syscall table and the architecture
+// check in the filter are written for.
+use std::{
+    env,
+    ffi::CString,
+    fs, io,
+    mem::{self, MaybeUninit},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::{fs::PermissionsExt, net::UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use nix::{
+    libc::{self, c_char, sock_filter},
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use tokio::{io::unix::AsyncFd, process::Command as TokioCommand, task::JoinHandle};
+use tracing::debug;
+
+use super::{syscalls::SYSCALLS, SyscallPolicy};
+use crate::process::Command;
+
+const AUDIT_ARCH_X86_64: u32 = 62 | 0x8000_0000 | 0x4000_0000;
+
+// offsets into struct seccomp_data
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+const ARGS_OFFSET: u32 = 16;
+
+/// The syscalls the C runtime needs to start up and exit, which are always allowed by an allow
+/// list.
+const RUNTIME_SYSCALLS: &[&str] = &[
+    "read",
+    "write",
+    "close",
+    "fstat",
+    "newfstatat",
+    "lseek",
+    "ioctl",
+    "mmap",
+    "mprotect",
+    "munmap",
+    "brk",
+    "pread64",
+    "access",
+    "openat",
+    "arch_prctl",
+    "set_tid_address",
+    "set_robust_list",
+    "rseq",
+    "prlimit64",
+    "getrandom",
+    "futex",
+    "rt_sigaction",
+    "rt_sigprocmask",
+    "rt_sigreturn",
+    "exit",
+    "exit_group",
+];
+
+pub(super) fn syscall_nr(name: &str) -> Result<i64> {
+    SYSCALLS
+        .iter()
+        .find(|(syscall, _)| syscall.trim_start_matches("SYS_") == name)
+        .map(|(_, nr)| *nr)
+        .ok_or(anyhow!("Unknown syscall {}", name))
+}
+
+fn syscall_name(nr: i64) -> String {
+    SYSCALLS
+        .iter()
+        .find(|(_, syscall)| *syscall == nr)
+        .map(|(name, _)| name.trim_start_matches("SYS_").to_string())
+        .unwrap_or(format!("syscall {}", nr))
+}
+
+fn stmt(code: u32, k: u32) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+fn load(offset: u32) -> sock_filter {
+    stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset)
+}
+
+fn ret(action: u32) -> sock_filter {
+    stmt(libc::BPF_RET | libc::BPF_K, action)
+}
+
+// `if nr == syscall { return action }`, with the nr loaded
+fn match_syscall(nr: i64, action: u32) -> [sock_filter; 2] {
+    [
+        jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, nr as u32, 0, 1),
+        ret(action),
+    ]
+}
+
+// `if nr == syscall && arg0 == value { allow }`, with the nr loaded before and after
+fn allow_with_first_arg(nr: i64, value: u64) -> [sock_filter; 7] {
+    let jeq = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    [
+        jump(jeq, nr as u32, 0, 5),
+        load(ARGS_OFFSET),
+        jump(jeq, value as u32, 0, 3),
+        load(ARGS_OFFSET + 4),
+        jump(jeq, (value >> 32) as u32, 0, 1),
+        ret(libc::SECCOMP_RET_ALLOW),
+        load(NR_OFFSET),
+    ]
+}
+
+/// Build the filter for the policy. `exec` and `socket` are the only execve and sendmsg the
+/// filter lets through no matter the policy, which are the calls made to hand the listener to
+/// the grader and start the program after the filter is installed. The socket must be an fd the
+/// program can't open, see handoff_fd.
+fn build_filter(policy: &SyscallPolicy, exec: u64, socket: RawFd) -> Result<Vec<sock_filter>> {
+    let notify = libc::SECCOMP_RET_USER_NOTIF;
+    let (names, matched, default) = match policy {
+        SyscallPolicy::Deny(names) => (names.clone(), notify, libc::SECCOMP_RET_ALLOW),
+        SyscallPolicy::Allow(names) => {
+            let mut names = names.clone();
+            names.extend(RUNTIME_SYSCALLS.iter().map(|name| name.to_string()));
+            (names, libc::SECCOMP_RET_ALLOW, notify)
+        }
+    };
+
+    let mut filter = vec![
+        load(ARCH_OFFSET),
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            AUDIT_ARCH_X86_64,
+            1,
+            0,
+        ),
+        ret(libc::SECCOMP_RET_KILL_PROCESS),
+        load(NR_OFFSET),
+    ];
+    filter.extend(allow_with_first_arg(libc::SYS_execve, exec));
+    filter.extend(allow_with_first_arg(libc::SYS_sendmsg, socket as u64));
+    for name in &names {
+        filter.extend(match_syscall(syscall_nr(name)?, matched));
+    }
+    filter.push(ret(default));
+
+    Ok(filter)
+}
+
+// The execve args, built before the fork since allocating in the child isn't safe
+struct ExecArgs {
+    program: CString,
+    _strings: Vec<CString>,
+    argv: Vec<*const c_char>,
+    envp: Vec<*const c_char>,
+}
+
+// the pointers are into the strings owned by the struct
+unsafe impl Send for ExecArgs {}
+unsafe impl Sync for ExecArgs {}
+
+impl ExecArgs {
+    fn new(cmd: &Command) -> Result<Self> {
+        let program = CString::new(resolve_program(cmd)?.into_os_string().into_encoded_bytes())?;
+        let argv = std::iter::once(cmd.program.clone())
+            .chain(cmd.args.iter().cloned())
+            .map(CString::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        let envp = cmd
+            .envs
+            .iter()
+            .map(|(key, val)| CString::new(format!("{}={}", key, val)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let pointers = |strings: &[CString]| {
+            strings
+                .iter()
+                .map(|s| s.as_ptr())
+                .chain(std::iter::once(std::ptr::null()))
+                .collect::<Vec<_>>()
+        };
+
+        Ok(Self {
+            argv: pointers(&argv),
+            envp: pointers(&envp),
+            program,
+            _strings: argv.into_iter().chain(envp).collect(),
+        })
+    }
+}
+
+// find the program the same way the shell would, paths are used as is and relative to the cwd
+fn resolve_program(cmd: &Command) -> Result<PathBuf> {
+    if cmd.program.contains('/') {
+        return Ok(cmd.program.clone().into());
+    }
+
+    let path = cmd
+        .envs
+        .get("PATH")
+        .cloned()
+        .or(env::var("PATH").ok())
+        .unwrap_or_default();
+
+    path.split(':')
+        .map(|dir| Path::new(dir).join(&cmd.program))
+        .find(|candidate| {
+            fs::metadata(candidate)
+                .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false)
+        })
+        .ok_or(anyhow!("Could not find {} in PATH", cmd.program))
+}
+
+// The fd the listener is handed to the grader over. It's the highest fd the process can open, and
+// the child lowers its fd limit to it before installing the filter. Since the limit can't be
+// raised back, the program can never open an fd with this number to get around the filter with
+// the sendmsg it allows.
+fn handoff_fd() -> io::Result<RawFd> {
+    let limit = nofile_limit()?;
+    match RawFd::try_from(limit.rlim_cur) {
+        Ok(fd) if fd > 3 => Ok(fd - 1),
+        _ => Err(io::Error::other(format!(
+            "unusable fd limit {}",
+            limit.rlim_cur
+        ))),
+    }
+}
+
+fn nofile_limit() -> io::Result<libc::rlimit> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(limit)
+}
+
+// move the socket to the handoff fd, which is then past the fd limit of the process. Only
+// async signal safe calls are made, since it runs between fork and exec.
+unsafe fn move_to_handoff_fd(socket: RawFd, handoff: RawFd) -> io::Result<()> {
+    if libc::dup3(socket, handoff, libc::O_CLOEXEC) < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let limit = libc::rlimit {
+        rlim_cur: handoff as libc::rlim_t,
+        rlim_max: handoff as libc::rlim_t,
+    };
+    if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// send the fd over the socket, without allocating
+unsafe fn send_fd(socket: RawFd, fd: RawFd) -> io::Result<()> {
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr() as *mut _,
+        iov_len: byte.len(),
+    };
+    let mut control = [0u64; 4];
+
+    let mut msg: libc::msghdr = mem::zeroed();
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut _;
+    msg.msg_controllen = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as _;
+
+    let cmsg = libc::CMSG_FIRSTHDR(&msg);
+    (*cmsg).cmsg_level = libc::SOL_SOCKET;
+    (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+    (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+    std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+
+    if libc::sendmsg(socket, &msg, 0) < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn recv_fd(socket: &UnixStream) -> io::Result<Option<OwnedFd>> {
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr() as *mut _,
+        iov_len: byte.len(),
+    };
+    let mut control = [0u64; 4];
+
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut _;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        let n = libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC);
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if n == 0 || cmsg.is_null() || (*cmsg).cmsg_type != libc::SCM_RIGHTS {
+            return Ok(None);
+        }
+        let fd = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd);
+        Ok(Some(OwnedFd::from_raw_fd(fd)))
+    }
+}
+
+/// The filter for one run of a command. Attaching it to the process makes the child install the
+/// filter and then exec the program itself, so the filter applies to the exec as well. The child
+/// hands the grader a listener which is notified whenever the program makes a forbidden syscall.
+pub(crate) struct SyscallFilter {
+    socket: UnixStream,
+}
+
+impl SyscallFilter {
+    pub fn attach(
+        policy: &SyscallPolicy,
+        cmd: &Command,
+        process: &mut TokioCommand,
+    ) -> Result<Self> {
+        let (socket, child_socket) = UnixStream::pair()?;
+        let exec = ExecArgs::new(cmd)?;
+        // the child inherits the fd limit, so it finds the same handoff fd
+        let handoff = handoff_fd().context("Unable to find an fd to hand off the listener")?;
+        let filter = build_filter(policy, exec.program.as_ptr() as u64, handoff)?;
+        let child_socket = OwnedFd::from(child_socket);
+
+        // only async signal safe calls are allowed between fork and exec
+        unsafe {
+            process.pre_exec(move || {
+                // use the whole struct so the closure doesn't capture the pointers on their own
+                let exec = &exec;
+                move_to_handoff_fd(child_socket.as_raw_fd(), handoff)?;
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let prog = libc::sock_fprog {
+                    len: filter.len() as u16,
+                    filter: filter.as_ptr() as *mut _,
+                };
+                let listener = libc::syscall(
+                    libc::SYS_seccomp,
+                    libc::SECCOMP_SET_MODE_FILTER,
+                    libc::SECCOMP_FILTER_FLAG_NEW_LISTENER,
+                    &prog as *const libc::sock_fprog,
+                );
+                if listener < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                send_fd(handoff, listener as RawFd)?;
+                libc::execve(
+                    exec.program.as_ptr(),
+                    exec.argv.as_ptr(),
+                    exec.envp.as_ptr(),
+                );
+                Err(io::Error::last_os_error())
+            });
+        }
+
+        Ok(Self { socket })
+    }
+
+    /// Watch for a forbidden syscall from the process with the given pid, or any of its children.
+    /// The process is killed when it makes one, and the syscall is put in the returned slot before
+    /// the kill.
+    pub fn watch(self, pid: u32) -> (Arc<Mutex<Option<String>>>, JoinHandle<Result<()>>) {
+        let violation = Arc::new(Mutex::new(None));
+        let slot = violation.clone();
+
+        let handle = tokio::spawn(async move {
+            // the child sends the listener before it execs, or closes the socket if it fails
+            let socket = self.socket;
+            let listener = tokio::task::spawn_blocking(move || recv_fd(&socket)).await??;
+            let Some(listener) = listener else {
+                return Ok(());
+            };
+
+            let listener = AsyncFd::new(listener)?;
+            loop {
+                let mut guard = listener.readable().await?;
+                let mut pollfd = libc::pollfd {
+                    fd: listener.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                unsafe { libc::poll(&mut pollfd, 1, 0) };
+                if pollfd.revents & libc::POLLIN == 0 {
+                    // every process using the filter has exited
+                    if pollfd.revents & libc::POLLHUP != 0 {
+                        return Ok(());
+                    }
+                    guard.clear_ready();
+                    continue;
+                }
+
+                let mut notif = MaybeUninit::<libc::seccomp_notif>::zeroed();
+                let res = unsafe {
+                    libc::ioctl(
+                        listener.as_raw_fd(),
+                        libc::SECCOMP_IOCTL_NOTIF_RECV as _,
+                        notif.as_mut_ptr(),
+                    )
+                };
+                if res < 0 {
+                    // the process making the call was killed before it could be received
+                    debug!(
+                        "error receiving seccomp notification: {}",
+                        io::Error::last_os_error()
+                    );
+                    guard.clear_ready();
+                    continue;
+                }
+
+                let notif = unsafe { notif.assume_init() };
+                let name = syscall_name(notif.data.nr as i64);
+                debug!(syscall = name, pid = notif.pid, "forbidden syscall");
+                *slot.lock().unwrap() = Some(name);
+
+                let _ = kill(Pid::from_raw(notif.pid as i32), Signal::SIGKILL);
+                let _ = kill(Pid::from_raw(pid as i32), Signal::SIGKILL);
+                return Ok(());
+            }
+        });
+
+        (violation, handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syscall_names() {
+        assert_eq!(syscall_nr("read").unwrap(), 0);
+        assert_eq!(syscall_name(libc::SYS_fork), "fork");
+        assert_eq!(syscall_name(100_000), "syscall 100000");
+        SyscallPolicy::Deny(vec!["not_a_syscall".to_string()])
+            .validate()
+            .unwrap_err();
+    }
+}
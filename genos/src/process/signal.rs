@@ -0,0 +1,252 @@
+use std::{fmt::Display, time::Duration};
+
+use anyhow::{Context, Result};
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use regex::bytes::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tokio::{
+    sync::mpsc::UnboundedReceiver,
+    time::{sleep, Instant},
+};
+use tracing::debug;
+
+use super::{
+    interaction::{regex_newlines, resume_point},
+    SignalType,
+};
+
+/// The signals which can be sent to a running process.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, JsonSchema)]
+pub enum SignalName {
+    SIGINT,
+    SIGTSTP,
+    SIGCONT,
+    SIGTERM,
+    SIGQUIT,
+    SIGHUP,
+    SIGUSR1,
+    SIGUSR2,
+    SIGALRM,
+    SIGKILL,
+}
+
+impl From<SignalName> for Signal {
+    fn from(value: SignalName) -> Self {
+        match value {
+            SignalName::SIGINT => Signal::SIGINT,
+            SignalName::SIGTSTP => Signal::SIGTSTP,
+            SignalName::SIGCONT => Signal::SIGCONT,
+            SignalName::SIGTERM => Signal::SIGTERM,
+            SignalName::SIGQUIT => Signal::SIGQUIT,
+            SignalName::SIGHUP => Signal::SIGHUP,
+            SignalName::SIGUSR1 => Signal::SIGUSR1,
+            SignalName::SIGUSR2 => Signal::SIGUSR2,
+            SignalName::SIGALRM => Signal::SIGALRM,
+            SignalName::SIGKILL => Signal::SIGKILL,
+        }
+    }
+}
+
+impl From<SignalName> for SignalType {
+    fn from(value: SignalName) -> Self {
+        SignalType::from(Signal::from(value) as i32)
+    }
+}
+
+impl Display for SignalName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// A signal sent to the process while it is running. Signals are sent in order, and each trigger
+/// only starts once the previous signal has been sent. So `after_ms` is the time since the previous
+/// signal, and `on_output` only matches output written after the previous signal's output. A match
+/// can only span as many lines as the pattern has `\n`s in it.
+/// Ex:
+/// signals:
+///     - signal: SIGTSTP
+///       on_output: "prompt> "
+///     - signal: SIGCONT
+///       after_ms: 200
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct ScheduledSignal {
+    pub signal: SignalName,
+    #[serde(flatten)]
+    pub trigger: SignalTrigger,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SignalTrigger {
+    AfterMs(u64),
+    /// a regex matched against stdout
+    OnOutput(String),
+}
+
+impl ScheduledSignal {
+    pub fn after(signal: SignalName, duration: Duration) -> Self {
+        Self {
+            signal,
+            trigger: SignalTrigger::AfterMs(duration.as_millis() as u64),
+        }
+    }
+
+    pub fn on_output<T: Into<String>>(signal: SignalName, pattern: T) -> Self {
+        Self {
+            signal,
+            trigger: SignalTrigger::OnOutput(pattern.into()),
+        }
+    }
+}
+
+/// A signal which was actually sent to the process, and when it was sent relative to the start of
+/// the process.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SentSignal {
+    pub signal: SignalName,
+    pub elapsed: Duration,
+}
+
+impl Display for SentSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} after {:?}", self.signal, self.elapsed)
+    }
+}
+
+/// Sends the scheduled signals to the process with the given pid. The scheduler is given the
+/// process's stdout as it is read so it can wait for output.
+pub struct SignalScheduler {
+    pid: Pid,
+    signals: Vec<ScheduledSignal>,
+    start: Instant,
+    sent: Vec<SentSignal>,
+}
+
+impl SignalScheduler {
+    pub fn new(pid: u32, signals: Vec<ScheduledSignal>) -> Self {
+        Self {
+            pid: Pid::from_raw(pid as i32),
+            signals,
+            start: Instant::now(),
+            sent: Vec::new(),
+        }
+    }
+
+    /// Runs until every signal has been sent, or until stdout is closed while waiting for output.
+    /// The caller is responsible for not polling this after the process has been reaped, otherwise
+    /// the pid could belong to some other process.
+    pub async fn run(&mut self, mut stdout: UnboundedReceiver<Vec<u8>>) -> Result<()> {
+        // the output which hasn't been matched yet, less what no match can start in anymore
+        let mut output = Vec::new();
+
+        for scheduled in self.signals.clone() {
+            match &scheduled.trigger {
+                SignalTrigger::AfterMs(ms) => sleep(Duration::from_millis(*ms)).await,
+                SignalTrigger::OnOutput(pattern) => {
+                    let regex = Regex::new(&format!("(?m){}", pattern))
+                        .context(format!("Invalid signal output pattern {}", pattern))?;
+                    let newlines = regex_newlines(pattern);
+                    loop {
+                        if let Some(found) = regex.find(&output) {
+                            output.drain(..found.end());
+                            break;
+                        }
+                        output.drain(..resume_point(&output, newlines));
+
+                        match stdout.recv().await {
+                            Some(chunk) => output.extend(chunk),
+                            None => {
+                                debug!(pattern, "stdout closed before the signal was sent");
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
+            }
+
+            debug!(signal = %scheduled.signal, "sending signal");
+            kill(self.pid, Signal::from(scheduled.signal))?;
+            self.sent.push(SentSignal {
+                signal: scheduled.signal,
+                elapsed: self.start.elapsed(),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn sent(&self) -> &[SentSignal] {
+        &self.sent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Config {
+        signals: Vec<ScheduledSignal>,
+    }
+
+    #[test]
+    fn signal_names_match_exit_signals() {
+        assert_eq!(SignalType::from(SignalName::SIGINT), SignalType::Other(2));
+        assert_eq!(SignalType::from(SignalName::SIGKILL), SignalType::Other(9));
+    }
+
+    #[tokio::test]
+    async fn signals_on_output_split_across_chunks() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        for chunk in ["x".repeat(10_000).as_str(), "\nrea", "dy\n"] {
+            tx.send(chunk.as_bytes().to_vec()).unwrap();
+        }
+
+        let signals = vec![ScheduledSignal::on_output(SignalName::SIGTERM, "^ready$")];
+        let mut scheduler = SignalScheduler::new(child.id(), signals);
+        scheduler.run(rx).await.unwrap();
+
+        assert_eq!(scheduler.sent().len(), 1);
+        let status = child.wait().unwrap();
+        assert_eq!(
+            std::os::unix::process::ExitStatusExt::signal(&status),
+            Some(15)
+        );
+    }
+
+    #[test]
+    fn deserialize_signals() {
+        let config: Config = toml::from_str(
+            r#"
+            [[signals]]
+            signal = "SIGTSTP"
+            on_output = "prompt> "
+
+            [[signals]]
+            signal = "SIGCONT"
+            after_ms = 200
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            config.signals[0].trigger,
+            SignalTrigger::OnOutput(_)
+        ));
+        assert!(matches!(
+            config.signals[1].trigger,
+            SignalTrigger::AfterMs(200)
+        ));
+    }
+}
@@ -7,6 +7,7 @@ use crate::{output::Output, points::PointQuantity, Executor};
 
 pub mod compare_files;
 pub mod import_files;
+pub mod prepare_workspace;
 
 /// System Stage executors represent executors which are for the autograding system itself. As
 /// such, they cannot by themselves fail a test. Any errors by a system stage should be treated as
@@ -1,16 +1,21 @@
 use std::{
     fmt::Display,
     path::{Path, PathBuf},
+    result::Result as StdResult,
+    time::Duration,
 };
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use tokio::{fs::File, io::AsyncReadExt};
 use tracing::debug;
 
 use crate::{
-    fs::{filename, filepath, ResourceLocatorCreator},
+    fs::{
+        self, filename, filepath, read_untrusted, resolve_in, ReadLimits, ResourceLocatorCreator,
+    },
     output::{self, Content, Output, RichTextMaker, Section, StatusUpdates, Update},
     points::PointQuantity,
     process::{Command, ExitStatus, ProcessExecutor},
@@ -18,21 +23,44 @@ use crate::{
     Executor,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+// the limits on reading a student file, so a huge file or one which never finishes being written
+// can't hang the grader
+const DEFAULT_MAX_STUDENT_FILE_KB: u64 = 1024;
+const STUDENT_FILE_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize, JsonSchema, Clone)]
 pub struct ComparesConfig {
     pub compares: Vec<CompareConfig>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, JsonSchema, Clone)]
 pub struct CompareConfig {
     pub expected: Vec<String>,
     pub student_file: String,
     pub compare_type: CompareType,
     pub points: PointQuantity,
     pub show_output: bool,
+    /// the largest student file which is compared, 1024 KB when not given
+    #[serde(default)]
+    pub max_size_kb: Option<u64>,
+}
+
+impl CompareConfig {
+    fn read_limits(&self) -> ReadLimits {
+        ReadLimits {
+            max_size: self.max_size_kb.unwrap_or(DEFAULT_MAX_STUDENT_FILE_KB) * 1024,
+            timeout: STUDENT_FILE_READ_TIMEOUT,
+        }
+    }
 }
 
-#[derive(Debug, Eq, PartialEq, Deserialize, Clone)]
+/// A student file which was read safely, see fs::read_untrusted.
+struct StudentFile {
+    path: PathBuf,
+    contents: Vec<u8>,
+}
+
+#[derive(Debug, Eq, PartialEq, Deserialize, JsonSchema, Clone)]
 pub enum CompareType {
     Diff,
     Grep,
@@ -139,16 +167,70 @@ where
         }
     }
 
-    async fn match_any(&self, compare: &CompareConfig, ws: &Path) -> Result<bool> {
+    // The student file is made by the student code, so it's checked before anything reads it. The
+    // error is the reason it can't be compared, which is given to the student.
+    async fn read_student_file(
+        compare: &CompareConfig,
+        ws: &Path,
+    ) -> Result<StdResult<StudentFile, String>> {
+        let name = &compare.student_file;
+
+        // the student file can be a symlink made by the student code, which can't be followed out
+        // of the ws
+        let path = match resolve_in(ws, name) {
+            Ok(path) => path,
+            Err(fs::Error::EscapesRoot(_)) => {
+                return Ok(Err(format!(
+                    "File {} leads outside of the workspace, so it can't be compared",
+                    name
+                )))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let limits = compare.read_limits();
+        let problem = match read_untrusted(&path, limits).await {
+            Ok(contents) => return Ok(Ok(StudentFile { path, contents })),
+            Err(fs::Error::NotFound) => {
+                debug!("Could not find student file: {}", path.display());
+                format!("Could not find file {} in root of workspace", name)
+            }
+            Err(fs::Error::NotRegularFile(_)) => format!(
+                "File {} isn't a regular file, so it can't be compared. Make sure it isn't a \
+                 symlink, directory or pipe.",
+                name
+            ),
+            Err(fs::Error::TooLarge { size, .. }) => format!(
+                "File {} is {} KB, over the limit of {} KB for compared files",
+                name,
+                size.div_ceil(1024),
+                limits.max_size / 1024
+            ),
+            Err(fs::Error::ReadTimedOut(_)) => format!(
+                "Reading file {} took over {} seconds, so it can't be compared",
+                name,
+                limits.timeout.as_secs()
+            ),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Err(problem))
+    }
+
+    async fn match_any(
+        &self,
+        compare: &CompareConfig,
+        ws: &Path,
+        student_file: &Path,
+    ) -> Result<bool> {
         // fs knows where to locate any expected resource files
         let finder = self.fs_creator.create(ws);
         let comparator = self.comparator_creator.create(&compare.compare_type);
-        let student_file = ws.join(&compare.student_file);
 
         for expected_file_name in &compare.expected {
             let expected_file_path = finder.find(expected_file_name)?;
             if comparator
-                .compare(&expected_file_path, &student_file)
+                .compare(&expected_file_path, student_file)
                 .await?
             {
                 return Ok(true);
@@ -194,10 +276,10 @@ where
         &self,
         compare: &CompareConfig,
         expected_file: &PathBuf,
-        student_file: &PathBuf,
+        student_file: &StudentFile,
     ) -> Result<output::Content> {
         if !compare.show_output {
-            let filename = filename(student_file)?;
+            let filename = filename(&student_file.path)?;
             let lines = [
                 format!("Actual {} did not match expected {}", filename, filename,),
                 "The instructor has chosen to keep this output hidden.".to_string(),
@@ -217,17 +299,19 @@ where
     async fn get_failed_diff_feedback(
         &self,
         expected_file: &PathBuf,
-        student_file: &PathBuf,
+        student_file: &StudentFile,
     ) -> Result<output::Content> {
         let expected_content = load_transformed_content(expected_file).await?.code();
-        let student_content = load_transformed_content(student_file).await?.code();
+        // the student file was already read within the limits, so it isn't read again
+        let student_content = transform_content(&student_file.contents).code();
+        let student_filename = filename(&student_file.path)?;
 
         let expected_section = Content::SubSection(
-            Section::new(format!("Expected {}", filename(student_file)?)).content(expected_content),
+            Section::new(format!("Expected {}", student_filename)).content(expected_content),
         );
 
         let actual_section = Content::SubSection(
-            Section::new(format!("Actual {}", filename(student_file)?)).content(student_content),
+            Section::new(format!("Actual {}", student_filename)).content(student_content),
         );
 
         Ok(Content::Multiline(
@@ -254,26 +338,25 @@ where
                 "Comparing {} ({})",
                 compare_config.student_file, compare_config.compare_type
             ));
-            let student_file = ws.join(&compare_config.student_file);
-
-            // first check to see if the student file exists
-            if !student_file.exists() {
-                debug!("Could not find student file: {}", student_file.display());
-                update.set_fail(compare_config.points);
-                update.set_notes(format!(
-                    "Could not find file {} in root of workspace",
-                    compare_config.student_file
-                ));
-                compare_status_updates.add_update(update);
+            let student_file = match Self::read_student_file(compare_config, ws).await? {
+                Ok(student_file) => student_file,
+                Err(problem) => {
+                    update.set_fail(compare_config.points);
+                    update.set_notes(problem);
+                    compare_status_updates.add_update(update);
 
-                points_lost += compare_config.points;
+                    points_lost += compare_config.points;
 
-                continue;
-            }
+                    continue;
+                }
+            };
 
             // if the file exists, then run the compare. Get the correct comparator from the
             // comparator factory.
-            if self.match_any(compare_config, ws).await? {
+            if self
+                .match_any(compare_config, ws, &student_file.path)
+                .await?
+            {
                 compare_status_updates.add_update(update);
                 continue;
             }
@@ -285,7 +368,7 @@ where
             let finder = self.fs_creator.create(ws);
             let expected_file = finder.find(&compare_config.expected[0])?;
             update.set_notes(
-                self.get_failed_compare_feedback(&compare_config, &expected_file, &student_file)
+                self.get_failed_compare_feedback(compare_config, &expected_file, &student_file)
                     .await?,
             );
 
@@ -304,6 +387,10 @@ async fn load_transformed_content(file: &PathBuf) -> Result<String> {
     let mut contents = Vec::new();
     file.read_to_end(&mut contents).await?;
 
+    Ok(transform_content(&contents))
+}
+
+fn transform_content(contents: &[u8]) -> String {
     let mut res = String::new();
     let mut line_number: u64 = 1;
 
@@ -332,14 +419,14 @@ async fn load_transformed_content(file: &PathBuf) -> Result<String> {
     };
 
     res.push_str(line_number_str().as_str());
-    for byte in &contents {
+    for byte in contents {
         res.push_str(transform_byte(byte).as_str());
         if char::from(*byte) == '\n' {
             res.push_str(line_number_str().as_str());
         }
     }
 
-    Ok(res)
+    res
 }
 
 #[cfg(test)]
@@ -378,6 +465,7 @@ mod tests {
                 compare_type: CompareType::Diff,
                 points: PointQuantity::Partial(Points::new(4)),
                 show_output: true,
+                max_size_kb: None,
             }],
         };
 
@@ -395,6 +483,86 @@ mod tests {
         assert!(res.output.unwrap().contains("Could not find file"));
     }
 
+    #[tokio::test]
+    async fn student_file_leading_outside_ws_fails_compare() {
+        let outside = MockDir::new().file(("expected_stdout", "expected"));
+        let ws = MockDir::new();
+        std::os::unix::fs::symlink(
+            outside.path_from_root("expected_stdout"),
+            ws.path_from_root("stdout"),
+        )
+        .unwrap();
+
+        let finder_creator = |_ws: &Path| -> Box<dyn ResourceLocator> { Box::new(MockDir::new()) };
+        let compares = ComparesConfig {
+            compares: vec![CompareConfig {
+                expected: vec!["expected_stdout".to_string()],
+                student_file: "stdout".to_string(),
+                compare_type: CompareType::Diff,
+                points: PointQuantity::Partial(Points::new(4)),
+                show_output: true,
+                max_size_kb: None,
+            }],
+        };
+
+        // the comparator is never run
+        let comparator_creator =
+            ComparatorCreatorImpl::new(MockProcessExecutor::with_responses([]));
+
+        let stage = CompareFiles::new(finder_creator, comparator_creator, compares);
+        let res = stage.run(ws.root.path()).await.unwrap();
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(4))
+            }
+        );
+
+        assert!(res
+            .output
+            .unwrap()
+            .contains("leads outside of the workspace"));
+    }
+
+    #[tokio::test]
+    async fn unsafe_student_files_fail_compare() {
+        let ws = MockDir::new()
+            .file(("large", "a".repeat(2048)))
+            .dir("dir", MockDir::new());
+        nix::unistd::mkfifo(&ws.path_from_root("fifo"), nix::sys::stat::Mode::S_IRWXU).unwrap();
+
+        let finder_creator = |_ws: &Path| -> Box<dyn ResourceLocator> { Box::new(MockDir::new()) };
+        let compare = |student_file: &str| CompareConfig {
+            expected: vec!["expected_stdout".to_string()],
+            student_file: student_file.to_string(),
+            compare_type: CompareType::Diff,
+            points: PointQuantity::Partial(Points::new(1)),
+            show_output: true,
+            max_size_kb: Some(1),
+        };
+        let compares = ComparesConfig {
+            compares: vec![compare("fifo"), compare("dir"), compare("large")],
+        };
+
+        // the comparator is never run
+        let comparator_creator =
+            ComparatorCreatorImpl::new(MockProcessExecutor::with_responses([]));
+
+        let stage = CompareFiles::new(finder_creator, comparator_creator, compares);
+        let res = stage.run(ws.root.path()).await.unwrap();
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(3))
+            }
+        );
+
+        let output = res.output.unwrap();
+        assert!(output.contains("File fifo isn't a regular file"));
+        assert!(output.contains("File dir isn't a regular file"));
+        assert!(output.contains("File large is 2 KB, over the limit of 1 KB"));
+    }
+
     #[tokio::test]
     async fn diff_compare_pass() {
         let finder_creator = |_ws: &Path| -> Box<dyn ResourceLocator> {
@@ -416,6 +584,7 @@ mod tests {
                     compare_type: CompareType::Diff,
                     points: PointQuantity::Partial(Points::new(1)),
                     show_output: true,
+                    max_size_kb: None,
                 },
                 CompareConfig {
                     expected: vec!["expected_stderr".to_string()],
@@ -423,6 +592,7 @@ mod tests {
                     compare_type: CompareType::Diff,
                     points: PointQuantity::Partial(Points::new(2)),
                     show_output: true,
+                    max_size_kb: None,
                 },
             ],
         };
@@ -466,6 +636,7 @@ mod tests {
                     compare_type: CompareType::Diff,
                     points: PointQuantity::FullPoints,
                     show_output: true,
+                    max_size_kb: None,
                 },
                 CompareConfig {
                     expected: vec!["expected_stderr".to_string()],
@@ -473,6 +644,7 @@ mod tests {
                     compare_type: CompareType::Diff,
                     points: PointQuantity::FullPoints,
                     show_output: true,
+                    max_size_kb: None,
                 },
             ],
         };
@@ -516,6 +688,7 @@ mod tests {
                     compare_type: CompareType::Diff,
                     points: PointQuantity::Partial(Points::new(1)),
                     show_output: true,
+                    max_size_kb: None,
                 },
                 CompareConfig {
                     expected: vec!["expected_stderr".to_string()],
@@ -523,6 +696,7 @@ mod tests {
                     compare_type: CompareType::Diff,
                     points: PointQuantity::Partial(Points::new(2)),
                     show_output: true,
+                    max_size_kb: None,
                 },
             ],
         };
@@ -569,6 +743,7 @@ mod tests {
                 compare_type: CompareType::Diff,
                 points: PointQuantity::Partial(Points::new(1)),
                 show_output: true,
+                max_size_kb: None,
             }],
         };
 
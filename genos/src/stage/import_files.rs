@@ -1,57 +1,282 @@
-use std::path::{Path, PathBuf};
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use serde::Deserialize;
-use tokio::fs::copy;
-use tracing::debug;
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer};
+use tokio::fs::{copy, create_dir_all, remove_file, set_permissions, symlink};
+use tracing::{debug, info};
 
-use crate::fs::ResourceLocator;
+use crate::fs::{files_in, resolve_in, ResourceLocator};
 
 use super::SystemStageExecutor;
 
+/// A located resource and where it goes in the ws.
+#[derive(Debug, Clone)]
+struct Import {
+    src: PathBuf,
+    // relative to the ws
+    dest: PathBuf,
+    mode: Option<u32>,
+    link: bool,
+}
+
 #[derive(Default)]
 pub struct ImportFiles {
-    files: Vec<PathBuf>,
+    imports: Vec<Import>,
 }
 
 impl ImportFiles {
     pub fn new<F: ResourceLocator>(config: &ImportConfig, finder: &F) -> Result<Self> {
         let mut imports = ImportFiles::default();
-        for file_name in &config.files {
-            imports.files.push(finder.find(&file_name)?);
+        for entry in &config.files {
+            imports.imports.extend(entry.spec().locate(finder)?);
         }
 
         Ok(imports)
     }
 }
 
+// anything already at `to` is replaced. A symlink is removed first so that the copy doesn't write
+// through it into whatever it points at.
+async fn remove_existing(to: &Path, src: &Path) -> Result<()> {
+    if let Ok(metadata) = to.symlink_metadata() {
+        info!(file=?to, by=?src, "overwriting file in ws");
+        if metadata.is_symlink() {
+            remove_file(to).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn copy_file(src: &Path, to: &Path, mode: Option<u32>) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        create_dir_all(parent).await?;
+    }
+    remove_existing(to, src).await?;
+
+    debug!(src=?src, dest=?to, "copying file");
+    copy(src, to).await?;
+
+    if let Some(mode) = mode {
+        set_permissions(to, std::fs::Permissions::from_mode(mode)).await?;
+    }
+    Ok(())
+}
+
 #[async_trait]
 impl SystemStageExecutor for ImportFiles {
     async fn run(&self, ws: &Path) -> Result<()> {
-        for file in &self.files {
-            let to = ws.join(
-                file.file_name()
-                    .ok_or(anyhow!("could not get filename for {}", file.display()))?,
-            );
+        for import in &self.imports {
+            let to = ws.join(&import.dest);
 
-            debug!(src=?file, dest=?to, "copying file");
-            copy(file, to).await?;
+            if import.link {
+                if let Some(parent) = to.parent() {
+                    create_dir_all(parent).await?;
+                }
+                remove_existing(&to, &import.src).await?;
+
+                debug!(src=?import.src, dest=?to, "linking file");
+                symlink(&import.src, &to).await?;
+            } else if import.src.is_dir() {
+                for file in files_in(&import.src).await? {
+                    copy_file(&import.src.join(&file), &to.join(&file), import.mode).await?;
+                }
+            } else {
+                copy_file(&import.src, &to, import.mode).await?;
+            }
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+/// The resources to import into the ws. Each entry is either the name of a resource, which is
+/// imported into the ws root, or an ImportSpec.
+/// Ex:
+/// import_files:
+///     files:
+///         - expected_stdout
+///         - { src: fixtures, dest: tests }
+///         - { src: "inputs/*.txt", dest: inputs }
+///         - { src: helper.sh, mode: "755" }
+///         - { src: large_dataset, link: true }
+#[derive(Debug, Default, Deserialize, JsonSchema, Clone)]
 pub struct ImportConfig {
-    files: Vec<String>,
+    files: Vec<ImportEntry>,
 }
 
 impl ImportConfig {
     pub fn new(files: impl IntoIterator<Item = impl Into<String>>) -> Self {
         Self {
-            files: files.into_iter().map(|f| f.into()).collect(),
+            files: files
+                .into_iter()
+                .map(|f| ImportEntry::Name(f.into()))
+                .collect(),
+        }
+    }
+
+    pub fn add_spec(&mut self, spec: ImportSpec) {
+        self.files.push(ImportEntry::Spec(spec));
+    }
+
+    /// The src of each entry which can't be imported with the finder, along with why. This checks
+    /// the config without importing anything.
+    pub fn unresolved<F: ResourceLocator>(&self, finder: &F) -> Vec<(String, anyhow::Error)> {
+        self.files
+            .iter()
+            .map(ImportEntry::spec)
+            .filter_map(|spec| spec.locate(finder).err().map(|e| (spec.src, e)))
+            .collect()
+    }
+
+    /// Whether a file is imported to the path in the ws, either on its own or in an imported
+    /// directory. This checks the config without importing anything.
+    pub fn imports<F: ResourceLocator>(&self, finder: &F, path: &str) -> bool {
+        let path: PathBuf = Path::new(path)
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .collect();
+
+        self.files
+            .iter()
+            .filter_map(|entry| entry.spec().locate(finder).ok())
+            .flatten()
+            .any(|import| match path.strip_prefix(&import.dest) {
+                Ok(rest) if rest.as_os_str().is_empty() => import.src.is_file(),
+                Ok(rest) => {
+                    import.src.is_dir()
+                        && resolve_in(&import.src, rest).is_ok_and(|file| file.is_file())
+                }
+                Err(_) => false,
+            })
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema, Clone)]
+#[serde(untagged)]
+pub enum ImportEntry {
+    Name(String),
+    Spec(ImportSpec),
+}
+
+impl ImportEntry {
+    fn spec(&self) -> ImportSpec {
+        match self {
+            Self::Name(name) => ImportSpec {
+                src: name.clone(),
+                ..Default::default()
+            },
+            Self::Spec(spec) => spec.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema, Clone)]
+pub struct ImportSpec {
+    /// a file, a directory which is imported recursively, or a glob pattern matching files. The
+    /// files matching a pattern keep their paths from the first part of the pattern with a glob
+    /// in it, so `inputs/**/*.txt` imports `inputs/a/1.txt` as `a/1.txt`.
+    pub src: String,
+    /// the directory in the ws to import into, the ws root when not given
+    pub dest: Option<String>,
+    /// the permission bits of each imported file in octal, ex: "755" for an executable script
+    #[serde(default, deserialize_with = "deserialize_mode")]
+    #[schemars(with = "Option<String>")]
+    pub mode: Option<u32>,
+    /// symlink to the resource instead of copying it, for large fixtures. The student code is
+    /// able to write through the link, so only link resources which are safe to change.
+    #[serde(default)]
+    pub link: bool,
+}
+
+impl ImportSpec {
+    fn is_glob(&self) -> bool {
+        self.src.contains(['*', '?', '['])
+    }
+
+    // the part of the src before the first component with a glob in it
+    fn glob_base(&self) -> PathBuf {
+        Path::new(&self.src)
+            .components()
+            .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+            .collect()
+    }
+
+    fn dest_dir(&self) -> Result<PathBuf> {
+        let dest = PathBuf::from(self.dest.as_deref().unwrap_or(""));
+        // imports can't be placed outside of the ws
+        if dest
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(anyhow!(
+                "Import destination must be a relative path inside of the ws: {}",
+                dest.display()
+            ));
+        }
+        Ok(dest)
+    }
+
+    fn locate<F: ResourceLocator>(&self, finder: &F) -> Result<Vec<Import>> {
+        if self.link && self.mode.is_some() {
+            return Err(anyhow!(
+                "The mode of a linked import can't be set, since it would change the resource: {}",
+                self.src
+            ));
         }
+
+        let dest = self.dest_dir()?;
+        let import = |src: PathBuf, name: &Path| Import {
+            src,
+            dest: dest.join(name),
+            mode: self.mode,
+            link: self.link,
+        };
+
+        if self.is_glob() {
+            let base = self.glob_base();
+            let found = finder.find_matching(&self.src)?;
+            if found.is_empty() {
+                return Err(anyhow!("No test resources match {}", self.src));
+            }
+
+            return found
+                .into_iter()
+                .map(|(name, src)| {
+                    let name = Path::new(&name).strip_prefix(&base)?.to_path_buf();
+                    Ok(import(src, &name))
+                })
+                .collect();
+        }
+
+        let src = finder
+            .find(&self.src)
+            .or_else(|_| finder.find_dir(&self.src))?;
+        let name = src
+            .file_name()
+            .ok_or(anyhow!("could not get filename for {}", src.display()))?
+            .to_owned();
+
+        Ok(vec![import(src, Path::new(&name))])
+    }
+}
+
+fn deserialize_mode<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mode = String::deserialize(deserializer)?;
+    let digits = mode.trim_start_matches("0o");
+
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(Some(mode)),
+        _ => Err(de::Error::custom(format!(
+            "expected permission bits in octal, ex: \"755\", but found {}",
+            mode
+        ))),
     }
 }
 
@@ -63,6 +288,15 @@ mod tests {
 
     use super::*;
 
+    fn plain(src: PathBuf) -> Import {
+        Import {
+            dest: PathBuf::from(src.file_name().unwrap()),
+            src,
+            mode: None,
+            link: false,
+        }
+    }
+
     #[tokio::test]
     async fn copies_files() {
         let dir1 = tempfile::tempdir().unwrap();
@@ -76,7 +310,7 @@ mod tests {
         assert!(f2.try_exists().unwrap());
 
         let import = ImportFiles {
-            files: vec![f1.clone(), f2.clone()],
+            imports: vec![plain(f1.clone()), plain(f2.clone())],
         };
 
         import.run(ws.path()).await.unwrap();
@@ -96,6 +330,25 @@ mod tests {
         assert_eq!(&contents, "file2".as_bytes());
     }
 
+    #[test]
+    fn finds_imported_paths() {
+        let config: ImportConfig = toml::from_str(
+            r#"
+            files = ["expected", { src = "fixtures", dest = "tests" }]
+            "#,
+        )
+        .unwrap();
+        let data = MockDir::new()
+            .file(("expected", ""))
+            .dir("fixtures", MockDir::new().file(("in.txt", "")));
+
+        assert!(config.imports(&data, "expected"));
+        assert!(config.imports(&data, "./tests/fixtures/in.txt"));
+        assert!(!config.imports(&data, "tests/fixtures/missing.txt"));
+        assert!(!config.imports(&data, "fixtures/in.txt"));
+        assert!(!config.imports(&data, "tests/fixtures/../../expected"));
+    }
+
     #[tokio::test]
     async fn import_config_to_executor() {
         let config = ImportConfig::new(["file1", "file2"]);
@@ -106,4 +359,125 @@ mod tests {
 
         ImportFiles::new(&config, &data).unwrap();
     }
+
+    #[tokio::test]
+    async fn imports_dirs_globs_and_links() {
+        let config: ImportConfig = toml::from_str(
+            r#"
+            files = [
+                "expected",
+                { src = "fixtures", dest = "tests" },
+                { src = "inputs/**/*.txt", dest = "in" },
+                { src = "helper.sh", dest = "bin", mode = "755" },
+                { src = "dataset", link = true },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let data = MockDir::new()
+            .file(("expected", "expected contents"))
+            .file(("helper.sh", "#!/bin/sh"))
+            .dir(
+                "fixtures",
+                MockDir::new()
+                    .file(("a", "a"))
+                    .dir("nested", MockDir::new().file(("b", "b"))),
+            )
+            .dir(
+                "inputs",
+                MockDir::new()
+                    .file(("1.txt", "1"))
+                    .file(("skip.bin", ""))
+                    .dir("more", MockDir::new().file(("2.txt", "2"))),
+            )
+            .dir("dataset", MockDir::new().file(("data.csv", "1,2")));
+        let ws = MockDir::new();
+
+        ImportFiles::new(&config, &data)
+            .unwrap()
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        for file in [
+            "expected",
+            "tests/fixtures/a",
+            "tests/fixtures/nested/b",
+            "in/1.txt",
+            "in/more/2.txt",
+            "dataset/data.csv",
+        ] {
+            assert!(
+                ws.path_from_root(file).is_file(),
+                "{} wasn't imported",
+                file
+            );
+        }
+        assert!(!ws.path_from_root("in/skip.bin").exists());
+
+        let helper = ws.path_from_root("bin/helper.sh");
+        assert_eq!(
+            helper.metadata().unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+
+        let dataset = ws.path_from_root("dataset");
+        assert!(dataset.symlink_metadata().unwrap().is_symlink());
+        assert_eq!(
+            std::fs::read_link(dataset).unwrap(),
+            data.path_from_root("dataset")
+        );
+    }
+
+    #[tokio::test]
+    async fn replaces_symlink_instead_of_writing_through_it() {
+        let data = MockDir::new().file(("target", "instructor"));
+        let outside = MockDir::new().file(("secret", "untouched"));
+        let ws = MockDir::new();
+        std::os::unix::fs::symlink(
+            outside.path_from_root("secret"),
+            ws.path_from_root("target"),
+        )
+        .unwrap();
+
+        ImportFiles::new(&ImportConfig::new(["target"]), &data)
+            .unwrap()
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(outside.path_from_root("secret")).unwrap(),
+            "untouched"
+        );
+        assert_eq!(
+            std::fs::read_to_string(ws.path_from_root("target")).unwrap(),
+            "instructor"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        let data = MockDir::new().file(("file", ""));
+
+        let spec = |config| toml::from_str::<ImportConfig>(config);
+        spec(r#"files = [{ src = "file", mode = "999" }]"#).unwrap_err();
+
+        for config in [
+            r#"files = [{ src = "file", dest = "../outside" }]"#,
+            r#"files = [{ src = "file", link = true, mode = "644" }]"#,
+            r#"files = ["*.missing"]"#,
+        ] {
+            assert!(ImportFiles::new(&spec(config).unwrap(), &data).is_err());
+        }
+
+        let config = spec(r#"files = ["file", "missing", { src = "*.missing" }]"#).unwrap();
+        let unresolved: Vec<String> = config
+            .unresolved(&data)
+            .into_iter()
+            .map(|(src, _)| src)
+            .collect();
+        assert_eq!(unresolved, ["missing", "*.missing"]);
+    }
 }
@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio::fs::{copy, create_dir_all};
+use tracing::{debug, info};
+
+use crate::fs::files_in;
+
+use super::SystemStageExecutor;
+
+/// A directory whose contents are copied into the ws. The name is used to say where a file came
+/// from in the logs.
+#[derive(Debug, Clone)]
+struct Layer {
+    name: String,
+    dir: PathBuf,
+}
+
+/// PrepareWorkspace copies the contents of each of its layers into the ws, in the order the
+/// layers were added. A file from a later layer replaces the file at the same path from an earlier
+/// layer, which is how instructor files take precedence over student files of the same name.
+/// Every replaced file is logged along with the layers involved.
+///
+/// Symlinks aren't copied, since a symlink in a submission could point anywhere on the system.
+#[derive(Debug, Default, Clone)]
+pub struct PrepareWorkspace {
+    layers: Vec<Layer>,
+}
+
+impl PrepareWorkspace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn layer<N: Into<String>, P: Into<PathBuf>>(mut self, name: N, dir: P) -> Self {
+        self.add_layer(name, dir);
+        self
+    }
+
+    pub fn add_layer<N: Into<String>, P: Into<PathBuf>>(&mut self, name: N, dir: P) {
+        self.layers.push(Layer {
+            name: name.into(),
+            dir: dir.into(),
+        });
+    }
+}
+
+#[async_trait]
+impl SystemStageExecutor for PrepareWorkspace {
+    async fn run(&self, ws: &Path) -> Result<()> {
+        // the layer which each file in the ws came from
+        let mut sources: HashMap<PathBuf, &str> = HashMap::new();
+
+        for layer in &self.layers {
+            if !layer.dir.is_dir() {
+                return Err(anyhow!(
+                    "Expected {} layer to be a directory: {}",
+                    layer.name,
+                    layer.dir.display()
+                ));
+            }
+
+            for file in files_in(&layer.dir).await? {
+                let to = ws.join(&file);
+                if let Some(parent) = to.parent() {
+                    create_dir_all(parent).await?;
+                }
+
+                if to.exists() {
+                    let replaced = sources.get(&file).copied().unwrap_or("ws");
+                    info!(file=?file, replaced, by=%layer.name, "overwriting file in ws");
+                }
+
+                debug!(src=?layer.dir.join(&file), dest=?to, "copying file");
+                copy(layer.dir.join(&file), &to).await?;
+                sources.insert(file, &layer.name);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::fs::read_to_string;
+
+    use crate::test_util::MockDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn later_layers_take_precedence() {
+        let submission = MockDir::new()
+            .file(("main.c", "student main"))
+            .file(("Makefile", "student makefile"))
+            .dir("src", MockDir::new().file(("list.c", "student list")));
+        let basefiles = MockDir::new()
+            .file(("Makefile", "instructor makefile"))
+            .dir("src", MockDir::new().file(("list.h", "instructor header")));
+        let ws = MockDir::new();
+
+        PrepareWorkspace::new()
+            .layer("submission", submission.root.path())
+            .layer("basefiles", basefiles.root.path())
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        let contents = |file| read_to_string(ws.path_from_root(file));
+        assert_eq!(contents("main.c").await.unwrap(), "student main");
+        assert_eq!(contents("Makefile").await.unwrap(), "instructor makefile");
+        assert_eq!(contents("src/list.c").await.unwrap(), "student list");
+        assert_eq!(contents("src/list.h").await.unwrap(), "instructor header");
+    }
+
+    #[tokio::test]
+    async fn skips_symlinks() {
+        let submission = MockDir::new().file(("main.c", "student main"));
+        std::os::unix::fs::symlink("/etc/passwd", submission.path_from_root("passwd")).unwrap();
+        let ws = MockDir::new();
+
+        PrepareWorkspace::new()
+            .layer("submission", submission.root.path())
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert!(ws.path_from_root("main.c").exists());
+        assert!(!ws.path_from_root("passwd").exists());
+    }
+
+    #[tokio::test]
+    async fn missing_layer_is_an_error() {
+        let ws = MockDir::new();
+
+        PrepareWorkspace::new()
+            .layer("basefiles", ws.path_from_root("basefiles"))
+            .run(ws.root.path())
+            .await
+            .unwrap_err();
+    }
+}
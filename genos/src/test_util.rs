@@ -67,13 +67,21 @@ impl MockDir {
 // top level
 impl ResourceLocator for MockDir {
     fn find(&self, name: &String) -> StdResult<PathBuf, fs::Error> {
-        let file = self.root.path().join(name);
+        let file = fs::resolve_in(self.root.path(), name)?;
         if !file.exists() {
             return Err(fs::Error::NotFound);
         }
 
         Ok(file)
     }
+
+    fn find_dir(&self, name: &String) -> StdResult<PathBuf, fs::Error> {
+        fs::find_dir_in(self.root.path(), name)
+    }
+
+    fn find_matching(&self, pattern: &str) -> StdResult<Vec<(String, PathBuf)>, fs::Error> {
+        fs::find_matching_in(self.root.path(), pattern)
+    }
 }
 
 pub struct MockFile {
@@ -115,11 +123,7 @@ impl MockExecutorInner {
         Self {
             commands: Vec::new(),
             responses: resp.into_iter().collect(),
-            default: Ok(process::Output {
-                status: ExitStatus::Ok,
-                stdout: "".to_string(),
-                stderr: "".to_string(),
-            }),
+            default: Ok(process::Output::from_exit_status(ExitStatus::Ok)),
         }
     }
 }
@@ -1,14 +1,39 @@
 use std::fmt::Display;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
+/// The tests generated from the rows of a parametrized test get ids derived from the id of the
+/// test, so row 1 of test 3 is test 3001, row 2 is 3002 and so on.
+pub const PARAM_ID_STRIDE: u32 = 1000;
+
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, Eq, PartialEq, Ord, PartialOrd, Hash,
+)]
 pub struct TestId(u32);
 
 impl TestId {
     pub fn new(id: u32) -> Self {
         Self(id)
     }
+
+    /// The id of the test generated from a row of this test, where the first row is 1. None when
+    /// the row is past the rows a test can have or the id is too large.
+    pub fn param(&self, row: u32) -> Option<Self> {
+        if row == 0 || row >= PARAM_ID_STRIDE {
+            return None;
+        }
+        self.0
+            .checked_mul(PARAM_ID_STRIDE)
+            .and_then(|base| base.checked_add(row))
+            .map(Self)
+    }
+
+    /// Whether the id is in the range of the ids generated from the rows of parametrized tests,
+    /// which a test config can't declare without colliding with them.
+    pub fn is_param(&self) -> bool {
+        self.0 >= PARAM_ID_STRIDE
+    }
 }
 
 impl Display for TestId {
@@ -29,3 +54,21 @@ impl From<i32> for TestId {
         Self::new(value as u32)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn param_ids() {
+        let tid = TestId::new(3);
+
+        assert_eq!(tid.param(1), Some(TestId::new(3001)));
+        assert_eq!(tid.param(999), Some(TestId::new(3999)));
+        assert_eq!(tid.param(0), None);
+        assert_eq!(tid.param(1000), None);
+        assert_eq!(TestId::new(u32::MAX).param(1), None);
+        assert!(TestId::new(3001).is_param());
+        assert!(!TestId::new(999).is_param());
+    }
+}
@@ -0,0 +1,181 @@
+/*
+ * genos_unittest.h
+ *
+ * Unit testing header for instructor test drivers. The driver is compiled together with the
+ * student sources and every test case is run as its own process, so a crash in one case can't
+ * affect any other case.
+ *
+ *     #include "genos_unittest.h"
+ *
+ *     GENOS_TEST(push_one) {
+ *         list_t *list = list_new();
+ *         list_push(list, 1);
+ *         GENOS_ASSERT(list_len(list) == 1);
+ *         GENOS_ASSERT_EQ_INT(list_get(list, 0), 1);
+ *     }
+ *
+ * Running `./driver push_one` runs a single case. The results are written using the following
+ * line protocol, which is parsed by the grader.
+ *
+ *     GENOS_UNITTEST:<nonce>:FAIL:<file>:<line>:<message>   an assertion failed, the case keeps
+ *                                                           running
+ *     GENOS_UNITTEST:<nonce>:PASS                           the case ran to completion without
+ *                                                           failures
+ *     GENOS_UNITTEST:<nonce>:DONE                           the case ran to completion with failures
+ *     GENOS_UNITTEST:<nonce>:UNKNOWN:<name>                 no case with the given name exists
+ *
+ * The student code runs in the same process, so the results aren't written to stdout where it
+ * could print them itself. The grader gives the path of a report file in GENOS_UNITTEST_REPORT and
+ * the number of an inherited fd as a second arg, `./driver push_one 3`. The fd is a pipe holding a
+ * nonce which is new for every run. It's read and closed before the case runs, and the grader
+ * rejects any record without the nonce. The nonce is never in the environment or on an open fd,
+ * but it is in the memory of the process, which the student code shares. So this stops output or
+ * guesses which look like records, not student code which searches the driver's memory for the
+ * nonce. When they aren't given, such as when running the driver by hand, the results go to
+ * stdout.
+ */
+
+#ifndef GENOS_UNITTEST_H
+#define GENOS_UNITTEST_H
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define GENOS_UNITTEST_PREFIX "GENOS_UNITTEST:"
+#define GENOS_UNITTEST_REPORT_ENV "GENOS_UNITTEST_REPORT"
+#define GENOS_UNITTEST_MAX_CASES 256
+#define GENOS_UNITTEST_MAX_NONCE 64
+
+typedef void (*genos_case_fn)(void);
+
+struct genos_case {
+    const char *name;
+    genos_case_fn fn;
+};
+
+static struct genos_case genos_cases[GENOS_UNITTEST_MAX_CASES];
+static int genos_num_cases = 0;
+static int genos_num_failures = 0;
+static FILE *genos_report = NULL;
+static char genos_nonce[GENOS_UNITTEST_MAX_NONCE + 1] = "";
+
+static void genos_register(const char *name, genos_case_fn fn) {
+    if (genos_num_cases == GENOS_UNITTEST_MAX_CASES) {
+        fprintf(stderr, "genos_unittest: too many test cases\n");
+        exit(2);
+    }
+    genos_cases[genos_num_cases].name = name;
+    genos_cases[genos_num_cases].fn = fn;
+    genos_num_cases++;
+}
+
+// reads the nonce out of the fd the grader passed and closes it, so the student code can't read
+// it from there
+static void genos_open_report(const char *nonce_fd) {
+    const char *path = getenv(GENOS_UNITTEST_REPORT_ENV);
+
+    genos_report = stdout;
+    if (nonce_fd != NULL && path != NULL) {
+        int fd = atoi(nonce_fd);
+        size_t len = 0;
+        ssize_t n;
+        while (len < GENOS_UNITTEST_MAX_NONCE &&
+               (n = read(fd, genos_nonce + len, GENOS_UNITTEST_MAX_NONCE - len)) > 0) {
+            len += n;
+        }
+        close(fd);
+        genos_nonce[len] = '\0';
+        if (len == 0) {
+            fprintf(stderr, "genos_unittest: unable to read the nonce\n");
+            exit(2);
+        }
+
+        // close on exec, so programs run by the student code don't get the report file
+        genos_report = fopen(path, "we");
+        if (genos_report == NULL) {
+            perror("genos_unittest: unable to open the report file");
+            exit(2);
+        }
+    }
+
+    unsetenv(GENOS_UNITTEST_REPORT_ENV);
+}
+
+static void genos_record(const char *record) {
+    fprintf(genos_report, GENOS_UNITTEST_PREFIX "%s:%s\n", genos_nonce, record);
+    // flush so the record isn't lost if the case crashes afterwards
+    fflush(genos_report);
+}
+
+static void genos_fail(const char *file, int line, const char *message) {
+    char genos_record_buf[1024];
+    genos_num_failures++;
+    snprintf(genos_record_buf, sizeof(genos_record_buf), "FAIL:%s:%d:%s", file, line, message);
+    genos_record(genos_record_buf);
+}
+
+#define GENOS_TEST(name)                                                     \
+    static void name(void);                                                  \
+    __attribute__((constructor)) static void genos_register_##name(void) {  \
+        genos_register(#name, name);                                         \
+    }                                                                        \
+    static void name(void)
+
+#define GENOS_ASSERT(expr)                                                   \
+    do {                                                                     \
+        if (!(expr)) {                                                       \
+            genos_fail(__FILE__, __LINE__, "assertion failed: " #expr);      \
+        }                                                                    \
+    } while (0)
+
+#define GENOS_ASSERT_EQ_INT(actual, expected)                                \
+    do {                                                                     \
+        long long genos_a = (long long)(actual);                             \
+        long long genos_e = (long long)(expected);                           \
+        if (genos_a != genos_e) {                                            \
+            char genos_msg[256];                                             \
+            snprintf(genos_msg, sizeof(genos_msg),                           \
+                     "expected " #actual " to be %lld, but found %lld",      \
+                     genos_e, genos_a);                                      \
+            genos_fail(__FILE__, __LINE__, genos_msg);                       \
+        }                                                                    \
+    } while (0)
+
+#define GENOS_ASSERT_EQ_STR(actual, expected)                                \
+    do {                                                                     \
+        const char *genos_a = (actual);                                      \
+        const char *genos_e = (expected);                                    \
+        if (genos_a == NULL || strcmp(genos_a, genos_e) != 0) {              \
+            char genos_msg[512];                                             \
+            snprintf(genos_msg, sizeof(genos_msg),                           \
+                     "expected " #actual " to be \"%s\", but found \"%s\"",  \
+                     genos_e, genos_a == NULL ? "(null)" : genos_a);         \
+            genos_fail(__FILE__, __LINE__, genos_msg);                       \
+        }                                                                    \
+    } while (0)
+
+int main(int argc, char **argv) {
+    if (argc != 2 && argc != 3) {
+        fprintf(stderr, "usage: %s <test case> [<nonce fd>]\n", argv[0]);
+        return 2;
+    }
+
+    genos_open_report(argc == 3 ? argv[2] : NULL);
+
+    for (int i = 0; i < genos_num_cases; i++) {
+        if (strcmp(genos_cases[i].name, argv[1]) == 0) {
+            genos_cases[i].fn();
+            genos_record(genos_num_failures == 0 ? "PASS" : "DONE");
+            return 0;
+        }
+    }
+
+    char genos_record_buf[512];
+    snprintf(genos_record_buf, sizeof(genos_record_buf), "UNKNOWN:%s", argv[1]);
+    genos_record(genos_record_buf);
+    return 2;
+}
+
+#endif
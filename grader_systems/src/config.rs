@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     fs,
     path::{Path, PathBuf},
 };
@@ -8,19 +9,42 @@ use genos::{
     gs::TestDescription,
     points::{PointQuantity, Points},
     stage::{compare_files::ComparesConfig, import_files::ImportConfig},
-    tid::TestId,
+    tid::{TestId, PARAM_ID_STRIDE},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use argh::FromArgs;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{Schema, SchemaObject},
+    JsonSchema,
+};
 use serde::{de, Deserialize, Deserializer};
+use serde_yaml::{Mapping, Value};
 use thiserror::Error;
 use tokio::{fs::File, io::AsyncReadExt};
 
-use crate::stage::{compile::CompileConfig, run::RunConfig};
+use crate::{
+    params::Params,
+    schema::TEST_SCHEMA_NAME,
+    stage::{
+        compile::CompileConfig,
+        interact::InteractConfig,
+        run::RunConfig,
+        script::ScriptConfig,
+        service::{ServiceAddress, ServiceConfig},
+        submission::SubmissionConfig,
+        symbols::SymbolsConfig,
+        unit_test::UnitTestConfig,
+    },
+    vars::{self, Vars},
+};
 
 pub const TEST_CONFIG_NAME: &'static str = "config.yaml";
 
+// the yaml tag which makes a value in a test config replace the default instead of merging with it
+const OVERRIDE_TAG: &str = "Override";
+
 #[async_trait]
 pub trait FromConfigFile {
     type Config;
@@ -37,16 +61,28 @@ pub struct Config {
     pub tests: Vec<TestConfig>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub enum TestType {
     Diff,
+    UnitTest,
+    Script,
+    Interactive,
+    Service,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 pub struct HwConfig {
     pub class: String,
     pub name: String,
     pub groups: Vec<TestGroup>,
+    /// checked before any test is run, see SubmissionConfig
+    pub submission: Option<SubmissionConfig>,
+    /// merged under every test config, see TestDefaults
+    #[serde(default)]
+    pub defaults: TestDefaults,
+    /// substituted into the templated strings of every test config, see Vars
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
 }
 
 #[async_trait]
@@ -61,21 +97,108 @@ impl FromConfigFile for HwConfig {
     }
 }
 
-#[derive(Deserialize)]
+/// The parts of the test configs which are the same for every test of the hw. Each test config is
+/// merged over the defaults before it's validated, so a test config only needs what's different
+/// about the test. Maps are merged key by key and lists are appended to. Any other value from the
+/// test config replaces the default, and a value tagged with !Override replaces the default
+/// instead of being merged with it.
+/// Ex:
+/// defaults:
+///     compile:
+///         make_args: [all]
+///     run:
+///         executable: bin/prog
+///         timeout_sec: 10
+///
+/// With the defaults above, the test config
+/// compile:
+///     make_args: !Override [debug]
+/// run:
+///     args: [--verbose]
+/// is run with `make debug` and `bin/prog --verbose`, with a timeout of 10 seconds.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct TestDefaults(Value);
+
+// the defaults are part of a test config, so they're described by the test config schema
+impl JsonSchema for TestDefaults {
+    fn schema_name() -> String {
+        "TestDefaults".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject::new_ref(TEST_SCHEMA_NAME.to_string()).into()
+    }
+}
+
+impl TestDefaults {
+    pub fn apply(&self, config: Value) -> Value {
+        merge(self.0.clone(), config)
+    }
+}
+
+// merges the value from the test config over the default value. Any !Override tags are removed
+// from the merged value, since they aren't part of the test config itself.
+fn merge(default: Value, value: Value) -> Value {
+    match (default, value) {
+        (_, Value::Tagged(tagged)) if tagged.tag == OVERRIDE_TAG => {
+            merge(Value::Null, tagged.value)
+        }
+        (default, Value::Mapping(mapping)) => {
+            let mut merged = match default {
+                Value::Mapping(default) => default,
+                _ => Mapping::new(),
+            };
+            for (key, value) in mapping {
+                let default = merged.remove(&key).unwrap_or(Value::Null);
+                merged.insert(key, merge(default, value));
+            }
+            Value::Mapping(merged)
+        }
+        (default, Value::Sequence(sequence)) => {
+            let mut merged = match default {
+                Value::Sequence(default) => default,
+                _ => Vec::new(),
+            };
+            merged.extend(sequence.into_iter().map(|value| merge(Value::Null, value)));
+            Value::Sequence(merged)
+        }
+        (_, Value::Tagged(mut tagged)) => {
+            tagged.value = merge(Value::Null, tagged.value);
+            Value::Tagged(tagged)
+        }
+        (_, value) => value,
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
 pub struct TestGroup {
     pub name: String,
     pub tests: Vec<TestId>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(remote = "Self")]
 pub struct TestConfig {
     pub description: TestDescription,
     pub test_type: TestType,
     pub compile: CompileConfig,
-    pub run: RunConfig,
+    pub run: Option<RunConfig>,
     pub compare_files: Option<ComparesConfig>,
     pub import_files: Option<ImportConfig>,
+    pub unit_test: Option<UnitTestConfig>,
+    pub script: Option<ScriptConfig>,
+    pub interact: Option<InteractConfig>,
+    pub service: Option<ServiceConfig>,
+    /// checked after the compile stage of any test type
+    pub symbols: Option<SymbolsConfig>,
+    /// run the submission without network access and with everything but the ws read only, see
+    /// IsolatedExecutor. The grader can't reach an isolated server over tcp, so service tests
+    /// need to use a unix socket.
+    #[serde(default)]
+    pub isolate: bool,
+    /// run the test once for each row of a table instead of once, see Params
+    pub params: Option<Params>,
 }
 
 #[async_trait]
@@ -83,29 +206,102 @@ impl FromConfigFile for TestConfig {
     type Config = TestConfig;
 
     async fn from_file(path: &Path) -> Result<Self::Config> {
+        Self::from_file_with_defaults(path, &TestDefaults::default()).await
+    }
+}
+
+impl TestConfig {
+    /// Loads the test config merged over the defaults. It's validated after the merge, since a test
+    /// config can rely on the defaults for any part of it.
+    pub async fn from_file_with_defaults(path: &Path, defaults: &TestDefaults) -> Result<Self> {
         let mut file = File::open(path).await?;
         let mut contents = String::new();
         file.read_to_string(&mut contents).await?;
-        Ok(serde_yaml::from_str(&contents)?)
+
+        let config = defaults.apply(serde_yaml::from_str(&contents)?);
+        serde_yaml::from_value(config)
+            .with_context(|| format!("Invalid test config {}", path.display()))
     }
-}
 
-impl TestConfig {
-    fn validate(&self) -> Result<(), TestConfigValidationError> {
+    /// Checks that the templated strings of the config only use known vars. The ws is only known
+    /// when the test runs, so it's checked with a placeholder.
+    pub fn check_vars(&self, vars: &Vars) -> Result<(), vars::Error> {
+        let vars = vars.with_ws(Path::new(""));
+        self.compile.make_args(&vars)?;
+        if let Some(run) = &self.run {
+            run.expand(&vars)?;
+        }
+        if let Some(service) = &self.service {
+            service.server_args(&vars, 0)?;
+        }
+        if let Some(unit_test) = &self.unit_test {
+            unit_test.expand(&vars)?;
+        }
+        if let Some(script) = &self.script {
+            script.expand(&vars)?;
+        }
+        if let Some(interact) = &self.interact {
+            interact.expand(&vars)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), TestConfigValidationError> {
         let mut configured_points = Vec::new();
 
         // GRADERS: Other fields in the test case config will require adding code here to do
         // validation, add a lines here to add the points in the config to he configured_points
         // vector.
 
-        if let Some(rc_config) = &self.run.return_code {
+        if let Some(rc_config) = self.run.as_ref().and_then(|run| run.return_code.as_ref()) {
             configured_points.push(rc_config.points);
         }
 
+        if let Some(signal_config) = self.run.as_ref().and_then(|run| run.signal.as_ref()) {
+            configured_points.push(signal_config.points);
+        }
+
         if let Some(compare_config) = &self.compare_files {
             configured_points.extend(compare_config.compares.iter().map(|compare| compare.points));
         }
 
+        if let Some(unit_test_config) = &self.unit_test {
+            configured_points.extend(unit_test_config.cases.iter().map(|case| case.points));
+        }
+
+        if let Some(interact_config) = &self.interact {
+            configured_points.push(interact_config.points);
+        }
+
+        if let Some(service_config) = &self.service {
+            configured_points.extend(
+                service_config
+                    .exchanges
+                    .iter()
+                    .map(|exchange| exchange.points),
+            );
+        }
+
+        if let Some(symbols_config) = &self.symbols {
+            configured_points.extend(symbols_config.rules().map(|rule| rule.points));
+        }
+
+        // compile warnings are deductions taken on top of the configured points, so they don't
+        // count towards the total.
+
+        if let Some(run) = &self.run {
+            run.check_pipeline()?;
+        }
+
+        let tcp_service = self
+            .service
+            .as_ref()
+            .is_some_and(|service| matches!(service.address, ServiceAddress::Tcp(_)));
+        if self.isolate && tcp_service {
+            return Err(TestConfigValidationError::IsolatedTcpService);
+        }
+
         // GRADERS: Add to configured_points above when adding a new type to the config which has
         // points assigned to it.
 
@@ -116,7 +312,9 @@ impl TestConfig {
             return Err(TestConfigValidationError::MixedPointQuantities);
         }
 
-        if all_partial_points {
+        // scripts report the points lost at runtime, so they own whatever isn't configured here and
+        // the total can't be checked.
+        if all_partial_points && !matches!(self.test_type, TestType::Script) {
             let total = configured_points
                 .iter()
                 .map(|p| match p {
@@ -147,6 +345,15 @@ pub enum TestConfigValidationError {
         configured_total_points: Points,
         calculated_total_points: Points,
     },
+
+    #[error("The program can't be run with {0} when it has pipe_in or pipe_out.")]
+    PipelineWith(&'static str),
+
+    #[error("An isolated server has no network access, so it needs to use a unix socket.")]
+    IsolatedTcpService,
+
+    #[error("Test id {0} collides with the ids of parametrized tests, test ids need to be less than {max}.", max = PARAM_ID_STRIDE)]
+    TestIdInParamRange(TestId),
 }
 
 // Add a custom deserializer to verify that the test config is valid.
@@ -156,6 +363,13 @@ impl<'de> Deserialize<'de> for TestConfig {
         D: Deserializer<'de>,
     {
         let this = Self::deserialize(deserializer)?;
+        // the generated ids of parametrized tests don't go through here, so any id in their range
+        // was declared by the config
+        if this.description.test_id.is_param() {
+            return Err(de::Error::custom(
+                TestConfigValidationError::TestIdInParamRange(this.description.test_id),
+            ));
+        }
         // validate that for each portion of the test case which has points associated with it, the
         // points are either all Partial(N) or all FullPoints. Also validate that if all points are
         // Partial, that the contained point value add up to the total_points in the test
@@ -167,23 +381,72 @@ impl<'de> Deserialize<'de> for TestConfig {
 }
 
 #[derive(FromArgs)]
-/// Run the autograder for the systems course
-pub struct Cli {
+/// Run the autograder for the systems course, or one of the tools for writing its configs
+pub struct Args {
     /// path to the hw config
     #[argh(option, short = 'h', from_str_fn(make_absolute))]
-    pub config: PathBuf,
+    pub config: Option<PathBuf>,
 
     /// path to the submission to run
     #[argh(option, short = 's', from_str_fn(make_absolute))]
-    pub submission: PathBuf,
+    pub submission: Option<PathBuf>,
 
     /// test grouping to run, must be a named group in the hw config
     #[argh(option, short = 'g')]
     pub group: Option<String>,
+
+    #[argh(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Args {
+    /// The config of a run of the grader, which is what's done without a subcommand.
+    pub fn run_config(self) -> Result<Cli, String> {
+        match (self.config, self.submission) {
+            (Some(config), Some(submission)) => Ok(Cli {
+                config,
+                submission,
+                group: self.group,
+            }),
+            _ => Err("Running the grader needs both --config and --submission".to_string()),
+        }
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    Validate(ValidateCli),
+    Schema(SchemaCli),
+}
+
+/// The config of a run of the grader given through the cli
+pub struct Cli {
+    pub config: PathBuf,
+    pub submission: PathBuf,
+    pub group: Option<String>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "validate")]
+/// Check the hw config and all of its test configs without running anything
+pub struct ValidateCli {
+    /// path to the hw config
+    #[argh(option, short = 'h', from_str_fn(make_absolute))]
+    pub config: PathBuf,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "schema")]
+/// Write the JSON schemas of the hw and test configs, and print the VS Code settings which use them
+pub struct SchemaCli {
+    /// dir to write the schemas to
+    #[argh(option, short = 'o')]
+    pub out: PathBuf,
 }
 
 fn make_absolute(path_arg: &str) -> Result<PathBuf, String> {
-    let path = fs::canonicalize(&path_arg)
+    let path = fs::canonicalize(path_arg)
         .map_err(|e| format!("error creating absolute path from {path_arg}: {e}"))?;
 
     if !path.exists() {
@@ -218,6 +481,130 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn deserialize_hw_config_with_submission() {
+        let config = serde_yaml::from_str::<HwConfig>(
+            r#"
+            class: 2022-winter
+            name: hw1
+            groups: []
+            defaults:
+                run:
+                    executable: bin/prog
+            submission:
+                description:
+                    name: Submission Structure
+                    description: Checks that all the files needed for grading were submitted
+                    test_id: 0
+                    total_points: 0
+                    visibility: Visible
+                required: [Makefile, main.c]
+                forbidden: ["*.o", a.out]
+                reject_binaries: true
+                max_file_size_kb: 512
+                text_files: ["*.c", "*.h"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.submission.unwrap().required, ["Makefile", "main.c"]);
+    }
+
+    fn with_defaults(defaults: &str, config: &str) -> serde_yaml::Result<TestConfig> {
+        let defaults: TestDefaults = serde_yaml::from_str(defaults).unwrap();
+        serde_yaml::from_value(defaults.apply(serde_yaml::from_str(config).unwrap()))
+    }
+
+    const DEFAULTS: &str = r#"
+            description:
+                visibility: Hidden
+            test_type: Diff
+            compile:
+                make_args: [all]
+                warnings:
+                    points: !Partial 0.5
+                    deduct_per: Warning
+            run:
+                args: [--quiet]
+                executable: bin/prog
+                timeout_sec: 10
+                stdout: stdout
+                return_code:
+                    expected: 0
+                    points: !Partial 1
+            "#;
+
+    #[test]
+    fn test_config_merged_over_defaults() {
+        let config = with_defaults(
+            DEFAULTS,
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 3
+            compile:
+                make_args: !Override [debug]
+            run:
+                args: [--verbose]
+                timeout_sec: 30
+            compare_files:
+                compares:
+                    - expected: [expected_stdout]
+                      student_file: stdout
+                      compare_type: Diff
+                      points: !Partial 2
+                      show_output: true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.compile.make_args.unwrap(), ["debug"]);
+        assert!(config.compile.warnings.is_some());
+
+        let run = config.run.unwrap();
+        assert_eq!(run.args, ["--quiet", "--verbose"]);
+        assert_eq!(run.executable, "bin/prog");
+        assert_eq!(run.timeout_sec, Some(30));
+        assert_eq!(run.stdout.unwrap(), "stdout");
+    }
+
+    #[test]
+    fn test_config_validated_after_merge() {
+        // the return code points from the defaults count towards the total
+        with_defaults(
+            DEFAULTS,
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 2
+            compare_files:
+                compares:
+                    - expected: [expected_stdout]
+                      student_file: stdout
+                      compare_type: Diff
+                      points: !Partial 2
+                      show_output: true
+            "#,
+        )
+        .unwrap_err();
+
+        with_defaults(
+            DEFAULTS,
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 1
+            "#,
+        )
+        .unwrap();
+    }
+
     #[test]
     fn deserialize_testcase_config() {
         serde_yaml::from_str::<TestConfig>(
@@ -234,6 +621,12 @@ mod tests {
             
             compile:
                 make_args: [arg1, arg 2, arg3]
+                warnings:
+                    points: !Partial 0.25
+                    flags:
+                        -Wreturn-type: !FullPoints
+                    deduct_per: Class
+                    max_deduction: 1
                 
             run:
                 args: [arg1]
@@ -355,4 +748,332 @@ mod tests {
         )
         .unwrap_err();
     }
+
+    #[test]
+    fn deserialize_test_config_pipeline_with_pty() {
+        let err = serde_yaml::from_str::<TestConfig>(
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 4.0
+                visibility: Hidden
+                tags: [tag 1, tag2]
+
+            test_type: Diff
+
+            compile:
+                make_args: [arg1, arg 2, arg3]
+
+            run:
+                args: []
+                executable: exec/bin
+                pty: {}
+                pipe_out: [[sort]]
+                return_code:
+                    expected: 0
+                    points: !Partial 4
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("pty"), "{}", err);
+    }
+
+    #[test]
+    fn deserialize_unit_test_config() {
+        serde_yaml::from_str::<TestConfig>(
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 2
+                visibility: Hidden
+
+            test_type: UnitTest
+
+            compile:
+                mode: !Compiler
+                    sources: [list.c, list_test.c]
+                    output: list_test
+
+            unit_test:
+                driver: list_test.c
+                executable: list_test
+                timeout_sec: 5
+                cases:
+                    -
+                        name: push
+                        points: !Partial 1.5
+                    -
+                        name: pop
+                        points: !Partial 0.5
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn deserialize_script_config() {
+        serde_yaml::from_str::<TestConfig>(
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 5
+                visibility: Hidden
+
+            test_type: Script
+
+            compile:
+                make_args: [server]
+
+            script:
+                path: grade.py
+                args: [--port, "8080"]
+                timeout_sec: 30
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn deserialize_interactive_config() {
+        serde_yaml::from_str::<TestConfig>(
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 2
+                visibility: Hidden
+
+            test_type: Interactive
+
+            compile:
+                make_args: [shell]
+
+            interact:
+                executable: shell
+                points: !Partial 2
+                steps:
+                    - !Expect { text: "$ " }
+                    - !Send echo hi
+                    - !Expect { regex: "^hi$", timeout_sec: 1 }
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn deserialize_service_config() {
+        serde_yaml::from_str::<TestConfig>(
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 4
+                visibility: Hidden
+
+            test_type: Service
+            isolate: true
+
+            compile:
+                make_args: [server]
+
+            service:
+                executable: server
+                args: [server.sock]
+                address: !Unix server.sock
+                exchanges:
+                    - name: echo
+                      points: !Partial 1
+                      request: !Line { send: [hello], expect: [hello] }
+                    - name: raw
+                      points: !Partial 1
+                      timeout_sec: 1
+                      request: !Raw { send: "ping", expect: "pong" }
+                    - name: index
+                      points: !Partial 2
+                      request:
+                          !Http
+                          method: POST
+                          path: /submit
+                          headers: { Content-Type: text/plain }
+                          body: hello
+                          expect_status: 201
+            "#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn deserialize_isolated_tcp_service_config() {
+        let err = serde_yaml::from_str::<TestConfig>(
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 1
+                visibility: Hidden
+
+            test_type: Service
+            isolate: true
+
+            compile:
+                make_args: [server]
+
+            service:
+                executable: server
+                args: ["${PORT}"]
+                address: !Tcp 0
+                exchanges:
+                    - name: echo
+                      points: !Partial 1
+                      request: !Line { send: [hello], expect: [hello] }
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unix socket"), "{}", err);
+    }
+
+    #[test]
+    fn deserialize_test_id_in_param_range() {
+        // test 3001 would collide with the first row of a parametrized test 3
+        let err = serde_yaml::from_str::<TestConfig>(
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 3001
+                total_points: 1
+                visibility: Hidden
+
+            test_type: Diff
+
+            compile:
+                make_args: [main]
+
+            run:
+                executable: main
+                args: []
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("parametrized"), "{}", err);
+    }
+
+    #[test]
+    fn check_vars_rejects_unknown_vars() {
+        let config = |make_arg: &str, script_arg: &str, interact_arg: &str| {
+            serde_yaml::from_str::<TestConfig>(&format!(
+                r#"
+                description:
+                    name: test 1
+                    description: test 1
+                    test_id: 1
+                    total_points: 2
+                    visibility: Hidden
+
+                test_type: Interactive
+
+                compile:
+                    make_args: ["{make_arg}"]
+
+                script:
+                    path: grade.sh
+                    args: ["{script_arg}"]
+
+                interact:
+                    executable: shell
+                    args: ["{interact_arg}"]
+                    points: !Partial 2
+                    steps:
+                        - !Expect {{ text: "$ " }}
+                "#
+            ))
+            .unwrap()
+        };
+        let vars = Vars::new().var("DATA", "/data");
+
+        config("${DATA}", "${WS}/out", "${DATA}")
+            .check_vars(&vars)
+            .unwrap();
+        for config in [
+            config("${DTA}", "${WS}/out", "${DATA}"),
+            config("${DATA}", "${OUT}/out", "${DATA}"),
+            config("${DATA}", "${WS}/out", "${DTA}"),
+        ] {
+            config.check_vars(&vars).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn deserialize_symbols_config() {
+        let config = serde_yaml::from_str::<TestConfig>(
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 3
+                visibility: Hidden
+
+            test_type: Interactive
+
+            compile:
+                make_args: [sort]
+
+            symbols:
+                files: [sort]
+                forbidden:
+                    - symbols: [qsort]
+                      points: !Partial 1
+                required:
+                    - symbols: [merge_sort]
+                      points: !Partial 1
+
+            interact:
+                executable: sort
+                points: !Partial 1
+                steps:
+                    - !Expect { text: "> " }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.symbols.unwrap().rules().count(), 2);
+    }
+
+    #[test]
+    fn deserialize_unit_test_config_points_dont_add_up_to_total() {
+        serde_yaml::from_str::<TestConfig>(
+            r#"
+            description:
+                name: test 1
+                description: test 1
+                test_id: 1
+                total_points: 2
+                visibility: Hidden
+
+            test_type: UnitTest
+
+            compile:
+                make_args: [list_test]
+
+            unit_test:
+                driver: list_test.c
+                executable: list_test
+                cases:
+                    -
+                        name: push
+                        points: !Partial 1
+            "#,
+        )
+        .unwrap_err();
+    }
 }
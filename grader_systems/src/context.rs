@@ -2,69 +2,251 @@ use std::{path::Path, sync::Arc};
 
 use crate::{
     config::{Cli, HwConfig, TestConfig, TestType},
-    finder::{Finder, TestConfigFinder, TestFileFinder},
-    stage::{compile::Compile, run::Run},
+    finder::{Finder, SystemFileFinder, TestConfigFinder, TestFileFinder},
+    stage::{
+        compile::{cache::BuildCache, Compile},
+        interact::Interact,
+        run::Run,
+        script::{Script, SUBMISSION_ENV, TEST_ID_ENV},
+        service::Service,
+        submission::ValidateSubmission,
+        symbols::Symbols,
+        unit_test::{UnitTest, UNITTEST_HEADER},
+    },
+    vars::Vars,
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context as _, Result};
+use async_trait::async_trait;
 use genos::{
     fs::ResourceLocator,
-    process::ShellExecutor,
+    genos::Genos,
+    gs::{Description, TestDescription, Visibility},
+    points::Points,
+    process::{self, Command, IsolatedExecutor, ProcessExecutor, ShellExecutor},
     stage::{
         compare_files::{ComparatorCreatorImpl, CompareFiles},
-        import_files::ImportFiles,
+        import_files::{ImportConfig, ImportFiles},
+        prepare_workspace::PrepareWorkspace,
     },
-    test::GenosTest,
+    test::{GenosTest, Test, TestResult},
+    tid::TestId,
+    Executor,
 };
 
+/// The executor the submission is run on, which is isolated if the test config asks for it. That's
+/// anything which runs or reads what the student wrote: its build, the symbols check and the
+/// script testing it as well as the program. Instructor commands like diff always run on the
+/// ShellExecutor.
+#[derive(Clone)]
+enum SubmissionExecutor {
+    Shell(ShellExecutor),
+    Isolated(IsolatedExecutor<ShellExecutor>),
+}
+
+impl SubmissionExecutor {
+    fn new(config: &TestConfig) -> Self {
+        match config.isolate {
+            true => Self::Isolated(IsolatedExecutor::new(ShellExecutor)),
+            false => Self::Shell(ShellExecutor),
+        }
+    }
+}
+
+#[async_trait]
+impl ProcessExecutor for SubmissionExecutor {
+    async fn run(&self, cmd: &Command) -> Result<process::Output> {
+        match self {
+            Self::Shell(executor) => executor.run(cmd).await,
+            Self::Isolated(executor) => executor.run(cmd).await,
+        }
+    }
+}
+
+/// A GenosTest along with the description from its config, which genos needs to report its
+/// results.
+pub struct DescribedTest {
+    description: TestDescription,
+    test: GenosTest,
+}
+
+impl DescribedTest {
+    pub fn new(description: TestDescription, test: GenosTest) -> Self {
+        Self { description, test }
+    }
+}
+
+#[async_trait]
+impl Executor for DescribedTest {
+    type Output = TestResult;
+
+    async fn run(&self, ws: &Path) -> Result<TestResult> {
+        self.test.run(ws).await
+    }
+}
+
+impl Test for DescribedTest {
+    fn points(&self) -> Points {
+        self.test.points()
+    }
+}
+
+impl Description for DescribedTest {
+    fn name(&self) -> String {
+        self.description.name()
+    }
+
+    fn description(&self) -> String {
+        self.description.description()
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.description.visibility()
+    }
+
+    fn id(&self) -> TestId {
+        Description::id(&self.description)
+    }
+
+    fn tags(&self) -> Vec<String> {
+        self.description.tags.clone().unwrap_or_default()
+    }
+}
+
 /// Holds all the context required to execute a run of the autograder
 pub struct Context {
     cli_config: Arc<Cli>,
     hw_config: Arc<HwConfig>,
     finder: Arc<Finder>,
+    // shared by the compile stage of every test so each unique build only runs once
+    build_cache: Arc<BuildCache>,
 }
 
 impl Context {
-    pub async fn new(cli_config: Cli, hw_config: HwConfig) -> Self {
-        let finder = Finder::from_hw_config_path(&cli_config.config).unwrap();
-        Self {
+    pub async fn new(cli_config: Cli, hw_config: HwConfig) -> Result<Self> {
+        let finder = Finder::from_hw_config_path(&cli_config.config)?;
+        Ok(Self {
             cli_config: Arc::new(cli_config),
             hw_config: Arc::new(hw_config),
             finder: Arc::new(finder),
+            build_cache: Arc::new(BuildCache::new()?),
+        })
+    }
+
+    pub async fn run_grader(&self) -> Result<Vec<TestResult>> {
+        let test_configs = self
+            .finder
+            .load_test_configs(&self.hw_config.defaults)
+            .await?;
+
+        let tests = test_configs
+            .iter()
+            .map(|config| {
+                let test = self.create_test(config)?;
+                Ok(DescribedTest::new(config.description.clone(), test))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Genos::builder()
+            .setups(self.create_setup_tests())
+            .tests(tests)
+            .build()
+            .run()
+            .await
+    }
+
+    // Setup tests are run before any of the other tests, and nothing else is run if one of them
+    // fails.
+    // 1. validate the structure of the submission (if configured)
+    fn create_setup_tests(&self) -> Vec<DescribedTest> {
+        let mut tests = Vec::new();
+
+        if let Some(submission) = &self.hw_config.submission {
+            let test = GenosTest::new(submission.description.total_points).stage(
+                ValidateSubmission::new(&self.cli_config.submission, submission.clone()),
+            );
+            tests.push(DescribedTest::new(submission.description.clone(), test));
         }
+
+        tests
     }
 
-    pub async fn run_grader(&self) -> Result<()> {
-        let _test_configs = self.finder.load_test_configs().await?;
+    // The files of every test's ws are layered in the order submission, basefiles and then the
+    // imports of the test, so that instructor files replace any student files of the same name.
+    fn prepare_workspace(&self) -> PrepareWorkspace {
+        let mut prepare = PrepareWorkspace::new().layer("submission", &self.cli_config.submission);
+        if let Some(basefiles) = self.finder.basefiles_dir() {
+            prepare.add_layer("basefiles", basefiles);
+        }
+        prepare
+    }
 
-        Ok(())
+    // the vars of the test, see Vars
+    fn vars(&self, config: &TestConfig) -> Vars {
+        Vars::for_test(
+            &self.hw_config,
+            &self.cli_config.config,
+            config.description.test_id,
+            &self.cli_config.submission,
+        )
     }
 
     fn create_test(&self, config: &TestConfig) -> Result<GenosTest> {
+        let vars = self.vars(config);
+        config.check_vars(&vars).with_context(|| {
+            format!(
+                "Invalid test config for test {}",
+                config.description.test_id
+            )
+        })?;
+
         match &config.test_type {
-            TestType::Diff => self.make_diff_test(config),
+            TestType::Diff => self.make_diff_test(config, vars),
+            TestType::UnitTest => self.make_unit_test(config, vars),
+            TestType::Script => self.make_script_test(config, vars),
+            TestType::Interactive => self.make_interactive_test(config, vars),
+            TestType::Service => self.make_service_test(config, vars),
         }
     }
 
     // Diff test is used to compare the output produced by the submission to expected output found
     // in test resuorces. It has the following stage order
-    // 1. import files (if required)
-    // 2. compile assignment
-    // 3. run assignment
-    // 4. compare output with expected
-    // 5. run assignment using valgrind to detect memmory leaks (if configured)
-    // 6. run assignment with memory limit to detect excess memory usage (if configured)
-    fn make_diff_test(&self, config: &TestConfig) -> Result<GenosTest> {
+    // 1. copy the submission and then the basefiles into the ws
+    // 2. import files (if required)
+    // 3. compile assignment (shared with every other test with the same build inputs)
+    // 4. check the symbols of the compiled files (if configured)
+    // 5. run assignment
+    // 6. compare output with expected
+    // 7. run assignment using valgrind to detect memmory leaks (if configured)
+    // 8. run assignment with memory limit to detect excess memory usage (if configured)
+    fn make_diff_test(&self, config: &TestConfig, vars: Vars) -> Result<GenosTest> {
         let mut test = GenosTest::new(config.description.total_points);
         let test_file_finder = TestFileFinder::new(config.description.test_id, self.finder.clone());
 
+        test.add_stage(self.prepare_workspace());
+
         if let Some(import_files) = &config.import_files {
             test.add_stage(ImportFiles::new(import_files, &test_file_finder)?)
         }
 
-        test.add_stage(Compile::new(&config.compile, ShellExecutor));
+        test.add_stage(
+            Compile::new(&config.compile, SubmissionExecutor::new(config))
+                .with_cache(self.build_cache.clone())
+                .vars(vars.clone()),
+        );
+
+        if let Some(symbols) = &config.symbols {
+            test.add_stage(Symbols::new(
+                SubmissionExecutor::new(config),
+                symbols.clone(),
+            ));
+        }
 
-        test.add_stage(Run::new(ShellExecutor, config.run.clone()));
+        let run = config
+            .run
+            .as_ref()
+            .ok_or(anyhow!("Expected diff test to have a run config"))?;
+        test.add_stage(Run::new(SubmissionExecutor::new(config), run.clone()).vars(vars));
 
         {
             let compare_files = config
@@ -77,10 +259,12 @@ impl Context {
             // test resource directory for expected output.
             // The current ws is defined by genos at runtime, so we can't know it when creating the
             // test so instead we give a factory that takes in the ws and produces the appropriate
-            // finder for that test case.
-            let test_file_finder = test_file_finder.clone();
+            // finder for that test case. Such a test would add the ws as the last layer, so that
+            // the student can't replace any of the instructor's expected output:
+            //     layers.clone().source("ws", DirFinder::new(ws.to_path_buf()))
+            let layers = self.finder.test_layers(config.description.test_id)?;
             let locator_creator = move |_path: &Path| {
-                let finder: Box<dyn ResourceLocator> = Box::new(test_file_finder.clone());
+                let finder: Box<dyn ResourceLocator> = Box::new(layers.clone());
                 finder
             };
 
@@ -93,4 +277,265 @@ impl Context {
 
         Ok(test)
     }
+
+    // Unit test compiles an instructor test driver with the submission and runs each of the
+    // driver's test cases in its own process. It has the following stage order
+    // 1. copy the submission and then the basefiles into the ws
+    // 2. import files (if required)
+    // 3. import the test driver and the unittest header
+    // 4. compile the driver with the submission
+    // 5. check the symbols of the compiled files (if configured)
+    // 6. run each test case
+    fn make_unit_test(&self, config: &TestConfig, vars: Vars) -> Result<GenosTest> {
+        let mut test = GenosTest::new(config.description.total_points);
+        let test_file_finder = TestFileFinder::new(config.description.test_id, self.finder.clone());
+
+        test.add_stage(self.prepare_workspace());
+
+        let unit_test = config
+            .unit_test
+            .as_ref()
+            .ok_or(anyhow!("Expected unit test to have a unit_test config"))?;
+
+        if let Some(import_files) = &config.import_files {
+            test.add_stage(ImportFiles::new(import_files, &test_file_finder)?)
+        }
+
+        test.add_stage(ImportFiles::new(
+            &ImportConfig::new([vars.expand(&unit_test.driver)?]),
+            &test_file_finder,
+        )?);
+        test.add_stage(ImportFiles::new(
+            &ImportConfig::new([UNITTEST_HEADER]),
+            &SystemFileFinder::new(self.finder.clone()),
+        )?);
+
+        test.add_stage(
+            Compile::new(&config.compile, SubmissionExecutor::new(config))
+                .with_cache(self.build_cache.clone())
+                .vars(vars.clone()),
+        );
+
+        if let Some(symbols) = &config.symbols {
+            test.add_stage(Symbols::new(
+                SubmissionExecutor::new(config),
+                symbols.clone(),
+            ));
+        }
+
+        test.add_stage(
+            UnitTest::new(SubmissionExecutor::new(config), unit_test.clone()).vars(vars),
+        );
+
+        Ok(test)
+    }
+
+    // Script test hands grading over to an instructor provided script found in the test resources.
+    // It has the following stage order
+    // 1. copy the submission and then the basefiles into the ws
+    // 2. import files (if required)
+    // 3. compile assignment (shared with every other test with the same build inputs)
+    // 4. check the symbols of the compiled files (if configured)
+    // 5. run the script, which reports the points lost and feedback
+    fn make_script_test(&self, config: &TestConfig, vars: Vars) -> Result<GenosTest> {
+        let mut test = GenosTest::new(config.description.total_points);
+        let test_file_finder = TestFileFinder::new(config.description.test_id, self.finder.clone());
+
+        test.add_stage(self.prepare_workspace());
+
+        let script = config
+            .script
+            .as_ref()
+            .ok_or(anyhow!("Expected script test to have a script config"))?;
+
+        if let Some(import_files) = &config.import_files {
+            test.add_stage(ImportFiles::new(import_files, &test_file_finder)?)
+        }
+
+        test.add_stage(
+            Compile::new(&config.compile, SubmissionExecutor::new(config))
+                .with_cache(self.build_cache.clone())
+                .vars(vars.clone()),
+        );
+
+        if let Some(symbols) = &config.symbols {
+            test.add_stage(Symbols::new(
+                SubmissionExecutor::new(config),
+                symbols.clone(),
+            ));
+        }
+
+        test.add_stage(
+            Script::new(
+                SubmissionExecutor::new(config),
+                script.clone(),
+                &test_file_finder,
+            )?
+            .env(SUBMISSION_ENV, self.cli_config.submission.to_string_lossy())
+            .env(TEST_ID_ENV, config.description.test_id.to_string())
+            .vars(vars),
+        );
+
+        Ok(test)
+    }
+
+    // Interactive test has a scripted conversation with the submission over stdin and stdout. It
+    // has the following stage order
+    // 1. copy the submission and then the basefiles into the ws
+    // 2. import files (if required)
+    // 3. compile assignment (shared with every other test with the same build inputs)
+    // 4. check the symbols of the compiled files (if configured)
+    // 5. run assignment while sending input and checking for the expected output
+    fn make_interactive_test(&self, config: &TestConfig, vars: Vars) -> Result<GenosTest> {
+        let mut test = GenosTest::new(config.description.total_points);
+        let test_file_finder = TestFileFinder::new(config.description.test_id, self.finder.clone());
+
+        test.add_stage(self.prepare_workspace());
+
+        let interact = config.interact.as_ref().ok_or(anyhow!(
+            "Expected interactive test to have an interact config"
+        ))?;
+
+        if let Some(import_files) = &config.import_files {
+            test.add_stage(ImportFiles::new(import_files, &test_file_finder)?)
+        }
+
+        test.add_stage(
+            Compile::new(&config.compile, SubmissionExecutor::new(config))
+                .with_cache(self.build_cache.clone())
+                .vars(vars.clone()),
+        );
+
+        if let Some(symbols) = &config.symbols {
+            test.add_stage(Symbols::new(
+                SubmissionExecutor::new(config),
+                symbols.clone(),
+            ));
+        }
+
+        test.add_stage(Interact::new(SubmissionExecutor::new(config), interact.clone()).vars(vars));
+
+        Ok(test)
+    }
+
+    // Service test starts the submission as a server and tests it with client requests. It has
+    // the following stage order
+    // 1. copy the submission and then the basefiles into the ws
+    // 2. import files (if required)
+    // 3. compile assignment (shared with every other test with the same build inputs)
+    // 4. check the symbols of the compiled files (if configured)
+    // 5. start the server, make each exchange with it and then kill it
+    fn make_service_test(&self, config: &TestConfig, vars: Vars) -> Result<GenosTest> {
+        let mut test = GenosTest::new(config.description.total_points);
+        let test_file_finder = TestFileFinder::new(config.description.test_id, self.finder.clone());
+
+        test.add_stage(self.prepare_workspace());
+
+        let service = config
+            .service
+            .as_ref()
+            .ok_or(anyhow!("Expected service test to have a service config"))?;
+
+        if let Some(import_files) = &config.import_files {
+            test.add_stage(ImportFiles::new(import_files, &test_file_finder)?)
+        }
+
+        test.add_stage(
+            Compile::new(&config.compile, SubmissionExecutor::new(config))
+                .with_cache(self.build_cache.clone())
+                .vars(vars.clone()),
+        );
+
+        if let Some(symbols) = &config.symbols {
+            test.add_stage(Symbols::new(
+                SubmissionExecutor::new(config),
+                symbols.clone(),
+            ));
+        }
+
+        test.add_stage(Service::new(SubmissionExecutor::new(config), service.clone()).vars(vars));
+
+        Ok(test)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use genos::{
+        output::Contains,
+        test_util::{MockDir, MockFile},
+    };
+
+    use super::*;
+    use crate::config::FromConfigFile;
+
+    const HW_CONFIG: &str = r#"
+class: cse29
+name: hw1
+groups: []
+submission:
+    description:
+        name: Submission Structure
+        description: Checks that all the files needed for grading were submitted
+        test_id: 0
+        total_points: 0
+        visibility: Visible
+    required: [main.c]
+"#;
+
+    const TEST_CONFIG: &str = r#"
+description:
+    name: test 1
+    description: test 1
+    test_id: 1
+    total_points: 1
+    visibility: Visible
+test_type: Script
+compile:
+    make_args: []
+script:
+    path: grade.sh
+"#;
+
+    #[tokio::test]
+    async fn tests_run_only_after_setup_passes() {
+        let data = MockDir::new()
+            .dir(
+                "system",
+                MockDir::new().file(MockFile::new("genos_unittest.h", "")),
+            )
+            .dir(
+                "2022-winter",
+                MockDir::new().dir(
+                    "hw1",
+                    MockDir::new()
+                        .file(MockFile::new("hw.yaml", HW_CONFIG))
+                        .dir(
+                            "test_1",
+                            MockDir::new()
+                                .file(MockFile::new("config.yaml", TEST_CONFIG))
+                                .file(MockFile::new("grade.sh", "echo {}")),
+                        ),
+                ),
+            );
+        let submission = MockDir::new().file(MockFile::new("other.c", ""));
+
+        let cli_config = Cli {
+            config: data.path_from_root("2022-winter/hw1/hw.yaml"),
+            submission: submission.root.path().to_path_buf(),
+            group: None,
+        };
+        let hw_config = HwConfig::from_file(&cli_config.config).await.unwrap();
+        let context = Context::new(cli_config, hw_config).await.unwrap();
+
+        let results = context.run_grader().await.unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].output.contains("main.c"));
+
+        // once the submission passes the setup, the tests are run after it
+        std::fs::write(submission.path_from_root("main.c"), "").unwrap();
+        let results = context.run_grader().await.unwrap();
+        assert_eq!(results.len(), 2);
+    }
 }
@@ -5,6 +5,7 @@
  *         // for files which are used by the autograder
  *     2022-fall/
  *         lib/
+ *             // files used by tests of multiple hws this quarter
  *         // all tests for each hw for this quarter
  *         hw1/
  *         hw2/
@@ -26,27 +27,35 @@
  */
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, RwLock},
 };
 
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use futures::future::join_all;
 use genos::{
-    fs::{filename, filepath, Error, ResourceLocator},
+    fs::{filename, filepath, find_dir_in, find_matching_in, resolve_in, Error, ResourceLocator},
     tid::TestId,
 };
 
 use glob::glob;
-use tokio::{fs::File, io::AsyncReadExt};
 use tracing::{debug, warn};
 
-use crate::config::{FromConfigFile, TestConfig, TEST_CONFIG_NAME};
+use crate::{
+    config::{TestConfig, TestDefaults, TEST_CONFIG_NAME},
+    params,
+};
 
 pub trait TestResourceFinder {
     fn test_resource(&self, tid: TestId, name: &String) -> Result<PathBuf, Error>;
+    fn test_resource_dir(&self, tid: TestId, name: &str) -> Result<PathBuf, Error>;
+    fn test_resources_matching(
+        &self,
+        tid: TestId,
+        pattern: &str,
+    ) -> Result<Vec<(String, PathBuf)>, Error>;
 }
 
 pub trait SystemResourceFinder {
@@ -55,7 +64,7 @@ pub trait SystemResourceFinder {
 
 #[async_trait]
 pub trait TestConfigFinder {
-    async fn load_test_configs(&self) -> Result<Vec<TestConfig>>;
+    async fn load_test_configs(&self, defaults: &TestDefaults) -> Result<Vec<TestConfig>>;
 }
 
 pub struct DirFinder {
@@ -71,43 +80,105 @@ impl DirFinder {
 
 impl ResourceLocator for DirFinder {
     fn find(&self, name: &String) -> Result<PathBuf, Error> {
-        let file = self.dir.join(name);
+        let file = resolve_in(&self.dir, name)?;
         if file.is_dir() || !file.exists() {
             return Err(Error::NotFound);
         }
 
         Ok(file)
     }
+
+    fn find_dir(&self, name: &String) -> Result<PathBuf, Error> {
+        find_dir_in(&self.dir, name)
+    }
+
+    fn find_matching(&self, pattern: &str) -> Result<Vec<(String, PathBuf)>, Error> {
+        find_matching_in(&self.dir, pattern)
+    }
 }
 
 pub struct Finder {
     // directories for each test. test_resource_dirs is the first place searched for when
     // attempting to retrieve resources for a test and are associated with the `test_X` directory
     // found in the hw directory.
-    test_resource_dirs: HashMap<TestId, Box<dyn ResourceLocator>>,
+    test_resource_dirs: HashMap<TestId, Arc<dyn ResourceLocator>>,
+
+    // the dir of each test generated from a parametrized test, which is the dir of the test it was
+    // generated from. It's filled in when the test configs are loaded, see Params.
+    generated_dirs: RwLock<HashMap<TestId, Arc<dyn ResourceLocator>>>,
 
     // static is a directory found in the hw root and is a place for files used by multiple tests
     // in that hw. It is searched if the requested resource does not exist in the hw directory.
-    static_resource_dir: Option<Box<dyn ResourceLocator>>,
+    static_resource_dir: Option<Arc<dyn ResourceLocator>>,
+
+    // lib is a directory found in the quarter root for files used by multiple hws. It is only
+    // searched as one of the layers given by test_layers.
+    lib_resource_dir: Option<Arc<dyn ResourceLocator>>,
 
     // system resource dir is the directory holding system files which are required for the
     // autograder to function, such as the unittest header.
-    system_resource_dir: Box<dyn ResourceLocator>,
+    system_resource_dir: Arc<dyn ResourceLocator>,
+
+    // basefiles is a directory found in the hw root whose contents are copied into the ws of every
+    // test, on top of the submission.
+    basefiles_dir: Option<PathBuf>,
 }
 
 impl Finder {
     pub fn new(
-        test_resource_dirs: HashMap<TestId, Box<dyn ResourceLocator>>,
-        system_resource_dir: Box<dyn ResourceLocator>,
-        static_resource_dir: Option<Box<dyn ResourceLocator>>,
+        test_resource_dirs: HashMap<TestId, Arc<dyn ResourceLocator>>,
+        system_resource_dir: Arc<dyn ResourceLocator>,
+        static_resource_dir: Option<Arc<dyn ResourceLocator>>,
+        lib_resource_dir: Option<Arc<dyn ResourceLocator>>,
+        basefiles_dir: Option<PathBuf>,
     ) -> Self {
         Self {
             test_resource_dirs,
+            generated_dirs: RwLock::default(),
             static_resource_dir,
+            lib_resource_dir,
             system_resource_dir,
+            basefiles_dir,
         }
     }
 
+    pub fn basefiles_dir(&self) -> Option<&Path> {
+        self.basefiles_dir.as_deref()
+    }
+
+    // the layers searched for the resources of a test. A test generated from a parametrized test
+    // uses the dir of the test it was generated from, see Params.
+    fn test_and_static_layers(&self, tid: TestId) -> Result<MultiSourceFinder, Error> {
+        let generated = self
+            .generated_dirs
+            .read()
+            .expect("generated dirs lock poisoned")
+            .get(&tid)
+            .cloned();
+        let test_dir = generated
+            .or_else(|| self.test_resource_dirs.get(&tid).cloned())
+            .ok_or(Error::UnknownTestId)?;
+
+        let mut layers = MultiSourceFinder::default().source("test", test_dir);
+        if let Some(dir) = &self.static_resource_dir {
+            layers.add_source("static", dir.clone());
+        }
+
+        Ok(layers)
+    }
+
+    /// Every resource dir a test can find files in, searched in the order: the test's dir,
+    /// static, lib and then system.
+    pub fn test_layers(&self, tid: TestId) -> Result<MultiSourceFinder, Error> {
+        let mut layers = self.test_and_static_layers(tid)?;
+        if let Some(dir) = &self.lib_resource_dir {
+            layers.add_source("lib", dir.clone());
+        }
+        layers.add_source("system", self.system_resource_dir.clone());
+
+        Ok(layers)
+    }
+
     // Expect the data directory to have a structure which can be read based on the hw config and
     // its location. For example, by knowing where the hw config is, we know the system dir is 2
     // levels up, and that the test resource dirs are in the same direcory and follow the naming
@@ -131,10 +202,16 @@ impl Finder {
                     let (_, id) = filename.split_once('_').unwrap();
                     let id = match id.parse() {
                         Ok(id) => id,
-                        Err(_) => return None,
+                        Err(_) => {
+                            warn!(
+                                "Test dir id isn't a number, skipping: {:?}",
+                                test_dir.display()
+                            );
+                            return None;
+                        }
                     };
                     let test_id = TestId::new(id);
-                    let finder: Box<dyn ResourceLocator> = Box::new(DirFinder::new(test_dir));
+                    let finder: Arc<dyn ResourceLocator> = Arc::new(DirFinder::new(test_dir));
                     Some((test_id, finder))
                 }
                 Err(e) => {
@@ -146,10 +223,14 @@ impl Finder {
 
         // the static directory is optional and is found in the hw root
         let dir = hw_root.join("static");
-        let static_resource_dir: Option<Box<dyn ResourceLocator>> = dir
+        let static_resource_dir: Option<Arc<dyn ResourceLocator>> = dir
             .try_exists()?
             // need the cast here to coerce the DirFinder into a trait object
-            .then(|| Box::new(DirFinder::new(dir)) as _);
+            .then(|| Arc::new(DirFinder::new(dir)) as _);
+
+        // basefiles is optional as well
+        let dir = hw_root.join("basefiles");
+        let basefiles_dir = dir.try_exists()?.then_some(dir);
 
         // the data root is two levels up from the hw root.
         let data_root = hw_root
@@ -158,6 +239,15 @@ impl Finder {
             .parent()
             .ok_or(anyhow!("could not locate data root"))?;
 
+        // lib is optional and is found in the quarter root, the parent of the hw root
+        let dir = hw_root
+            .parent()
+            .ok_or(anyhow!("hw root has no parent"))?
+            .join("lib");
+        let lib_resource_dir: Option<Arc<dyn ResourceLocator>> = dir
+            .try_exists()?
+            .then(|| Arc::new(DirFinder::new(dir)) as _);
+
         let system_resource_dir = data_root.join("system");
 
         if !system_resource_dir.exists() {
@@ -166,25 +256,30 @@ impl Finder {
 
         Ok(Self::new(
             test_resource_dirs,
-            Box::new(DirFinder::new(system_resource_dir)),
+            Arc::new(DirFinder::new(system_resource_dir)),
             static_resource_dir,
+            lib_resource_dir,
+            basefiles_dir,
         ))
     }
 }
 
 impl TestResourceFinder for Finder {
     fn test_resource(&self, tid: TestId, name: &String) -> Result<PathBuf, Error> {
-        let resource_dir = self
-            .test_resource_dirs
-            .get(&tid)
-            .ok_or(Error::UnknownTestId)?;
+        self.test_and_static_layers(tid)?.find(name)
+    }
 
-        resource_dir
-            .find(name)
-            .or_else(|_e| match self.static_resource_dir.as_ref() {
-                Some(dir) => dir.find(name),
-                None => Err(Error::NotFound),
-            })
+    fn test_resource_dir(&self, tid: TestId, name: &str) -> Result<PathBuf, Error> {
+        self.test_and_static_layers(tid)?
+            .find_dir(&name.to_string())
+    }
+
+    fn test_resources_matching(
+        &self,
+        tid: TestId,
+        pattern: &str,
+    ) -> Result<Vec<(String, PathBuf)>, Error> {
+        self.test_and_static_layers(tid)?.find_matching(pattern)
     }
 }
 
@@ -196,12 +291,12 @@ impl SystemResourceFinder for Finder {
 
 #[async_trait]
 impl TestConfigFinder for Finder {
-    async fn load_test_configs(&self) -> Result<Vec<TestConfig>> {
+    async fn load_test_configs(&self, defaults: &TestDefaults) -> Result<Vec<TestConfig>> {
         // load all in configs in parallel
-        join_all(self.test_resource_dirs.iter().map(|(tid, dir)| async {
+        let configs = join_all(self.test_resource_dirs.iter().map(|(tid, dir)| async {
                 let path = dir.find(&TEST_CONFIG_NAME.to_string())?;
 
-                let config = TestConfig::from_file(&path).await?;
+                let config = TestConfig::from_file_with_defaults(&path, defaults).await?;
 
                 assert_eq!(
                     config.description.test_id, *tid,
@@ -209,11 +304,38 @@ impl TestConfigFinder for Finder {
                     config.description.test_id, *tid
                 );
 
-                Ok(config)
+                // a parametrized test is run as the tests generated from its rows
+                let tests = params::generate(config, dir.as_ref())?;
+                Ok((*tid, dir.clone(), tests))
             }))
             .await
             .into_iter()
-            .collect()
+            .collect::<Result<Vec<_>>>()?;
+
+        // two test dirs can have the same id, like test_3 and test_03, and each id can only be one
+        // test
+        let mut ids = HashSet::new();
+        let mut generated_dirs = HashMap::new();
+        for (tid, dir, tests) in &configs {
+            for test in tests {
+                let id = test.description.test_id;
+                if !ids.insert(id) {
+                    return Err(anyhow!("Test id {} is used by more than one test", id));
+                }
+                if id != *tid {
+                    generated_dirs.insert(id, dir.clone());
+                }
+            }
+        }
+        *self
+            .generated_dirs
+            .write()
+            .expect("generated dirs lock poisoned") = generated_dirs;
+
+        Ok(configs
+            .into_iter()
+            .flat_map(|(_, _, tests)| tests)
+            .collect())
     }
 }
 
@@ -246,18 +368,123 @@ where
     fn find(&self, name: &String) -> Result<PathBuf, Error> {
         self.finder.test_resource(self.tid, name)
     }
+
+    fn find_dir(&self, name: &String) -> Result<PathBuf, Error> {
+        self.finder.test_resource_dir(self.tid, name)
+    }
+
+    fn find_matching(&self, pattern: &str) -> Result<Vec<(String, PathBuf)>, Error> {
+        self.finder.test_resources_matching(self.tid, pattern)
+    }
+}
+
+/// SystemFileFinder provides a wrapper which can be given to tests which need files from the system
+/// resource directory, such as the unittest header.
+pub struct SystemFileFinder<F> {
+    finder: Arc<F>,
+}
+
+impl<F> SystemFileFinder<F> {
+    pub fn new(finder: Arc<F>) -> Self {
+        Self { finder }
+    }
+}
+
+impl<F> ResourceLocator for SystemFileFinder<F>
+where
+    F: SystemResourceFinder + Send + Sync,
+{
+    fn find(&self, name: &String) -> Result<PathBuf, Error> {
+        self.finder.system_resource(name)
+    }
 }
 
-#[derive(Default)]
+/// MultiSourceFinder is a ResourceLocator made of layers of other locators. A lookup searches the
+/// layers in the order they were added and the first layer with the resource satisfies it. Each
+/// layer is named so that lookups can report where a resource came from.
+#[derive(Default, Clone)]
 pub struct MultiSourceFinder {
-    finders: Vec<Box<dyn ResourceLocator>>,
+    sources: Vec<(String, Arc<dyn ResourceLocator>)>,
 }
 
 impl MultiSourceFinder {
-    pub fn source(mut self, source: Box<dyn ResourceLocator>) -> Self {
-        self.finders.push(source);
+    pub fn source<N, L>(mut self, name: N, source: L) -> Self
+    where
+        N: Into<String>,
+        L: ResourceLocator + 'static,
+    {
+        self.add_source(name, source);
         self
     }
+
+    pub fn add_source<N, L>(&mut self, name: N, source: L)
+    where
+        N: Into<String>,
+        L: ResourceLocator + 'static,
+    {
+        self.sources.push((name.into(), Arc::new(source)));
+    }
+
+    /// Finds a file resource along with the name of the layer it was found in.
+    pub fn locate(&self, name: &String) -> Result<(&str, PathBuf), Error> {
+        self.locate_with(|source| source.find(name))
+    }
+
+    /// Finds a directory resource along with the name of the layer it was found in.
+    pub fn locate_dir(&self, name: &String) -> Result<(&str, PathBuf), Error> {
+        self.locate_with(|source| source.find_dir(name))
+    }
+
+    // an error other than NotFound, like an invalid pattern, stops the search
+    fn locate_with<F>(&self, find: F) -> Result<(&str, PathBuf), Error>
+    where
+        F: Fn(&dyn ResourceLocator) -> Result<PathBuf, Error>,
+    {
+        for (layer, source) in &self.sources {
+            match find(source.as_ref()) {
+                Ok(path) => {
+                    debug!(layer, path=?path, "found resource");
+                    return Ok((layer, path));
+                }
+                Err(Error::NotFound) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(Error::NotFound)
+    }
+}
+
+impl ResourceLocator for MultiSourceFinder {
+    fn find(&self, name: &String) -> Result<PathBuf, Error> {
+        self.locate(name).map(|(_, path)| path)
+    }
+
+    fn find_dir(&self, name: &String) -> Result<PathBuf, Error> {
+        self.locate_dir(name).map(|(_, path)| path)
+    }
+
+    // the matches from every layer are combined, with a file from an earlier layer taking the
+    // place of a file found under the same name in a later one
+    fn find_matching(&self, pattern: &str) -> Result<Vec<(String, PathBuf)>, Error> {
+        let mut found: Vec<(String, PathBuf)> = Vec::new();
+        for (_, source) in &self.sources {
+            let matches = match source.find_matching(pattern) {
+                Ok(matches) => matches,
+                Err(Error::NotFound) => continue,
+                Err(e) => return Err(e),
+            };
+
+            for (name, path) in matches {
+                if !found.iter().any(|(found_name, _)| *found_name == name) {
+                    found.push((name, path));
+                }
+            }
+        }
+
+        found.sort();
+        Ok(found)
+    }
 }
 
 #[cfg(test)]
@@ -315,6 +542,32 @@ mod tests {
         }
 
         assert!(finder.static_resource_dir.is_none());
+        assert!(finder.basefiles_dir().is_none());
+    }
+
+    #[test]
+    fn finds_basefiles() {
+        let mock_data_dir = MockDir::new()
+            .dir(
+                "system",
+                MockDir::new().file(MockFile::new("genos_unittest.h", "contents")),
+            )
+            .dir(
+                "2022-winter",
+                MockDir::new().dir(
+                    "hw1",
+                    MockDir::new().dir("test_1", MockDir::new()).dir(
+                        "basefiles",
+                        MockDir::new().file(MockFile::new("Makefile", "all:")),
+                    ),
+                ),
+            );
+
+        let finder = Finder::from_mock_dir(&mock_data_dir, "2022-winter/hw1/hw.yaml").unwrap();
+        assert_eq!(
+            finder.basefiles_dir().unwrap(),
+            mock_data_dir.path_from_root("2022-winter/hw1/basefiles")
+        );
     }
 
     #[test]
@@ -439,4 +692,276 @@ mod tests {
         let path = test_finder.find(&"static_file".to_string()).unwrap();
         assert!(path.exists());
     }
+
+    #[test]
+    fn test_file_finder_finds_dirs_and_patterns() {
+        let mock_data_dir = MockDir::new()
+            .dir(
+                "system",
+                MockDir::new().file(MockFile::new("genos_unittest.h", "contents")),
+            )
+            .dir(
+                "2022-winter",
+                MockDir::new().dir(
+                    "hw1",
+                    MockDir::new()
+                        .dir(
+                            "test_1",
+                            MockDir::new()
+                                .dir("fixtures", MockDir::new().file(("a.txt", "test")))
+                                .file(("input.txt", "test")),
+                        )
+                        .dir(
+                            "static",
+                            MockDir::new()
+                                .dir("shared", MockDir::new())
+                                .file(("input.txt", "static"))
+                                .file(("other.txt", "static")),
+                        ),
+                ),
+            );
+
+        let finder =
+            Arc::new(Finder::from_mock_dir(&mock_data_dir, "2022-winter/hw1/hw.yaml").unwrap());
+        let test_finder = TestFileFinder::new(1.into(), finder.clone());
+
+        assert!(test_finder.find(&"fixtures".to_string()).is_err());
+        assert!(test_finder.find_dir(&"fixtures".to_string()).is_ok());
+        assert!(test_finder.find_dir(&"shared".to_string()).is_ok());
+
+        let found = test_finder.find_matching("*.txt").unwrap();
+        assert_eq!(
+            found,
+            [
+                (
+                    "input.txt".to_string(),
+                    mock_data_dir.path_from_root("2022-winter/hw1/test_1/input.txt")
+                ),
+                (
+                    "other.txt".to_string(),
+                    mock_data_dir.path_from_root("2022-winter/hw1/static/other.txt")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_source_finder_searches_layers_in_order() {
+        let first = MockDir::new()
+            .file(("shared", "first"))
+            .file(("a.txt", "first"));
+        let second = MockDir::new()
+            .file(("shared", "second"))
+            .file(("only_second", "second"))
+            .file(("a.txt", "second"))
+            .file(("b.txt", "second"))
+            .dir("dir", MockDir::new());
+
+        let finder = MultiSourceFinder::default()
+            .source("first", first)
+            .source("second", second);
+
+        let (layer, path) = finder.locate(&"shared".to_string()).unwrap();
+        assert_eq!(layer, "first");
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "first");
+
+        let (layer, _) = finder.locate(&"only_second".to_string()).unwrap();
+        assert_eq!(layer, "second");
+
+        let (layer, _) = finder.locate_dir(&"dir".to_string()).unwrap();
+        assert_eq!(layer, "second");
+
+        assert!(matches!(
+            finder.find(&"missing".to_string()),
+            Err(Error::NotFound)
+        ));
+
+        let found = finder.find_matching("*.txt").unwrap();
+        let contents: Vec<_> = found
+            .iter()
+            .map(|(name, path)| (name.as_str(), std::fs::read_to_string(path).unwrap()))
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                ("a.txt", "first".to_string()),
+                ("b.txt", "second".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layers_include_lib_and_system() {
+        let mock_data_dir = MockDir::new()
+            .dir(
+                "system",
+                MockDir::new().file(MockFile::new("genos_unittest.h", "contents")),
+            )
+            .dir(
+                "2022-winter",
+                MockDir::new()
+                    .dir(
+                        "lib",
+                        MockDir::new()
+                            .file(("common.h", "lib"))
+                            .file(("lib.h", "lib")),
+                    )
+                    .dir(
+                        "hw1",
+                        MockDir::new()
+                            .dir("test_1", MockDir::new().file(("expected", "test")))
+                            .dir("static", MockDir::new().file(("common.h", "static"))),
+                    ),
+            );
+
+        let finder = Finder::from_mock_dir(&mock_data_dir, "2022-winter/hw1/hw.yaml").unwrap();
+        let layers = finder.test_layers(1.into()).unwrap();
+
+        for (name, expected_layer) in [
+            ("expected", "test"),
+            ("common.h", "static"),
+            ("lib.h", "lib"),
+            ("genos_unittest.h", "system"),
+        ] {
+            let (layer, _) = layers.locate(&name.to_string()).unwrap();
+            assert_eq!(layer, expected_layer);
+        }
+
+        // lib is only searched through the layers
+        let test_finder = TestFileFinder::new(1.into(), Arc::new(finder));
+        assert!(test_finder.find(&"genos_unittest.h".to_string()).is_err());
+        assert!(matches!(
+            layers
+                .clone()
+                .source("empty", MockDir::new())
+                .locate(&"x".to_string()),
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn loads_tests_generated_from_params() {
+        let mock_data_dir = MockDir::new()
+            .dir(
+                "system",
+                MockDir::new().file(MockFile::new("genos_unittest.h", "contents")),
+            )
+            .dir(
+                "2022-winter",
+                MockDir::new().dir(
+                    "hw1",
+                    MockDir::new().dir(
+                        "test_3",
+                        MockDir::new()
+                            .file((
+                                "config.yaml",
+                                r#"
+description:
+    name: io
+    description: io
+    test_id: 3
+    total_points: 0
+    visibility: Visible
+test_type: Diff
+compile: {}
+params: !Csv cases.csv
+"#,
+                            ))
+                            .file(("cases.csv", "name\nfirst\nsecond\n"))
+                            .file(("second.out", "expected")),
+                    ),
+                ),
+            );
+
+        let finder = Finder::from_mock_dir(&mock_data_dir, "2022-winter/hw1/hw.yaml").unwrap();
+        let mut ids: Vec<TestId> = finder
+            .load_test_configs(&TestDefaults::default())
+            .await
+            .unwrap()
+            .iter()
+            .map(|config| config.description.test_id)
+            .collect();
+        ids.sort();
+
+        assert_eq!(ids, [TestId::new(3001), TestId::new(3002)]);
+
+        // the generated tests find their resources in the dir of the parametrized test
+        let layers = finder.test_layers(TestId::new(3002)).unwrap();
+        let (layer, _) = layers.locate(&"second.out".to_string()).unwrap();
+        assert_eq!(layer, "test");
+        assert!(finder.test_layers(TestId::new(4001)).is_err());
+    }
+
+    #[tokio::test]
+    async fn generated_id_of_test_dir_is_error() {
+        let config = |tid: u32, params: &str| {
+            format!(
+                r#"
+description:
+    name: io
+    description: io
+    test_id: {}
+    total_points: 0
+    visibility: Visible
+test_type: Diff
+compile: {{}}
+{}
+"#,
+                tid, params
+            )
+        };
+        let mock_data_dir = MockDir::new()
+            .dir(
+                "system",
+                MockDir::new().file(MockFile::new("genos_unittest.h", "contents")),
+            )
+            .dir(
+                "2022-winter",
+                MockDir::new().dir(
+                    "hw1",
+                    MockDir::new()
+                        .dir(
+                            "test_3",
+                            MockDir::new()
+                                .file(("config.yaml", config(3, "params: !Rows [{name: first}]"))),
+                        )
+                        .dir(
+                            "test_3001",
+                            MockDir::new().file(("config.yaml", config(3001, ""))),
+                        ),
+                ),
+            );
+
+        let finder = Finder::from_mock_dir(&mock_data_dir, "2022-winter/hw1/hw.yaml").unwrap();
+        let e = finder
+            .load_test_configs(&TestDefaults::default())
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{:#}", e).contains("Test id 3001 collides with the ids of parametrized tests"),
+            "{:#}",
+            e
+        );
+    }
+
+    #[test]
+    fn system_file_finder() {
+        let mock_data_dir = MockDir::new()
+            .dir(
+                "system",
+                MockDir::new().file(MockFile::new("genos_unittest.h", "contents")),
+            )
+            .dir(
+                "2022-winter",
+                MockDir::new().dir("hw1", MockDir::new().dir("test_1", MockDir::new())),
+            );
+
+        let finder =
+            Arc::new(Finder::from_mock_dir(&mock_data_dir, "2022-winter/hw1/hw.yaml").unwrap());
+        let system_finder = SystemFileFinder::new(finder);
+        let path = system_finder.find(&"genos_unittest.h".to_string()).unwrap();
+        assert!(path.exists());
+
+        system_finder.find(&"test_1".to_string()).unwrap_err();
+    }
 }
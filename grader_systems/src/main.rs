@@ -1,24 +1,55 @@
 use anyhow::Result;
-use config::{Cli, FromConfigFile, HwConfig};
+use config::{Args, Cli, Command, FromConfigFile, HwConfig};
 use context::Context;
 use tracing::error;
 
 mod config;
 mod context;
 mod finder;
+mod params;
+mod schema;
 mod stage;
+mod validate;
+mod vars;
 
 async fn run_grader(cli_config: Cli) -> Result<()> {
     let hw_config = HwConfig::from_file(&cli_config.config).await?;
-    let context = Context::new(cli_config, hw_config).await;
-    context.run_grader().await
+    let context = Context::new(cli_config, hw_config).await?;
+    context.run_grader().await?;
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() {
-    let cli_config: Cli = argh::from_env();
+    let args: Args = argh::from_env();
 
-    if let Err(e) = run_grader(cli_config).await {
-        error!("Error running grader: {e}");
+    match args.command {
+        None => match args.run_config() {
+            Ok(cli_config) => {
+                if let Err(e) = run_grader(cli_config).await {
+                    error!("Error running grader: {e}");
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        Some(Command::Schema(schema_config)) => match schema::write_schemas(&schema_config.out) {
+            Ok(settings) => println!("{:#}", settings),
+            Err(e) => error!("Error writing schemas: {e}"),
+        },
+        Some(Command::Validate(validate_config)) => {
+            let problems = validate::validate(&validate_config.config);
+            for problem in &problems {
+                eprintln!("{}", problem);
+            }
+
+            if !problems.is_empty() {
+                let plural = if problems.len() == 1 { "" } else { "s" };
+                eprintln!("Found {} problem{}", problems.len(), plural);
+                std::process::exit(1);
+            }
+        }
     }
 }
@@ -0,0 +1,348 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use genos::{
+    fs::ResourceLocator,
+    points::Points,
+    stage::import_files::{ImportConfig, ImportSpec},
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::config::TestConfig;
+
+/// The rows of a parametrized test. Each row is its own test, which is the test config with the
+/// values of the row replacing the ones in the config, and has an id derived from the id of the
+/// config, see TestId::param. The rows are either given in the config or in a csv file in the
+/// test dir.
+/// Ex:
+/// params: !Rows
+///     - name: Empty input
+///       args: [-n]
+///       stdin: empty.txt
+///       expected: [empty.out]
+///       points: 2
+///
+/// Ex: params: !Csv cases.csv
+/// name,args,stdin,expected,points
+/// Empty input,-n,empty.txt,empty.out,2
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub enum Params {
+    Rows(Vec<ParamRow>),
+    Csv(String),
+}
+
+/// The values of a row which replace the ones in the test config. The args are added after the
+/// args of the run config, the stdin file is imported into the ws at the same path, and there is
+/// one expected file for each compare in the order they're configured. When the points are given,
+/// the points of the compares should be FullPoints so they follow the points of the row.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct ParamRow {
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub stdin: Option<String>,
+    #[serde(default)]
+    pub expected: Vec<String>,
+    pub points: Option<Points>,
+}
+
+/// A row of a csv file, where the args and the expected files are separated by whitespace.
+#[derive(Deserialize)]
+struct CsvRow {
+    name: String,
+    #[serde(default)]
+    args: String,
+    #[serde(default)]
+    stdin: Option<String>,
+    #[serde(default)]
+    expected: String,
+    #[serde(default)]
+    points: Option<Points>,
+}
+
+impl From<CsvRow> for ParamRow {
+    fn from(row: CsvRow) -> Self {
+        let words = |s: &str| s.split_whitespace().map(String::from).collect();
+        Self {
+            name: row.name,
+            args: words(&row.args),
+            stdin: row.stdin,
+            expected: words(&row.expected),
+            points: row.points,
+        }
+    }
+}
+
+impl Params {
+    /// The rows, reading the csv file from the test dir when they're in one.
+    pub fn rows(&self, test_dir: &dyn ResourceLocator) -> Result<Vec<ParamRow>> {
+        let file = match self {
+            Self::Rows(rows) => return Ok(rows.clone()),
+            Self::Csv(file) => file,
+        };
+
+        let path = test_dir
+            .find(file)
+            .with_context(|| format!("Unable to find params file {}", file))?;
+        csv::Reader::from_path(&path)?
+            .deserialize::<CsvRow>()
+            .enumerate()
+            .map(|(i, row)| {
+                row.map(ParamRow::from)
+                    .with_context(|| format!("Invalid row {} of {}", i + 1, file))
+            })
+            .collect()
+    }
+}
+
+impl ParamRow {
+    /// The test config of the row, where the first row is 1. The config isn't validated, so every
+    /// problem with the rows can be found.
+    pub fn apply(&self, mut config: TestConfig, row: u32) -> Result<TestConfig> {
+        let base = config.description.test_id;
+        config.params = None;
+        config.description.name = self.name.clone();
+        config.description.test_id = base.param(row).ok_or(anyhow!(
+            "Test {} has more rows than a parametrized test can have",
+            base
+        ))?;
+        if let Some(points) = self.points {
+            config.description.total_points = points;
+        }
+
+        if !self.args.is_empty() || self.stdin.is_some() {
+            let run = config.run.as_mut().ok_or(anyhow!(
+                "Expected test with args or stdin to have a run config"
+            ))?;
+            run.args.extend(self.args.iter().cloned());
+            if let Some(stdin) = &self.stdin {
+                run.stdin = Some(stdin.clone());
+            }
+        }
+
+        // the program reads its stdin from the ws, so the row's stdin is imported to the same path
+        if let Some(stdin) = &self.stdin {
+            let dest = Path::new(stdin)
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map(|dir| dir.to_string_lossy().into_owned());
+            config
+                .import_files
+                .get_or_insert_with(ImportConfig::default)
+                .add_spec(ImportSpec {
+                    src: stdin.clone(),
+                    dest,
+                    ..Default::default()
+                });
+        }
+
+        if !self.expected.is_empty() {
+            let compares = config
+                .compare_files
+                .as_mut()
+                .map(|compare_files| &mut compare_files.compares[..])
+                .unwrap_or_default();
+            if compares.len() != self.expected.len() {
+                return Err(anyhow!(
+                    "Expected one expected file for each of the {} compares, found {}",
+                    compares.len(),
+                    self.expected.len()
+                ));
+            }
+
+            for (compare, expected) in compares.iter_mut().zip(&self.expected) {
+                compare.expected = vec![expected.clone()];
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// The tests which a test config is run as, which are the tests generated from its rows when it's
+/// parametrized and otherwise just the test itself.
+pub fn generate(config: TestConfig, test_dir: &dyn ResourceLocator) -> Result<Vec<TestConfig>> {
+    let Some(params) = config.params.clone() else {
+        return Ok(vec![config]);
+    };
+    let tid = config.description.test_id;
+
+    let rows = params
+        .rows(test_dir)
+        .with_context(|| format!("Unable to load the params of test {}", tid))?;
+    if rows.is_empty() {
+        return Err(anyhow!("Expected parametrized test {} to have rows", tid));
+    }
+
+    rows.iter()
+        .zip(1..)
+        .map(|(row, i)| {
+            row.apply(config.clone(), i)
+                .and_then(|config| Ok(config.validate().map(|_| config)?))
+                .with_context(|| format!("Invalid row {} ({}) of test {}", i, row.name, tid))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use genos::{
+        process::ShellExecutor,
+        stage::import_files::ImportFiles,
+        test_util::{MockDir, MockFile},
+        tid::TestId,
+        Executor,
+    };
+
+    use crate::{finder::DirFinder, stage::run::Run};
+
+    use super::*;
+
+    const TEST_CONFIG: &str = r#"
+        description:
+            name: io
+            description: checks the output for each input
+            test_id: 3
+            total_points: 1
+            visibility: Visible
+        test_type: Diff
+        compile:
+            make_args: []
+        run:
+            executable: bin/prog
+            args: [--quiet]
+            stdout: stdout
+        compare_files:
+            compares:
+                - expected: [expected_stdout]
+                  student_file: stdout
+                  compare_type: Diff
+                  points: !FullPoints
+                  show_output: true
+        "#;
+
+    fn config(params: &str) -> TestConfig {
+        serde_yaml::from_str(&format!("{}{}", TEST_CONFIG, params)).unwrap()
+    }
+
+    #[test]
+    fn generates_test_for_each_row() {
+        let dir = MockDir::new();
+        let config = config(
+            r#"
+        params: !Rows
+            - name: empty
+              stdin: empty.txt
+              expected: [empty.out]
+            - name: large
+              args: [-n, "100"]
+              expected: [large.out]
+              points: 2.5
+        "#,
+        );
+
+        let tests = generate(config, &DirFinder::new(dir.root.path().to_path_buf())).unwrap();
+
+        assert_eq!(tests.len(), 2);
+        let (empty, large) = (&tests[0], &tests[1]);
+
+        assert_eq!(empty.description.test_id, TestId::new(3001));
+        assert_eq!(empty.description.name, "empty");
+        assert_eq!(empty.description.total_points, Points::new(1));
+        let run = empty.run.as_ref().unwrap();
+        assert_eq!(run.args, ["--quiet"]);
+        assert_eq!(run.stdin.as_deref(), Some("empty.txt"));
+        assert_eq!(
+            empty.compare_files.as_ref().unwrap().compares[0].expected,
+            ["empty.out"]
+        );
+
+        assert_eq!(large.description.test_id, TestId::new(3002));
+        assert_eq!(large.description.total_points, Points::new(2.5));
+        assert_eq!(large.run.as_ref().unwrap().args, ["--quiet", "-n", "100"]);
+        assert!(large.params.is_none());
+    }
+
+    #[test]
+    fn reads_rows_from_csv() {
+        let dir = MockDir::new().file((
+            "cases.csv",
+            "name,args,stdin,expected,points\n\
+             empty,,empty.txt,empty.out,\n\
+             large,-n 100,,large.out,2\n",
+        ));
+
+        let tests = generate(
+            config("params: !Csv cases.csv"),
+            &DirFinder::new(dir.root.path().to_path_buf()),
+        )
+        .unwrap();
+
+        assert_eq!(tests.len(), 2);
+        assert_eq!(
+            tests[0].run.as_ref().unwrap().stdin.as_deref(),
+            Some("empty.txt")
+        );
+        assert_eq!(tests[0].description.total_points, Points::new(1));
+        assert_eq!(
+            tests[1].run.as_ref().unwrap().args,
+            ["--quiet", "-n", "100"]
+        );
+        assert_eq!(tests[1].run.as_ref().unwrap().stdin, None);
+        assert_eq!(tests[1].description.total_points, Points::new(2));
+    }
+
+    #[test]
+    fn invalid_rows_are_errors() {
+        let dir = MockDir::new();
+        let finder = DirFinder::new(dir.root.path().to_path_buf());
+
+        let e = generate(
+            config("params: !Rows [{name: two, expected: [a.out, b.out]}]"),
+            &finder,
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", e).contains("Invalid row 1 (two) of test 3"));
+
+        generate(config("params: !Rows []"), &finder).unwrap_err();
+        generate(config("params: !Csv missing.csv"), &finder).unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn runs_row_with_its_stdin() {
+        let dir = MockDir::new()
+            .file(MockFile::new("prog", "#!/bin/sh\ncat\n"))
+            .dir("inputs", MockDir::new().file(("words.txt", "hello\n")));
+        let finder = DirFinder::new(dir.root.path().to_path_buf());
+        let config = config(
+            r#"
+        import_files:
+            files: [{ src: prog, dest: bin, mode: "755" }]
+        params: !Rows
+            - name: words
+              stdin: inputs/words.txt
+        "#,
+        );
+
+        let test = generate(config, &finder).unwrap().remove(0);
+        let ws = MockDir::new();
+        ImportFiles::new(test.import_files.as_ref().unwrap(), &finder)
+            .unwrap()
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        let mut run = test.run.unwrap();
+        run.disable_garbage_memory = Some(true);
+        Run::new(ShellExecutor, run)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(ws.root.path().join("stdout")).unwrap(),
+            "hello\n"
+        );
+    }
+}
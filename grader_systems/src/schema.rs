@@ -0,0 +1,290 @@
+use std::{collections::BTreeSet, fs, path::Path};
+
+use anyhow::Result;
+use schemars::{
+    gen::SchemaSettings,
+    schema::{InstanceType, Metadata, RootSchema, Schema, SchemaObject, SingleOrVec},
+    visit::{visit_root_schema, visit_schema_object, Visitor},
+    JsonSchema,
+};
+use serde_json::json;
+
+use crate::config::{HwConfig, TestConfig, TEST_CONFIG_NAME};
+
+/// The file names the schemas are written as. The hw schema refers to the test config schema for
+/// the defaults, so the two are kept in the same dir.
+pub const HW_SCHEMA_NAME: &str = "hw.schema.json";
+pub const TEST_SCHEMA_NAME: &str = "config.schema.json";
+
+/// A YAML tag used in the configs, along with the kind of node it tags, ex: `!Partial scalar`.
+/// Editors need to be told about every tag used, or they report the tags as errors.
+type CustomTag = String;
+
+/// Rewrites a schema generated from the config types to describe the YAML the configs are written
+/// in, instead of the JSON serde would use for the same types.
+///
+/// serde_yaml gives the variant of an enum as a YAML tag, ex: `points: !Partial 2`. Editors don't
+/// include tags in what they check against the schema, so they see `points: 2`. The one of
+/// `{"Partial": 2}` or `"FullPoints"` which is generated for the enum is replaced with the any of
+/// the variant contents. A unit variant is either a plain name or a tag with no value.
+///
+/// Unknown fields are ignored when a config is read, so a typo in a field name is silently
+/// ignored. They are reported by editors instead. When `required` is false, nothing is required,
+/// since the fields of a test config can come from the hw defaults.
+struct YamlSchema {
+    required: bool,
+    // each tagged variant along with what it tags
+    tags: Vec<(String, Schema)>,
+}
+
+impl YamlSchema {
+    fn untag_variants(&mut self, schema: &mut SchemaObject) {
+        // an enum which is flattened into a struct is a part of the struct's fields, not tagged
+        if schema.object.is_some() {
+            return;
+        }
+        let Some(subschemas) = schema.subschemas.as_mut() else {
+            return;
+        };
+        let Some(variants) = subschemas.one_of.take() else {
+            return;
+        };
+
+        let untagged = variants.into_iter().map(|variant| {
+            let Schema::Object(mut variant) = variant else {
+                return variant;
+            };
+
+            // a unit variant is a string with only its name allowed
+            if let Some(name) = variant
+                .enum_values
+                .as_ref()
+                .and_then(|values| values.first())
+                .and_then(|value| value.as_str())
+                .map(String::from)
+            {
+                self.tags.push((name.clone(), Schema::Bool(true)));
+                return SchemaObject {
+                    metadata: variant.metadata.take(),
+                    enum_values: Some(vec![json!(name), json!(null)]),
+                    ..Default::default()
+                }
+                .into();
+            }
+
+            // any other variant is an object with the variant name as its only field
+            let Some(object) = variant.object.as_mut() else {
+                return variant.into();
+            };
+            let Some((name, contents)) = object.properties.pop_first() else {
+                return variant.into();
+            };
+            self.tags.push((name.clone(), contents.clone()));
+
+            let description = variant.metadata.take().and_then(|m| m.description);
+            let mut contents = contents.into_object();
+            let metadata = contents.metadata();
+            metadata.title = Some(format!("!{}", name));
+            metadata.description = metadata.description.take().or(description);
+            contents.into()
+        });
+
+        subschemas.any_of = Some(untagged.collect());
+    }
+}
+
+impl Visitor for YamlSchema {
+    fn visit_schema_object(&mut self, schema: &mut SchemaObject) {
+        self.untag_variants(schema);
+
+        let Some(object) = schema.object.as_mut() else {
+            return visit_schema_object(self, schema);
+        };
+        if !self.required {
+            object.required.clear();
+        }
+
+        // a struct with a flattened enum has the rest of its fields in the variants of the enum,
+        // so neither can forbid the fields it doesn't have. The variants keep their required
+        // fields, since that's how the variant is picked.
+        if let Some(subschemas) = schema.subschemas.as_mut() {
+            for property in object.properties.values_mut() {
+                self.visit_schema(property);
+            }
+            for variant in subschemas.one_of.iter_mut().flatten() {
+                if let Schema::Object(SchemaObject {
+                    object: Some(variant),
+                    ..
+                }) = variant
+                {
+                    variant.additional_properties = None;
+                }
+            }
+            return;
+        }
+
+        if object.additional_properties.is_none() {
+            object.additional_properties = Some(Box::new(Schema::Bool(false)));
+        }
+        visit_schema_object(self, schema);
+    }
+}
+
+// the kind of YAML node which a tag's contents are written as
+fn node_kind(root: &RootSchema, schema: &Schema) -> &'static str {
+    let Schema::Object(schema) = schema else {
+        return "scalar";
+    };
+
+    if let Some(definition) = schema
+        .reference
+        .as_ref()
+        .and_then(|reference| reference.strip_prefix("#/definitions/"))
+        .and_then(|name| root.definitions.get(name))
+    {
+        return node_kind(root, definition);
+    }
+
+    match &schema.instance_type {
+        Some(SingleOrVec::Single(instance)) => match **instance {
+            InstanceType::Object => "mapping",
+            InstanceType::Array => "sequence",
+            _ => "scalar",
+        },
+        _ if schema.object.is_some() => "mapping",
+        _ if schema.array.is_some() => "sequence",
+        _ => "scalar",
+    }
+}
+
+/// Generates the schema of a config type for YAML, see YamlSchema, along with the tags it uses.
+fn yaml_schema_for<T: JsonSchema>(title: &str, required: bool) -> (RootSchema, Vec<CustomTag>) {
+    let mut root = SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<T>();
+    root.schema.metadata = Some(Box::new(Metadata {
+        title: Some(title.to_string()),
+        ..Default::default()
+    }));
+
+    let mut visitor = YamlSchema {
+        required,
+        tags: Vec::new(),
+    };
+    visit_root_schema(&mut visitor, &mut root);
+
+    let tags = visitor
+        .tags
+        .iter()
+        .map(|(name, contents)| format!("!{} {}", name, node_kind(&root, contents)))
+        .collect();
+
+    (root, tags)
+}
+
+/// Writes the schemas of the hw config and the test config to the dir, and returns the editor
+/// settings which use them. The settings are for the YAML extension of VS Code.
+pub fn write_schemas(dir: &Path) -> Result<serde_json::Value> {
+    let (hw, hw_tags) = yaml_schema_for::<HwConfig>("hw config", true);
+    // nothing is required in a test config, since anything can be given by the hw defaults. The
+    // merged configs are checked by the validate subcommand.
+    let (test, test_tags) = yaml_schema_for::<TestConfig>("test config", false);
+
+    fs::create_dir_all(dir)?;
+    let dir = dir.canonicalize()?;
+    let hw_path = dir.join(HW_SCHEMA_NAME);
+    let test_path = dir.join(TEST_SCHEMA_NAME);
+    fs::write(&hw_path, serde_json::to_string_pretty(&hw)?)?;
+    fs::write(&test_path, serde_json::to_string_pretty(&test)?)?;
+
+    let tags: BTreeSet<CustomTag> = hw_tags.into_iter().chain(test_tags).collect();
+    Ok(json!({
+        "yaml.schemas": {
+            (hw_path.to_string_lossy()): "**/hw.yaml",
+            (test_path.to_string_lossy()): format!("**/test_*/{}", TEST_CONFIG_NAME),
+        },
+        "yaml.customTags": tags,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use genos::test_util::MockDir;
+    use serde_json::Value;
+
+    use super::*;
+
+    fn schema_json<T: JsonSchema>(required: bool) -> (Value, Vec<CustomTag>) {
+        let (schema, tags) = yaml_schema_for::<T>("config", required);
+        (serde_json::to_value(schema).unwrap(), tags)
+    }
+
+    #[test]
+    fn tagged_enums_are_untagged() {
+        let (schema, tags) = schema_json::<TestConfig>(false);
+        let definitions = &schema["definitions"];
+
+        assert_eq!(
+            definitions["PointQuantity"]["anyOf"],
+            json!([
+                { "enum": ["FullPoints", null] },
+                { "title": "!Partial", "$ref": "#/definitions/Points" },
+            ])
+        );
+        assert_eq!(definitions["Points"]["multipleOf"], json!(0.25));
+
+        for tag in [
+            "!FullPoints scalar",
+            "!Partial scalar",
+            "!Compiler mapping",
+            "!Deny sequence",
+            "!Send scalar",
+            "!Expect mapping",
+        ] {
+            assert!(tags.contains(&tag.to_string()), "missing tag {}", tag);
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_forbidden() {
+        let (schema, _) = schema_json::<TestConfig>(false);
+        let definitions = &schema["definitions"];
+
+        assert_eq!(schema["additionalProperties"], json!(false));
+        assert_eq!(
+            definitions["RunConfig"]["additionalProperties"],
+            json!(false)
+        );
+        // nothing is required, since it can come from the defaults
+        assert!(schema.get("required").is_none());
+        assert!(definitions["RunConfig"].get("required").is_none());
+
+        // the fields of a flattened enum are in its variants, which pick the variant
+        let signal = &definitions["ScheduledSignal"];
+        assert!(signal.get("additionalProperties").is_none());
+        assert_eq!(signal["oneOf"][0]["required"], json!(["after_ms"]));
+        assert!(signal["oneOf"][0].get("additionalProperties").is_none());
+    }
+
+    #[test]
+    fn writes_schemas() {
+        let dir = MockDir::new();
+
+        let settings = write_schemas(dir.root.path()).unwrap();
+
+        let hw: Value =
+            serde_json::from_str(&fs::read_to_string(dir.path_from_root(HW_SCHEMA_NAME)).unwrap())
+                .unwrap();
+        assert_eq!(hw["required"], json!(["class", "groups", "name"]));
+        assert_eq!(
+            hw["definitions"]["TestDefaults"]["$ref"],
+            json!(TEST_SCHEMA_NAME)
+        );
+        assert!(dir.path_from_root(TEST_SCHEMA_NAME).is_file());
+
+        assert!(settings["yaml.customTags"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("!Partial scalar")));
+    }
+}
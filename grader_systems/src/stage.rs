@@ -1,2 +1,8 @@
 pub mod compile;
+pub mod interact;
 pub mod run;
+pub mod script;
+pub mod service;
+pub mod submission;
+pub mod symbols;
+pub mod unit_test;
@@ -1,80 +1,408 @@
-use std::path::Path;
+use std::{path::Path, sync::Arc, time::Duration};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use genos::{
+    fs::filepath,
     output::{self, Content, RichTextMaker, Section, StatusUpdates, Update},
     points::PointQuantity,
     process::{self, Command, ExitStatus, ProcessExecutor},
     stage::StageResult,
     Executor,
 };
+use glob::glob;
+use schemars::JsonSchema;
 use serde::Deserialize;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
 
-#[derive(Debug, Default, Clone, Deserialize)]
+use crate::vars::{self, Vars};
+
+use self::{
+    cache::BuildCache,
+    warnings::{parse_warnings, warnings_table, WarningsConfig},
+};
+
+pub mod cache;
+pub mod warnings;
+
+const DEFAULT_COMPILER: &str = "gcc";
+const DEFAULT_CMAKE_BUILD_DIR: &str = "build";
+
+// give a default timeout of 5 minutes, plenty for any assignment which compiles in a sane way.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// The output of every build command is appended to these files in the ws so that graders can
+/// inspect them after the run.
+pub const COMPILE_STDOUT_FILE: &str = "compile_stdout.txt";
+pub const COMPILE_STDERR_FILE: &str = "compile_stderr.txt";
+
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
 pub struct CompileConfig {
     /// We always require a makefile to be pressent in the ws root. The args in the config are
     /// passed directly to make.
     /// Ex: "make"
     /// Ex: "make TEST=main.c"
     pub make_args: Option<Vec<String>>,
+
+    /// How the submission gets built. When not given, the submission is built with make using
+    /// make_args.
+    #[serde(default)]
+    pub mode: CompileMode,
+
+    /// When given, the compiler's stderr is searched for warnings which lose points.
+    pub warnings: Option<WarningsConfig>,
+
+    /// Applied to each build command individually.
+    pub timeout_sec: Option<u64>,
+}
+
+impl CompileConfig {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_sec.map(Duration::from_secs)
+    }
+
+    /// The make args with the vars substituted into them.
+    pub fn make_args(&self, vars: &Vars) -> Result<Vec<String>, vars::Error> {
+        vars.expand_all(self.make_args.as_deref().unwrap_or_default())
+    }
+}
+
+/// CompileMode selects the build system used to compile the submission. Only `Make` will pick up
+/// a makefile from the workspace, every other mode ignores it so a student's makefile can't
+/// influence grading.
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+pub enum CompileMode {
+    #[default]
+    Make,
+    Compiler(CompilerConfig),
+    CMake(CMakeConfig),
+    Commands(Vec<String>),
+}
+
+/// Invoke the compiler directly on every source file matching the given glob patterns.
+/// Ex: gcc -Wall -Werror main.c list.c -o main
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+pub struct CompilerConfig {
+    pub compiler: Option<String>,
+    /// glob patterns relative to the ws root
+    pub sources: Vec<String>,
+    pub flags: Option<Vec<String>>,
+    pub output: String,
+}
+
+/// Configure the project with cmake into the build dir and then build it.
+/// Ex: cmake -S . -B build && cmake --build build
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+pub struct CMakeConfig {
+    pub build_dir: Option<String>,
+    pub configure_args: Option<Vec<String>>,
+    pub build_args: Option<Vec<String>>,
+}
+
+/// A single command which is run as part of building the submission. Each step gets its own
+/// status update and its own stdout/stderr sections in the feedback.
+struct BuildStep {
+    description: String,
+    feedback_name: String,
+    cmd: Command,
+}
+
+impl BuildStep {
+    fn new<D: Into<String>, N: Into<String>>(
+        description: D,
+        feedback_name: N,
+        cmd: Command,
+    ) -> Self {
+        Self {
+            description: description.into(),
+            feedback_name: feedback_name.into(),
+            cmd,
+        }
+    }
 }
 
 pub struct Compile<E> {
     args: Vec<String>,
+    mode: CompileMode,
+    warnings: Option<WarningsConfig>,
+    timeout: Duration,
+    cache: Option<Arc<BuildCache>>,
+    vars: Vars,
     executor: E,
 }
 
 impl<E: ProcessExecutor> Compile<E> {
     pub fn new(config: &CompileConfig, executor: E) -> Self {
         let args = config.clone().make_args.unwrap_or(vec![]);
-        Self { args, executor }
+        Self {
+            args,
+            mode: config.mode.clone(),
+            warnings: config.warnings.clone(),
+            timeout: config.timeout().unwrap_or(DEFAULT_TIMEOUT),
+            cache: None,
+            vars: Vars::new(),
+            executor,
+        }
     }
 
-    fn get_compile_feedback(&self, output: process::Output) -> Content {
-        let stdout =
-            Content::SubSection(Section::new("Compile Stdout").content(output.stdout.code()));
-        let stderr =
-            Content::SubSection(Section::new("Compile Stderr").content(output.stderr.code()));
+    /// The vars substituted into the make args, along with the ws.
+    pub fn vars(mut self, vars: Vars) -> Self {
+        self.vars = vars;
+        self
+    }
 
-        Content::Multiline([stdout, stderr].to_vec())
+    /// Share builds with every other Compile stage using the same cache. The build is only run if
+    /// no other stage has built the exact same inputs.
+    pub fn with_cache(mut self, cache: Arc<BuildCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    // run each step in order, stopping at the first one which fails
+    async fn run_steps(&self, ws: &Path, steps: &[BuildStep]) -> Result<Vec<process::Output>> {
+        let mut outputs = Vec::new();
+        for step in steps {
+            let output = step.cmd.run_with(&self.executor).await?;
+            save_compile_output(ws, step, &output).await?;
+
+            let is_ok = output.status.is_ok();
+            outputs.push(output);
+            if !is_ok {
+                break;
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    fn get_build_steps(&self, ws: &Path, args: Vec<String>) -> Result<Vec<BuildStep>, String> {
+        let steps = match &self.mode {
+            CompileMode::Make => vec![BuildStep::new(
+                "Compiling submission",
+                "Compile",
+                Command::new("make").args(args),
+            )],
+            CompileMode::Compiler(config) => {
+                let sources = find_sources(ws, &config.sources)?;
+                let cmd = Command::new(config.compiler.as_deref().unwrap_or(DEFAULT_COMPILER))
+                    .args(config.flags.clone().unwrap_or_default())
+                    .args(sources)
+                    .arg("-o")
+                    .arg(&config.output);
+
+                vec![BuildStep::new("Compiling submission", "Compiler", cmd)]
+            }
+            CompileMode::CMake(config) => {
+                let build_dir = config
+                    .build_dir
+                    .as_deref()
+                    .unwrap_or(DEFAULT_CMAKE_BUILD_DIR);
+
+                let configure = Command::new("cmake")
+                    .args(["-S", ".", "-B", build_dir])
+                    .args(config.configure_args.clone().unwrap_or_default());
+                let build = Command::new("cmake")
+                    .args(["--build", build_dir])
+                    .args(config.build_args.clone().unwrap_or_default());
+
+                vec![
+                    BuildStep::new("Configuring submission", "CMake Configure", configure),
+                    BuildStep::new("Building submission", "CMake Build", build),
+                ]
+            }
+            CompileMode::Commands(commands) => commands
+                .iter()
+                .map(|command| {
+                    BuildStep::new(
+                        format!("Running `{}`", command),
+                        "Build Command",
+                        Command::new("sh").arg("-c").arg(command),
+                    )
+                })
+                .collect(),
+        };
+
+        Ok(steps
+            .into_iter()
+            .map(|mut step| {
+                step.cmd.set_cwd(ws);
+                step.cmd.set_timeout(self.timeout);
+                step
+            })
+            .collect())
+    }
+
+    fn get_compile_feedback(&self, step: &BuildStep, output: process::Output) -> Content {
+        if let ExitStatus::Timeout(duration) = output.status {
+            return format!(
+                "Compilation timed out after {:?}. Check for anything which could cause the build \
+                 to run forever, such as recursive make rules or runaway template instantiation.",
+                duration
+            )
+            .into();
+        }
+
+        let stdout = Content::SubSection(
+            Section::new(format!("{} Stdout", step.feedback_name)).content(output.stdout.code()),
+        );
+        let stderr = Content::SubSection(
+            Section::new(format!("{} Stderr", step.feedback_name)).content(output.stderr.code()),
+        );
+
+        let command = Content::SubSection(
+            Section::new(format!("{} Command", step.feedback_name))
+                .content(step.cmd.to_string().code()),
+        );
+
+        match &self.mode {
+            // make is the only mode where the student knows exactly what is being run, so there
+            // is no need to show the command.
+            CompileMode::Make => Content::Multiline([stdout, stderr].to_vec()),
+            _ => Content::Multiline([command, stdout, stderr].to_vec()),
+        }
     }
 }
 
+// resolve all the source globs relative to the ws. The sources are given to the compiler relative
+// to the ws root so that the compiler diagnostics don't leak the location of the ws.
+fn find_sources(ws: &Path, patterns: &[String]) -> Result<Vec<String>, String> {
+    let mut sources = Vec::new();
+    for pattern in patterns {
+        let full_pattern = ws.join(pattern);
+        let paths = glob(filepath(&full_pattern).map_err(|e| e.to_string())?)
+            .map_err(|e| format!("Invalid source pattern {}: {}", pattern, e))?;
+
+        for path in paths {
+            let path = path.map_err(|e| e.to_string())?;
+            let relative = path.strip_prefix(ws).map_err(|e| e.to_string())?;
+            let relative = filepath(relative).map_err(|e| e.to_string())?.to_string();
+            if !sources.contains(&relative) {
+                sources.push(relative);
+            }
+        }
+    }
+
+    if sources.is_empty() {
+        return Err(format!(
+            "Could not find any source files matching {}",
+            patterns.join(", ")
+        ));
+    }
+
+    Ok(sources)
+}
+
+// append the output of the build step to the compile output files in the ws
+async fn save_compile_output(ws: &Path, step: &BuildStep, output: &process::Output) -> Result<()> {
+    for (file, content) in [
+        (COMPILE_STDOUT_FILE, &output.stdout),
+        (COMPILE_STDERR_FILE, &output.stderr),
+    ] {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(ws.join(file))
+            .await?;
+
+        file.write_all(format!("$ {}\n", step.cmd).as_bytes())
+            .await?;
+        file.write_all(content.as_bytes()).await?;
+        if !content.is_empty() && !content.ends_with('\n') {
+            file.write_all(b"\n").await?;
+        }
+        // tokio files write in the background, so without a flush the write may not have finished
+        // when the file is read
+        file.flush().await?;
+    }
+
+    Ok(())
+}
+
 #[async_trait]
 impl<E: ProcessExecutor> Executor for Compile<E> {
     type Output = StageResult;
     async fn run(&self, ws: &Path) -> Result<Self::Output> {
         let mut section = Section::new("Compile");
         let mut status_updates = StatusUpdates::default();
-        let mut update = Update::new_pass("Compiling submission");
 
-        let output = Command::new("make")
-            .args(self.args.clone())
-            .cwd(ws)
-            .run_with(&self.executor)
-            .await?;
-
-        match &output.status {
-            ExitStatus::Ok => {
-                status_updates.add_update(update);
+        let args = self.vars.with_ws(ws).expand_all(&self.args)?;
+        let steps = match self.get_build_steps(ws, args) {
+            Ok(steps) => steps,
+            Err(reason) => {
+                status_updates.add_update(
+                    Update::new_fail("Compiling submission", PointQuantity::FullPoints)
+                        .notes(reason),
+                );
                 section.add_content(status_updates);
 
-                Ok(StageResult::new_continue(PointQuantity::zero())
-                    .with_output(output::Output::new().section(section)))
+                return Ok(StageResult::new_unrecoverable_failure()
+                    .with_output(output::Output::new().section(section)));
             }
+        };
+
+        if steps.is_empty() {
+            return Err(anyhow!(
+                "Expected compile stage to have at least one command"
+            ));
+        }
+
+        let outputs = match &self.cache {
+            Some(cache) => {
+                let commands = steps
+                    .iter()
+                    .map(|step| step.cmd.clone())
+                    .collect::<Vec<_>>();
+                cache
+                    .get_or_build(ws, &commands, || self.run_steps(ws, &steps))
+                    .await?
+            }
+            None => self.run_steps(ws, &steps).await?,
+        };
+
+        let mut stderr = String::new();
+        for (step, output) in steps.iter().zip(outputs) {
+            let mut update = Update::new_pass(&step.description);
 
-            _ => {
-                update.set_fail(PointQuantity::FullPoints);
-                update.set_notes(self.get_compile_feedback(output));
+            if let ExitStatus::Ok = &output.status {
+                stderr.push_str(&output.stderr);
                 status_updates.add_update(update);
+                continue;
+            }
 
-                section.add_content(status_updates);
+            update.set_fail(PointQuantity::FullPoints);
+            update.set_notes(self.get_compile_feedback(step, output));
+            status_updates.add_update(update);
+
+            section.add_content(status_updates);
+
+            return Ok(StageResult::new_unrecoverable_failure()
+                .with_output(output::Output::new().section(section)));
+        }
+
+        let mut points_lost = PointQuantity::zero();
+        if let Some(config) = &self.warnings {
+            let warnings = parse_warnings(&stderr);
+            points_lost = config.deduction(&warnings);
+
+            let mut update = Update::new_pass("Checking compiler warnings");
+            if points_lost != PointQuantity::zero() {
+                update.set_fail(points_lost);
+            }
 
-                Ok(StageResult::new_unrecoverable_failure()
-                    .with_output(output::Output::new().section(section)))
+            if !warnings.is_empty() {
+                update.set_notes(Content::Multiline(vec![
+                    format!("Found {} compiler warning(s)", warnings.len()).into(),
+                    warnings_table(&warnings).code().into(),
+                ]));
             }
+
+            status_updates.add_update(update);
         }
+
+        section.add_content(status_updates);
+
+        Ok(StageResult::new_continue(points_lost)
+            .with_output(output::Output::new().section(section)))
     }
 }
 
@@ -84,8 +412,9 @@ mod tests {
 
     use genos::{
         output::Contains,
+        points::Points,
         stage::StageStatus,
-        test_util::{MockExecutorInner, MockProcessExecutor},
+        test_util::{MockDir, MockExecutorInner, MockProcessExecutor},
     };
 
     use super::*;
@@ -99,6 +428,7 @@ mod tests {
         let executor = MockProcessExecutor::new(data.clone());
         let config = CompileConfig {
             make_args: Some(vec!["arg1".to_string(), "arg2".to_string()]),
+            ..Default::default()
         };
 
         let compile = Compile::new(&config, executor);
@@ -118,6 +448,28 @@ mod tests {
         assert_eq!(cmd.cwd.unwrap().as_path(), ws.path());
     }
 
+    #[tokio::test]
+    async fn compile_make_args_vars() {
+        let data = Arc::new(Mutex::new(MockExecutorInner::with_responses([Ok(
+            process::Output::from_exit_status(ExitStatus::Ok),
+        )])));
+
+        let executor = MockProcessExecutor::new(data.clone());
+        let config = CompileConfig {
+            make_args: Some(vec!["TEST=test_${TEST_ID}.c".to_string()]),
+            ..Default::default()
+        };
+
+        Compile::new(&config, executor)
+            .vars(Vars::new().var("TEST_ID", "4"))
+            .run(tempfile::tempdir().unwrap().path())
+            .await
+            .unwrap();
+
+        let cmd = data.lock().unwrap().commands.pop().unwrap();
+        assert_eq!(cmd.args, ["TEST=test_4.c"]);
+    }
+
     #[tokio::test]
     async fn compile_failure() {
         let data = Arc::new(Mutex::new(MockExecutorInner::with_responses([Ok(
@@ -127,6 +479,7 @@ mod tests {
         let executor = MockProcessExecutor::new(data.clone());
         let config = CompileConfig {
             make_args: Some(vec!["arg1".to_string(), "arg2".to_string()]),
+            ..Default::default()
         };
 
         let compile = Compile::new(&config, executor);
@@ -137,4 +490,319 @@ mod tests {
         assert!(res.output.as_ref().unwrap().contains("stdout here"));
         assert!(res.output.as_ref().unwrap().contains("stderr also"));
     }
+
+    #[tokio::test]
+    async fn compiler_mode_compiles_matching_sources() {
+        let data = Arc::new(Mutex::new(MockExecutorInner::with_responses([])));
+        let executor = MockProcessExecutor::new(data.clone());
+        let config = CompileConfig {
+            mode: CompileMode::Compiler(CompilerConfig {
+                compiler: Some("clang".to_string()),
+                sources: vec!["*.c".to_string(), "lib/*.c".to_string()],
+                flags: Some(vec!["-Wall".to_string()]),
+                output: "main".to_string(),
+            }),
+            ..Default::default()
+        };
+
+        let ws = MockDir::new()
+            .file(("main.c", ""))
+            .file(("Makefile", ""))
+            .dir("lib", MockDir::new().file(("list.c", "")));
+
+        let res = Compile::new(&config, executor)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::zero()
+            }
+        );
+
+        let commands = &data.lock().unwrap().commands;
+        assert_eq!(commands.len(), 1);
+        assert_eq!(&commands[0].program, "clang");
+        assert_eq!(
+            commands[0].args,
+            ["-Wall", "main.c", "lib/list.c", "-o", "main"]
+        );
+    }
+
+    #[tokio::test]
+    async fn compiler_mode_no_sources_found() {
+        let executor = MockProcessExecutor::with_responses([]);
+        let config = CompileConfig {
+            mode: CompileMode::Compiler(CompilerConfig {
+                sources: vec!["*.c".to_string()],
+                output: "main".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let ws = tempfile::tempdir().unwrap();
+        let res = Compile::new(&config, executor.clone())
+            .run(ws.path())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        assert!(res
+            .output
+            .unwrap()
+            .contains("Could not find any source files"));
+        assert!(executor.inner.lock().unwrap().commands.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cmake_mode_configures_then_builds() {
+        let data = Arc::new(Mutex::new(MockExecutorInner::with_responses([])));
+        let executor = MockProcessExecutor::new(data.clone());
+        let config = CompileConfig {
+            mode: CompileMode::CMake(CMakeConfig::default()),
+            ..Default::default()
+        };
+
+        let ws = tempfile::tempdir().unwrap();
+        Compile::new(&config, executor)
+            .run(ws.path())
+            .await
+            .unwrap();
+
+        let commands = &data.lock().unwrap().commands;
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].to_string(), "cmake -S . -B build");
+        assert_eq!(commands[1].to_string(), "cmake --build build");
+    }
+
+    #[tokio::test]
+    async fn cmake_configure_failure_skips_build() {
+        let data = Arc::new(Mutex::new(MockExecutorInner::with_responses([Ok(
+            process::Output::new(ExitStatus::Failure(1), "", "missing CMakeLists.txt"),
+        )])));
+        let executor = MockProcessExecutor::new(data.clone());
+        let config = CompileConfig {
+            mode: CompileMode::CMake(CMakeConfig::default()),
+            ..Default::default()
+        };
+
+        let ws = tempfile::tempdir().unwrap();
+        let res = Compile::new(&config, executor)
+            .run(ws.path())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        let output = res.output.unwrap();
+        assert!(output.contains("CMake Configure Stderr"));
+        assert!(output.contains("missing CMakeLists.txt"));
+        assert_eq!(data.lock().unwrap().commands.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn commands_mode_runs_each_command() {
+        let data = Arc::new(Mutex::new(MockExecutorInner::with_responses([])));
+        let executor = MockProcessExecutor::new(data.clone());
+        let config = CompileConfig {
+            mode: CompileMode::Commands(vec![
+                "gcc -c list.c".to_string(),
+                "gcc main.c list.o -o main".to_string(),
+            ]),
+            ..Default::default()
+        };
+
+        let ws = tempfile::tempdir().unwrap();
+        Compile::new(&config, executor)
+            .run(ws.path())
+            .await
+            .unwrap();
+
+        let commands = &data.lock().unwrap().commands;
+        assert_eq!(commands.len(), 2);
+        assert_eq!(&commands[1].program, "sh");
+        assert_eq!(commands[1].args, ["-c", "gcc main.c list.o -o main"]);
+    }
+
+    #[test]
+    fn deserialize_compile_modes() {
+        let config: CompileConfig = serde_yaml::from_str("make_args: [arg1]").unwrap();
+        assert!(matches!(config.mode, CompileMode::Make));
+
+        let config: CompileConfig = serde_yaml::from_str(
+            r#"
+            mode: !Compiler
+                sources: ["*.c"]
+                flags: [-Wall, -Werror]
+                output: main
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(config.mode, CompileMode::Compiler(_)));
+
+        let config: CompileConfig = serde_yaml::from_str(
+            r#"
+            mode: !Commands
+                - ./configure
+                - gcc main.c -o main
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(config.mode, CompileMode::Commands(cmds) if cmds.len() == 2));
+    }
+
+    #[tokio::test]
+    async fn compile_warnings_lose_points() {
+        let stderr = "main.c:4:9: warning: unused variable 'x' [-Wunused-variable]\n\
+                      main.c:9:1: warning: control reaches end of non-void function [-Wreturn-type]";
+        let executor = MockProcessExecutor::with_responses([Ok(process::Output::new(
+            ExitStatus::Ok,
+            "",
+            stderr,
+        ))]);
+        let config = CompileConfig {
+            warnings: Some(WarningsConfig {
+                points: Some(PointQuantity::Partial(Points::new(0.5))),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let ws = tempfile::tempdir().unwrap();
+        let res = Compile::new(&config, executor)
+            .run(ws.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(1))
+            }
+        );
+        let output = res.output.unwrap();
+        assert!(output.contains("Found 2 compiler warning(s)"));
+        assert!(output.contains("-Wreturn-type"));
+    }
+
+    #[tokio::test]
+    async fn compile_without_warnings_config_ignores_warnings() {
+        let executor = MockProcessExecutor::with_responses([Ok(process::Output::new(
+            ExitStatus::Ok,
+            "",
+            "main.c:4:9: warning: unused variable 'x' [-Wunused-variable]",
+        ))]);
+
+        let ws = tempfile::tempdir().unwrap();
+        let res = Compile::new(&CompileConfig::default(), executor)
+            .run(ws.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::zero()
+            }
+        );
+        assert!(!res.output.unwrap().contains("compiler warning"));
+    }
+
+    #[tokio::test]
+    async fn compile_timeout_is_unrecoverable() {
+        let data = Arc::new(Mutex::new(MockExecutorInner::with_responses([Ok(
+            process::Output::from_exit_status(ExitStatus::Timeout(Duration::from_secs(10))),
+        )])));
+        let executor = MockProcessExecutor::new(data.clone());
+        let config = CompileConfig {
+            timeout_sec: Some(10),
+            ..Default::default()
+        };
+
+        let ws = tempfile::tempdir().unwrap();
+        let res = Compile::new(&config, executor)
+            .run(ws.path())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        assert!(res
+            .output
+            .unwrap()
+            .contains("Compilation timed out after 10s"));
+
+        let cmd = data.lock().unwrap().commands.pop().unwrap();
+        assert_eq!(cmd.timeout, Some(Duration::from_secs(10)));
+    }
+
+    #[tokio::test]
+    async fn compile_uses_default_timeout() {
+        let data = Arc::new(Mutex::new(MockExecutorInner::with_responses([])));
+        let executor = MockProcessExecutor::new(data.clone());
+
+        let ws = tempfile::tempdir().unwrap();
+        Compile::new(&CompileConfig::default(), executor)
+            .run(ws.path())
+            .await
+            .unwrap();
+
+        let cmd = data.lock().unwrap().commands.pop().unwrap();
+        assert_eq!(cmd.timeout, Some(DEFAULT_TIMEOUT));
+    }
+
+    #[tokio::test]
+    async fn compile_output_saved_to_ws() {
+        let executor = MockProcessExecutor::with_responses([
+            Ok(process::Output::new(ExitStatus::Ok, "configured", "")),
+            Ok(process::Output::new(
+                ExitStatus::Ok,
+                "built",
+                "warning here",
+            )),
+        ]);
+        let config = CompileConfig {
+            mode: CompileMode::CMake(CMakeConfig::default()),
+            ..Default::default()
+        };
+
+        let ws = tempfile::tempdir().unwrap();
+        Compile::new(&config, executor)
+            .run(ws.path())
+            .await
+            .unwrap();
+
+        let stdout = std::fs::read_to_string(ws.path().join(COMPILE_STDOUT_FILE)).unwrap();
+        assert_eq!(
+            stdout,
+            "$ cmake -S . -B build\nconfigured\n$ cmake --build build\nbuilt\n"
+        );
+
+        let stderr = std::fs::read_to_string(ws.path().join(COMPILE_STDERR_FILE)).unwrap();
+        assert!(stderr.ends_with("warning here\n"));
+    }
+
+    #[tokio::test]
+    async fn cached_compile_runs_once() {
+        let data = Arc::new(Mutex::new(MockExecutorInner::with_responses([Ok(
+            process::Output::new(ExitStatus::Failure(2), "", "main.c: error"),
+        )])));
+        let executor = MockProcessExecutor::new(data.clone());
+        let cache = Arc::new(BuildCache::new().unwrap());
+
+        for _ in 0..3 {
+            let ws = MockDir::new().file(("main.c", "int main() {"));
+            let res = Compile::new(&CompileConfig::default(), executor.clone())
+                .with_cache(cache.clone())
+                .run(ws.root.path())
+                .await
+                .unwrap();
+
+            assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+            assert!(res.output.unwrap().contains("main.c: error"));
+            assert!(ws.path_from_root(COMPILE_STDERR_FILE).exists());
+        }
+
+        assert_eq!(data.lock().unwrap().commands.len(), 1);
+    }
 }
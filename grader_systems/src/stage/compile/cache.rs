@@ -0,0 +1,341 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use genos::{
+    fs::resolve_in,
+    process::{self, Command},
+};
+use sha2::{Digest, Sha256};
+use tempfile::{tempdir, TempDir};
+use tokio::{sync::Mutex as AsyncMutex, task::spawn_blocking};
+use tracing::debug;
+
+// maps the path of each file in a directory, relative to the directory, to the hash of its contents
+type Snapshot = BTreeMap<PathBuf, String>;
+
+/// BuildCache is shared between all the tests in a run so that a submission is only built once for
+/// every unique set of build inputs. The inputs are every file in the ws at the time of the build,
+/// which includes the submission and any imported files, along with the build commands.
+///
+/// The first test to build with a given set of inputs runs the build, and every other test with
+/// the same inputs waits for it to finish. Afterwards, the files created or modified by the build
+/// are copied into the ws of every dependent test and the files it deleted are removed from it, so
+/// every test sees the ws the build left. The build outputs are reused so that a failed build gives
+/// the same feedback to every test.
+pub struct BuildCache {
+    // removed along with the artifacts when the cache is dropped
+    root: TempDir,
+    entries: Mutex<HashMap<String, Arc<AsyncMutex<Option<CachedBuild>>>>>,
+}
+
+#[derive(Clone)]
+struct CachedBuild {
+    outputs: Vec<process::Output>,
+    artifacts: PathBuf,
+    // the files in the ws, relative to it, which the build deleted
+    deleted: Vec<PathBuf>,
+}
+
+impl BuildCache {
+    /// The build artifacts are stored in a temp dir for as long as the cache is alive.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            root: tempdir().context("Unable to create the build cache dir")?,
+            entries: Mutex::default(),
+        })
+    }
+
+    /// Returns the outputs of the build for the ws, only running build if the build inputs have
+    /// not been seen before.
+    pub async fn get_or_build<F, Fut>(
+        &self,
+        ws: &Path,
+        commands: &[Command],
+        build: F,
+    ) -> Result<Vec<process::Output>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<process::Output>>>,
+    {
+        let before = snapshot(ws).await?;
+        let key = cache_key(&before, commands);
+
+        let entry = self
+            .entries
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        let mut entry = entry.lock().await;
+        if let Some(cached) = entry.as_ref() {
+            debug!(key, "reusing cached build");
+            remove_files(ws, &cached.deleted).await?;
+            copy_dir(&cached.artifacts, ws).await?;
+            return Ok(cached.outputs.clone());
+        }
+
+        let outputs = build().await?;
+
+        let artifacts = self.root.path().join(&key);
+        let after = snapshot(ws).await?;
+        for (path, hash) in &after {
+            if before.get(path) == Some(hash) {
+                continue;
+            }
+
+            let dest = artifacts.join(path);
+            tokio::fs::create_dir_all(dest.parent().unwrap()).await?;
+            tokio::fs::copy(ws.join(path), &dest)
+                .await
+                .context(format!("Error caching build artifact {}", path.display()))?;
+        }
+        tokio::fs::create_dir_all(&artifacts).await?;
+        let deleted = before
+            .keys()
+            .filter(|path| !after.contains_key(*path))
+            .cloned()
+            .collect();
+
+        debug!(key, "cached build");
+        *entry = Some(CachedBuild {
+            outputs: outputs.clone(),
+            artifacts,
+            deleted,
+        });
+
+        Ok(outputs)
+    }
+}
+
+fn cache_key(snapshot: &Snapshot, commands: &[Command]) -> String {
+    let mut hasher = Sha256::new();
+    for (path, hash) in snapshot {
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(hash.as_bytes());
+        hasher.update([0]);
+    }
+
+    // the envs are a hash map and don't have a stable order, so the commands are hashed field by
+    // field instead of through their display.
+    for cmd in commands {
+        hasher.update(cmd.program.as_bytes());
+        for arg in &cmd.args {
+            hasher.update([0]);
+            hasher.update(arg.as_bytes());
+        }
+
+        let envs = cmd.envs.iter().collect::<BTreeMap<_, _>>();
+        hasher.update(format!("{:?}{:?}", envs, cmd.timeout).as_bytes());
+        hasher.update([1]);
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+async fn snapshot(dir: &Path) -> Result<Snapshot> {
+    let dir = dir.to_path_buf();
+    spawn_blocking(move || {
+        let mut snapshot = Snapshot::new();
+        snapshot_dir(&dir, &dir, &mut snapshot)?;
+        Ok(snapshot)
+    })
+    .await?
+}
+
+fn snapshot_dir(root: &Path, dir: &Path, snapshot: &mut Snapshot) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        // don't follow symlinked directories, they could easily lead to a cycle
+        if fs::symlink_metadata(&path)?.is_dir() {
+            snapshot_dir(root, &path, snapshot)?;
+        } else if path.is_file() {
+            let mut hasher = Sha256::new();
+            io::copy(&mut fs::File::open(&path)?, &mut hasher)?;
+            let hash = format!("{:x}", hasher.finalize());
+            snapshot.insert(path.strip_prefix(root)?.to_path_buf(), hash);
+        }
+    }
+
+    Ok(())
+}
+
+// the files are resolved in the ws, since it may not be laid out like the ws which was built
+async fn remove_files(ws: &Path, files: &[PathBuf]) -> Result<()> {
+    for file in files {
+        match tokio::fs::remove_file(resolve_in(ws, file)?).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+async fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    let (from, to) = (from.to_path_buf(), to.to_path_buf());
+    spawn_blocking(move || copy_dir_sync(&from, &to)).await?
+}
+
+fn copy_dir_sync(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let path = entry?.path();
+        let dest = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir_sync(&path, &dest)?;
+        } else {
+            // copy also carries over the permissions, so executables stay executable
+            fs::copy(&path, &dest)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use genos::{
+        process::ExitStatus,
+        test_util::{create_temp_file_in, MockDir},
+    };
+
+    use super::*;
+
+    fn make_ws() -> MockDir {
+        MockDir::new()
+            .file(("main.c", "int main() {}"))
+            .file(("Makefile", "all:"))
+    }
+
+    async fn fake_build(ws: &Path, count: &AtomicU32) -> Result<Vec<process::Output>> {
+        count.fetch_add(1, Ordering::Relaxed);
+        create_temp_file_in(ws, "main", "binary");
+        std::fs::create_dir(ws.join("obj")).unwrap();
+        create_temp_file_in(ws.join("obj"), "main.o", "object");
+        Ok(vec![process::Output::new(ExitStatus::Ok, "built", "")])
+    }
+
+    #[tokio::test]
+    async fn identical_inputs_build_once() {
+        let cache = BuildCache::new().unwrap();
+        let commands = [Command::new("make")];
+        let count = AtomicU32::new(0);
+
+        let ws1 = make_ws();
+        let ws2 = make_ws();
+        for ws in [&ws1, &ws2] {
+            let outputs = cache
+                .get_or_build(ws.root.path(), &commands, || {
+                    fake_build(ws.root.path(), &count)
+                })
+                .await
+                .unwrap();
+            assert_eq!(&outputs[0].stdout, "built");
+        }
+
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        // the artifacts were restored into the second ws
+        let main = std::fs::read_to_string(ws2.path_from_root("main")).unwrap();
+        assert_eq!(&main, "binary");
+        assert!(ws2.path_from_root("obj/main.o").exists());
+    }
+
+    #[tokio::test]
+    async fn deleted_files_are_removed_from_dependent_ws() {
+        let cache = BuildCache::new().unwrap();
+        let commands = [Command::new("make")];
+        let count = AtomicU32::new(0);
+
+        let ws1 = make_ws().file(("a.out", "stale"));
+        let ws2 = make_ws().file(("a.out", "stale"));
+        for ws in [&ws1, &ws2] {
+            cache
+                .get_or_build(ws.root.path(), &commands, || async {
+                    count.fetch_add(1, Ordering::Relaxed);
+                    std::fs::remove_file(ws.path_from_root("a.out")).unwrap();
+                    Ok(vec![process::Output::new(ExitStatus::Ok, "", "")])
+                })
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+        assert!(!ws2.path_from_root("a.out").exists());
+        assert!(ws2.path_from_root("main.c").exists());
+    }
+
+    #[tokio::test]
+    async fn different_inputs_build_separately() {
+        let cache = BuildCache::new().unwrap();
+        let count = AtomicU32::new(0);
+
+        let ws1 = make_ws();
+        cache
+            .get_or_build(ws1.root.path(), &[Command::new("make")], || {
+                fake_build(ws1.root.path(), &count)
+            })
+            .await
+            .unwrap();
+
+        // same files, different command
+        let ws2 = make_ws();
+        cache
+            .get_or_build(
+                ws2.root.path(),
+                &[Command::new("make").arg("debug")],
+                || fake_build(ws2.root.path(), &count),
+            )
+            .await
+            .unwrap();
+
+        // same command, different files
+        let ws3 = make_ws().file(("extra.h", ""));
+        cache
+            .get_or_build(ws3.root.path(), &[Command::new("make")], || {
+                fake_build(ws3.root.path(), &count)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn failed_build_is_reused() {
+        let cache = BuildCache::new().unwrap();
+        let commands = [Command::new("make")];
+        let count = AtomicU32::new(0);
+
+        for _ in 0..2 {
+            let ws = make_ws();
+            let outputs = cache
+                .get_or_build(ws.root.path(), &commands, || async {
+                    count.fetch_add(1, Ordering::Relaxed);
+                    Ok(vec![process::Output::new(
+                        ExitStatus::Failure(2),
+                        "",
+                        "main.c:1:1: error: expected ';'",
+                    )])
+                })
+                .await
+                .unwrap();
+
+            assert_eq!(outputs[0].status, ExitStatus::Failure(2));
+        }
+
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::{HashMap, HashSet};
+
+use genos::points::{PointQuantity, Points};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// A single diagnostic emitted by gcc or clang with the severity `warning`.
+/// Ex: main.c:12:9: warning: unused variable 'x' [-Wunused-variable]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Warning {
+    pub file: String,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub flag: Option<String>,
+    pub message: String,
+}
+
+impl Warning {
+    // warnings which were not tagged with a flag by the compiler are grouped together
+    fn class(&self) -> &str {
+        self.flag.as_deref().unwrap_or("other")
+    }
+}
+
+/// Parse all the warnings out of the compiler's stderr. Any lines which are not warnings, such as
+/// errors, notes, or the source excerpts following a diagnostic, are ignored. Duplicate warnings,
+/// which happen when the same header is included in multiple translation units, are only reported
+/// once.
+pub fn parse_warnings(stderr: &str) -> Vec<Warning> {
+    let mut seen = HashSet::new();
+
+    stderr
+        .lines()
+        .filter_map(parse_warning_line)
+        .filter(|warning| seen.insert(warning.clone()))
+        .collect()
+}
+
+fn parse_warning_line(line: &str) -> Option<Warning> {
+    let (location, message) = line.split_once(": warning: ")?;
+
+    let (file, line, column) = split_location(location);
+
+    let message = message.trim();
+    let (message, flag) = match message.rsplit_once(" [") {
+        Some((msg, flag)) if flag.starts_with("-W") && flag.ends_with(']') => {
+            // flags which take a level are tagged with it, ex: [-Wformat=] or
+            // [-Wimplicit-fallthrough=3], but they're configured by name
+            let flag = flag.trim_end_matches(']');
+            let flag = flag.split_once('=').map_or(flag, |(name, _)| name);
+            (msg, Some(flag.to_string()))
+        }
+        _ => (message, None),
+    };
+
+    Some(Warning {
+        file,
+        line,
+        column,
+        flag,
+        message: message.to_string(),
+    })
+}
+
+// location is file:line:column, but column and line are optional depending on the compiler and
+// the kind of warning.
+fn split_location(location: &str) -> (String, Option<u32>, Option<u32>) {
+    let mut file = location;
+    let mut numbers = Vec::new();
+    while numbers.len() < 2 {
+        match file
+            .rsplit_once(':')
+            .map(|(rest, num)| (rest, num.parse::<u32>()))
+        {
+            Some((rest, Ok(num))) => {
+                numbers.push(num);
+                file = rest;
+            }
+            _ => break,
+        }
+    }
+
+    numbers.reverse();
+    (
+        file.to_string(),
+        numbers.first().copied(),
+        numbers.get(1).copied(),
+    )
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, JsonSchema)]
+pub enum DeductionUnit {
+    /// every warning is deducted
+    #[default]
+    Warning,
+    /// each class of warning is only deducted once, no matter how many times it occurs
+    Class,
+}
+
+/// Configures how many points are lost for compiler warnings.
+/// Ex:
+/// warnings:
+///     points: !Partial 0.25
+///     flags:
+///         -Wreturn-type: !FullPoints
+///         -Wunused-variable: !Partial 0.5
+///     max_deduction: 2
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+pub struct WarningsConfig {
+    /// points lost for any warning which does not have an entry in flags. When not given, warnings
+    /// without an entry in flags are reported but do not lose points.
+    pub points: Option<PointQuantity>,
+    pub flags: Option<HashMap<String, PointQuantity>>,
+    #[serde(default)]
+    pub deduct_per: DeductionUnit,
+    /// the most partial points which can be lost to warnings
+    pub max_deduction: Option<Points>,
+}
+
+impl WarningsConfig {
+    fn points_for(&self, warning: &Warning) -> Option<PointQuantity> {
+        self.flags
+            .as_ref()
+            .and_then(|flags| flags.get(warning.class()))
+            .copied()
+            .or(self.points)
+    }
+
+    /// Calculates the points lost for the given warnings, taking into account the cap.
+    pub fn deduction(&self, warnings: &[Warning]) -> PointQuantity {
+        let mut classes = HashSet::new();
+        let deduction = warnings
+            .iter()
+            .filter(|warning| match self.deduct_per {
+                DeductionUnit::Warning => true,
+                DeductionUnit::Class => classes.insert(warning.class()),
+            })
+            .filter_map(|warning| self.points_for(warning))
+            .fold(PointQuantity::zero(), |acc, curr| acc + curr);
+
+        match (deduction, self.max_deduction) {
+            (PointQuantity::Partial(points), Some(max)) => PointQuantity::Partial(points.min(max)),
+            _ => deduction,
+        }
+    }
+}
+
+/// Formats the warnings into a table with aligned columns. Ex:
+///
+/// file    line  col  flag               message
+/// main.c  12    9    -Wunused-variable  unused variable 'x'
+pub fn warnings_table(warnings: &[Warning]) -> String {
+    let header = ["file", "line", "col", "flag", "message"].map(String::from);
+    let rows = warnings.iter().map(|warning| {
+        let optional = |val: Option<u32>| val.map_or("-".to_string(), |v| v.to_string());
+        [
+            warning.file.clone(),
+            optional(warning.line),
+            optional(warning.column),
+            warning.flag.clone().unwrap_or("-".to_string()),
+            warning.message.clone(),
+        ]
+    });
+
+    let rows = std::iter::once(header).chain(rows).collect::<Vec<_>>();
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GCC_STDERR: &str = r#"main.c: In function 'main':
+main.c:4:9: warning: unused variable 'x' [-Wunused-variable]
+    4 |     int x;
+      |         ^
+main.c:9:1: warning: control reaches end of non-void function [-Wreturn-type]
+    9 | }
+      | ^
+list.c:20:5: error: 'y' undeclared (first use in this function)
+main.c:4:9: warning: unused variable 'x' [-Wunused-variable]
+"#;
+
+    #[test]
+    fn parses_gcc_warnings() {
+        let warnings = parse_warnings(GCC_STDERR);
+        assert_eq!(
+            warnings,
+            vec![
+                Warning {
+                    file: "main.c".to_string(),
+                    line: Some(4),
+                    column: Some(9),
+                    flag: Some("-Wunused-variable".to_string()),
+                    message: "unused variable 'x'".to_string(),
+                },
+                Warning {
+                    file: "main.c".to_string(),
+                    line: Some(9),
+                    column: Some(1),
+                    flag: Some("-Wreturn-type".to_string()),
+                    message: "control reaches end of non-void function".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_warning_without_flag_or_column() {
+        let warnings = parse_warnings("util.h:3: warning: some old style warning");
+        assert_eq!(
+            warnings,
+            vec![Warning {
+                file: "util.h".to_string(),
+                line: Some(3),
+                column: None,
+                flag: None,
+                message: "some old style warning".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn flags_are_parsed_without_their_level() {
+        let warnings = parse_warnings(
+            "main.c:5:14: warning: format '%d' expects argument of type 'int', but argument 2 \
+             has type 'long int' [-Wformat=]\n\
+             main.c:9:13: warning: this statement may fall through [-Wimplicit-fallthrough=3]",
+        );
+        let flags: Vec<_> = warnings.iter().map(|w| w.flag.as_deref()).collect();
+        assert_eq!(flags, [Some("-Wformat"), Some("-Wimplicit-fallthrough")]);
+
+        let config = WarningsConfig {
+            flags: Some(HashMap::from([(
+                "-Wformat".to_string(),
+                PointQuantity::Partial(Points::new(2)),
+            )])),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.deduction(&warnings),
+            PointQuantity::Partial(Points::new(2))
+        );
+    }
+
+    #[test]
+    fn deduction_per_warning_and_class() {
+        let warnings = parse_warnings(
+            "a.c:1:1: warning: unused variable 'x' [-Wunused-variable]\n\
+             a.c:2:1: warning: unused variable 'y' [-Wunused-variable]\n\
+             a.c:3:1: warning: implicit declaration [-Wimplicit-function-declaration]",
+        );
+
+        let mut config = WarningsConfig {
+            points: Some(PointQuantity::Partial(Points::new(0.25))),
+            flags: Some(HashMap::from([(
+                "-Wunused-variable".to_string(),
+                PointQuantity::Partial(Points::new(0.5)),
+            )])),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.deduction(&warnings),
+            PointQuantity::Partial(Points::new(1.25))
+        );
+
+        config.deduct_per = DeductionUnit::Class;
+        assert_eq!(
+            config.deduction(&warnings),
+            PointQuantity::Partial(Points::new(0.75))
+        );
+    }
+
+    #[test]
+    fn deduction_is_capped() {
+        let stderr = (1..=40)
+            .map(|line| format!("a.c:{}:1: warning: unused [-Wunused-variable]", line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let warnings = parse_warnings(&stderr);
+        assert_eq!(warnings.len(), 40);
+
+        let config = WarningsConfig {
+            points: Some(PointQuantity::Partial(Points::new(0.25))),
+            max_deduction: Some(Points::new(1)),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.deduction(&warnings),
+            PointQuantity::Partial(Points::new(1))
+        );
+    }
+
+    #[test]
+    fn full_points_deduction_for_flag() {
+        let warnings = parse_warnings(GCC_STDERR);
+        let config = WarningsConfig {
+            flags: Some(HashMap::from([(
+                "-Wreturn-type".to_string(),
+                PointQuantity::FullPoints,
+            )])),
+            max_deduction: Some(Points::new(1)),
+            ..Default::default()
+        };
+
+        assert_eq!(config.deduction(&warnings), PointQuantity::FullPoints);
+    }
+
+    #[test]
+    fn formats_table() {
+        let table = warnings_table(&parse_warnings(GCC_STDERR));
+        let expected = "file    line  col  flag               message\n\
+                        main.c  4     9    -Wunused-variable  unused variable 'x'\n\
+                        main.c  9     1    -Wreturn-type      control reaches end of non-void function";
+        assert_eq!(table, expected);
+    }
+}
@@ -0,0 +1,342 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use genos::{
+    fs::{filepath, resolve_in},
+    output::{self, Content, RichTextMaker, Section, StatusUpdates, Update},
+    points::PointQuantity,
+    process::{self, Command, ExitStatus, Interaction, ProcessExecutor, PtyConfig, StdinPipe},
+    stage::{StageResult, StageStatus},
+    Executor,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use super::run::get_signal_feedback;
+use crate::vars::{self, Vars};
+
+// give a default timeout of 1 minute. Number chosen arbitrarily.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Runs the student executable while having a scripted conversation with it. The args are
+/// templated, see Vars.
+/// Ex:
+/// interact:
+///     executable: shell
+///     points: !Partial 2
+///     steps:
+///         - !Expect { text: "$ " }
+///         - !Send "echo hi"
+///         - !Expect { regex: "^hi$", timeout_sec: 1 }
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct InteractConfig {
+    pub executable: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// the timeout for the whole run, each expectation has its own timeout as well
+    pub timeout_sec: Option<u64>,
+    pub steps: Interaction,
+    pub points: PointQuantity,
+    /// run the program attached to a terminal instead of pipes, input sent to the program is
+    /// echoed back by the terminal like it would be when typed
+    pub pty: Option<PtyConfig>,
+}
+
+impl InteractConfig {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_sec.map(Duration::from_secs)
+    }
+
+    /// The config with the vars substituted into its templated strings.
+    pub fn expand(&self, vars: &Vars) -> Result<Self, vars::Error> {
+        Ok(Self {
+            args: vars.expand_all(&self.args)?,
+            ..self.clone()
+        })
+    }
+}
+
+/// Interact runs the student executable with an Interaction on stdin and stdout. The points are
+/// lost if the program doesn't respond as expected at any point in the interaction, and the
+/// transcript up to that point is given as feedback.
+pub struct Interact<E> {
+    executor: E,
+    config: InteractConfig,
+    vars: Vars,
+}
+
+impl<E: ProcessExecutor> Interact<E> {
+    pub fn new(executor: E, config: InteractConfig) -> Self {
+        Self {
+            executor,
+            config,
+            vars: Vars::new(),
+        }
+    }
+
+    /// The vars substituted into the args, along with the ws.
+    pub fn vars(mut self, vars: Vars) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    fn get_interact_command(&self, executable: &str, ws: &Path) -> Result<Command> {
+        let args = self.config.expand(&self.vars.with_ws(ws))?.args;
+        let mut cmd = Command::new(executable)
+            .args(&args)
+            .stdin(StdinPipe::Interaction(self.config.steps.clone()))
+            .timeout(self.config.timeout().unwrap_or(DEFAULT_TIMEOUT))
+            .cwd(ws);
+
+        if let Some(pty) = self.config.pty {
+            cmd.set_pty(pty);
+        }
+
+        Ok(cmd)
+    }
+
+    // returns the notes for a failed interaction, or None if it went as expected
+    fn get_interaction_feedback(&self, res: &process::Output) -> Result<Option<Content>> {
+        let transcript = res
+            .transcript
+            .as_ref()
+            .ok_or(anyhow!("Expected interaction to produce a transcript"))?;
+
+        let mut notes = Vec::new();
+        match &res.status {
+            ExitStatus::Timeout(duration) => {
+                notes.push(format!("Runtime error: program timed out after {:?}", duration).into())
+            }
+            ExitStatus::Signal(signal) => notes.push(get_signal_feedback(signal)),
+            _ => (),
+        }
+
+        if let Some(divergence) = transcript.divergence() {
+            notes.push(format!("Interaction failed: {}", divergence).into());
+        }
+
+        if notes.is_empty() {
+            return Ok(None);
+        }
+
+        notes.push(Content::SubSection(
+            Section::new("Transcript").content(transcript.to_string().code()),
+        ));
+
+        Ok(Some(Content::Multiline(notes)))
+    }
+}
+
+#[async_trait]
+impl<E: ProcessExecutor> Executor for Interact<E> {
+    type Output = StageResult;
+
+    async fn run(&self, ws: &Path) -> Result<Self::Output> {
+        let mut section = Section::new("Interact With Program");
+
+        let executable = resolve_in(ws, &self.config.executable)?;
+        if !executable.exists() {
+            return Err(anyhow!(
+                "Could not find student executable at {:?}",
+                executable.display()
+            ));
+        }
+
+        let cmd = self.get_interact_command(filepath(&executable)?, ws)?;
+        section.add_content(("run command", cmd.to_string().code()));
+
+        let res = cmd.run_with(&self.executor).await?;
+
+        let mut update = Update::new_pass("Running interaction");
+        let mut points_lost = PointQuantity::zero();
+        if let Some(notes) = self.get_interaction_feedback(&res)? {
+            update.set_fail(self.config.points);
+            update.set_notes(notes);
+            points_lost += self.config.points;
+        }
+        section.add_content(StatusUpdates::default().update(update));
+
+        // same as the run stage, a program which crashes or hangs can't be graded any further
+        let status = match res.status.completed() {
+            true => StageStatus::Continue { points_lost },
+            false => StageStatus::UnrecoverableFailure,
+        };
+
+        Ok(StageResult::new(
+            status,
+            Some(output::Output::new().section(section)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use genos::{
+        output::Contains,
+        points::Points,
+        process::ShellExecutor,
+        test_util::{MockDir, MockProcessExecutor},
+    };
+
+    use super::*;
+
+    const REPL: &str = r#"#!/bin/sh
+        if [ -t 0 ]; then
+            printf 'tty'
+        fi
+        printf '> '
+        while read line; do
+            if [ "$line" = "quit" ]; then
+                exit 0
+            fi
+            printf 'echo: %s\n> ' "$line"
+        done
+    "#;
+
+    fn make_ws() -> MockDir {
+        let ws = MockDir::new().file(("repl", REPL));
+        let path = ws.path_from_root("repl");
+        std::fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        ws
+    }
+
+    fn make_config(steps: &str) -> InteractConfig {
+        InteractConfig {
+            executable: "./repl".to_string(),
+            args: Vec::new(),
+            timeout_sec: None,
+            steps: serde_yaml::from_str(steps).unwrap(),
+            points: PointQuantity::Partial(Points::new(2)),
+            pty: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn interaction_passes() {
+        let ws = make_ws();
+        let config = make_config(
+            r#"
+            - !Expect { text: "> " }
+            - !Send hello
+            - !Expect { regex: "^echo: hello$" }
+            - !Send quit
+            "#,
+        );
+
+        let res = Interact::new(ShellExecutor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::zero()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn interaction_fails_with_transcript() {
+        let ws = make_ws();
+        let config = make_config(
+            r#"
+            - !Expect { text: "> " }
+            - !Send hello
+            - !Expect { regex: "^HELLO$", timeout_sec: 1 }
+            - !Send quit
+            "#,
+        );
+
+        let res = Interact::new(ShellExecutor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(2))
+            }
+        );
+
+        let output = res.output.unwrap();
+        assert!(output.contains("expected output matching /^HELLO$/"));
+        assert!(output.contains("> hello\n"));
+        assert!(output.contains("echo: hello"));
+    }
+
+    #[tokio::test]
+    async fn interaction_in_pty() {
+        let ws = make_ws();
+        let mut config = make_config(
+            r#"
+            - !Expect { text: "tty> " }
+            - !Send hello
+            - !Expect { regex: "^echo: hello" }
+            - !Send quit
+            "#,
+        );
+        config.pty = Some(PtyConfig::default());
+
+        let res = Interact::new(ShellExecutor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::zero()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_transcript_is_system_error() {
+        let ws = make_ws();
+        let config = make_config("[]");
+        let executor = MockProcessExecutor::with_responses([Ok(
+            process::Output::from_exit_status(ExitStatus::Ok),
+        )]);
+
+        Interact::new(executor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn executable_outside_ws_is_error() {
+        let outside = make_ws();
+        let ws = MockDir::new();
+        std::os::unix::fs::symlink(outside.path_from_root("repl"), ws.path_from_root("repl"))
+            .unwrap();
+        let executor = MockProcessExecutor::with_responses([]);
+
+        Interact::new(executor, make_config("[]"))
+            .run(ws.root.path())
+            .await
+            .unwrap_err();
+    }
+
+    #[test]
+    fn get_interact_command_vars() {
+        let ws = make_ws();
+        let mut config = make_config("[]");
+        config.args = vec![
+            "--id".to_string(),
+            "${TEST_ID}".to_string(),
+            "${WS}".to_string(),
+        ];
+
+        let cmd = Interact::new(ShellExecutor, config)
+            .vars(Vars::new().var("TEST_ID", "3"))
+            .get_interact_command("repl", ws.root.path())
+            .unwrap();
+
+        assert_eq!(cmd.args, ["--id", "3", filepath(ws.root.path()).unwrap()]);
+    }
+}
@@ -1,24 +1,33 @@
-use std::{path::Path, time::Duration};
+use std::{collections::HashMap, iter, path::Path, time::Duration};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use genos::{
+    fs::{filepath, resolve_in},
     gs::running_in_gs,
     output::{self, Output, RichTextMaker, Section, StatusUpdates, Update},
     points::PointQuantity,
     process::{
-        self, is_program_in_path, Command, ExitStatus, ProcessExecutor, SignalType, StdinPipe,
+        is_program_in_path, Command, ExitStatus, ProcessExecutor, PtyConfig, ScheduledSignal,
+        SentSignal, SignalName, SignalType, StdinPipe, SyscallPolicy,
     },
     stage::{StageResult, StageStatus},
     Executor,
 };
+use schemars::JsonSchema;
 use serde::Deserialize;
 use tracing::debug;
 
+use crate::{
+    config::TestConfigValidationError,
+    vars::{self, Vars},
+};
+
 // give a default timeout of 1 minute. Number chosen arbitrarily.
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
 
-#[derive(Debug, Default, Clone, Deserialize)]
+/// The args, io files and env of the program are templated, see Vars.
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
 pub struct RunConfig {
     pub args: Vec<String>,
     pub executable: String,
@@ -27,24 +36,94 @@ pub struct RunConfig {
     pub stderr: Option<String>,
     pub stdin: Option<String>,
     pub return_code: Option<ReturnCodeConfig>,
+    /// the signal the program is expected to be terminated by, which should be one of the signals
+    /// it is sent.
+    /// Ex: signal: {expected: SIGINT, points: !FullPoints}
+    pub signal: Option<SignalConfig>,
     pub disable_garbage_memory: Option<bool>,
+    /// run the program attached to a terminal instead of pipes
+    pub pty: Option<PtyConfig>,
+    /// commands piped into the program, each one is the program followed by its args. stdin is
+    /// given to the first command in the pipeline.
+    /// Ex: pipe_in: [[cat, input.txt], [sort]]
+    pub pipe_in: Option<Vec<Vec<String>>>,
+    /// commands the program's output is piped through, stdout is saved from the last one.
+    /// Ex: pipe_out: [[uniq, -c]]
+    pub pipe_out: Option<Vec<Vec<String>>>,
+    /// signals sent to the program while it runs, see ScheduledSignal.
+    /// Ex:
+    /// signals:
+    ///     - signal: SIGINT
+    ///       after_ms: 500
+    pub signals: Option<Vec<ScheduledSignal>>,
+    /// the syscalls the program may make, see SyscallPolicy. The program isn't run under valgrind
+    /// when this is set, since the policy would apply to valgrind as well.
+    /// Ex: seccomp: !Deny [fork, vfork, clone, clone3]
+    pub seccomp: Option<SyscallPolicy>,
+    /// the environment of the program, which otherwise has an empty one.
+    /// Ex: env: {DATA_DIR: "${STATIC}/data"}
+    #[serde(default)]
+    pub env: HashMap<String, String>,
 }
 
 impl RunConfig {
     pub fn timeout(&self) -> Option<Duration> {
-        self.timeout_sec.map(|val| Duration::from_secs(val))
+        self.timeout_sec.map(Duration::from_secs)
+    }
+
+    /// Checks that the program isn't run in a pipeline with something which needs it to be run on
+    /// its own.
+    pub fn check_pipeline(&self) -> Result<(), TestConfigValidationError> {
+        if self.pipe_in.is_none() && self.pipe_out.is_none() {
+            return Ok(());
+        }
+        if self.pty.is_some() {
+            return Err(TestConfigValidationError::PipelineWith("a pty"));
+        }
+        if self.signals.is_some() {
+            return Err(TestConfigValidationError::PipelineWith("signals"));
+        }
+        if self.seccomp.is_some() {
+            return Err(TestConfigValidationError::PipelineWith("a seccomp policy"));
+        }
+        Ok(())
+    }
+
+    /// The config with the vars substituted into its templated strings.
+    pub fn expand(&self, vars: &Vars) -> Result<Self, vars::Error> {
+        let expand_file =
+            |file: &Option<String>| file.as_deref().map(|f| vars.expand(f)).transpose();
+        Ok(Self {
+            args: vars.expand_all(&self.args)?,
+            stdout: expand_file(&self.stdout)?,
+            stderr: expand_file(&self.stderr)?,
+            stdin: expand_file(&self.stdin)?,
+            env: self
+                .env
+                .iter()
+                .map(|(key, val)| Ok((key.clone(), vars.expand(val)?)))
+                .collect::<Result<_, vars::Error>>()?,
+            ..self.clone()
+        })
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub struct ReturnCodeConfig {
     pub expected: i32,
     pub points: PointQuantity,
 }
 
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct SignalConfig {
+    pub expected: SignalName,
+    pub points: PointQuantity,
+}
+
 pub struct Run<E> {
     executor: E,
     config: RunConfig,
+    vars: Vars,
 }
 
 impl<E> Run<E>
@@ -59,53 +138,118 @@ where
                 "expected valgrind to exist when in gs"
             );
         }
-        Self { executor, config }
+        Self {
+            executor,
+            config,
+            vars: Vars::new(),
+        }
     }
 
-    fn get_run_command(&self, ws: &Path) -> Command {
-        let mut cmd = if !self.config.disable_garbage_memory.unwrap_or(false)
-            && is_program_in_path("valgrind")
-        {
-            Command::new("valgrind")
-                .arg("--log-file=valgrind.log")
-                .arg("--malloc-fill=0xFF")
-                .arg("--free-fill=0xAA")
-                .arg(&self.config.executable)
-                .args(&self.config.args)
-        } else {
-            Command::new(&self.config.executable).args(&self.config.args)
-        };
+    /// The vars substituted into the config, along with the ws.
+    pub fn vars(mut self, vars: Vars) -> Self {
+        self.vars = vars;
+        self
+    }
 
-        if let Some(stdout_file) = &self.config.stdout {
-            cmd.set_stdout(stdout_file);
+    fn get_run_command(&self, ws: &Path) -> Result<Command> {
+        let config = self.config.expand(&self.vars.with_ws(ws))?;
+        // the program is spawned from its path in the ws, so it's the file which was checked for
+        let executable = resolve_in(ws, &config.executable)?;
+        let mut program = Self::get_program_command(&config, filepath(&executable)?);
+        for (key, val) in &config.env {
+            program = program.env(key, val);
+        }
+        for signal in config.signals.iter().flatten() {
+            program.add_signal(signal.clone());
+        }
+        if let Some(policy) = &config.seccomp {
+            program.set_seccomp(policy.clone());
+        }
+        let pipe_in = config.pipe_in.clone().unwrap_or_default();
+        let pipe_out = config.pipe_out.clone().unwrap_or_default();
+
+        let mut commands = pipe_in
+            .iter()
+            .map(|args| pipe_command(args))
+            .collect::<Result<Vec<_>>>()?;
+        commands.push(program);
+        for args in &pipe_out {
+            commands.push(pipe_command(args)?);
         }
 
-        if let Some(stderr_file) = &self.config.stderr {
-            cmd.set_stderr(stderr_file);
+        let mut commands = commands.into_iter().map(|cmd| cmd.cwd(ws));
+        let mut cmd = commands.next().unwrap();
+        for next in commands {
+            cmd.add_pipe(next);
         }
 
-        if let Some(stdin_file) = &self.config.stdin {
-            cmd.set_stdin(StdinPipe::Path(stdin_file.into()));
+        // the io files are opened by the grader and not the program, so they can't be allowed to
+        // lead outside of the ws. They're left relative to the cwd so the run command reads well.
+        let in_ws = |file: &String| resolve_in(ws, file).map(|_| file.clone());
+
+        if let Some(stdout_file) = &config.stdout {
+            cmd.set_stdout(in_ws(stdout_file)?);
+        }
+
+        if let Some(stderr_file) = &config.stderr {
+            cmd.set_stderr(in_ws(stderr_file)?);
+        }
+
+        if let Some(stdin_file) = &config.stdin {
+            cmd.set_stdin(StdinPipe::Path(in_ws(stdin_file)?.into()));
+        }
+
+        if let Some(pty) = config.pty {
+            cmd.set_pty(pty);
         }
 
-        cmd.set_timeout(self.config.timeout().unwrap_or(DEFAULT_TIMEOUT));
-        cmd.set_cwd(ws);
+        cmd.set_timeout(config.timeout().unwrap_or(DEFAULT_TIMEOUT));
 
-        cmd
+        Ok(cmd)
     }
 
-    fn get_failed_run_notes(&self, res: &process::Output) -> output::Content {
-        match &res.status {
+    // the command which runs the student program, without any of the io
+    fn get_program_command(config: &RunConfig, executable: &str) -> Command {
+        let use_valgrind = !config.disable_garbage_memory.unwrap_or(false)
+            && config.seccomp.is_none()
+            && is_program_in_path("valgrind");
+        if use_valgrind {
+            Command::new("valgrind")
+                .arg("--log-file=valgrind.log")
+                .arg("--malloc-fill=0xFF")
+                .arg("--free-fill=0xAA")
+                .arg(executable)
+                .args(&config.args)
+        } else {
+            Command::new(executable).args(&config.args)
+        }
+    }
+
+    fn get_failed_run_notes(&self, status: &ExitStatus) -> output::Content {
+        match status {
             ExitStatus::Timeout(duration) => {
                 format!("Runtime error: program timed out after {:?}", duration).into()
             }
             ExitStatus::Signal(signal) => get_signal_feedback(signal),
+            ExitStatus::ForbiddenSyscall(syscall) => format!(
+                "Runtime error: Your submission made the system call `{}`, which isn't allowed \
+                 in this assignment",
+                syscall
+            )
+            .into(),
             _ => unreachable!(),
         }
     }
 }
 
-fn get_signal_feedback(signal: &SignalType) -> output::Content {
+fn pipe_command(args: &[String]) -> Result<Command> {
+    let (program, args) = args
+        .split_first()
+        .ok_or(anyhow!("Expected piped command to have a program"))?;
+    Ok(Command::new(program).args(args))
+}
+
+pub fn get_signal_feedback(signal: &SignalType) -> output::Content {
     match signal {
         SignalType::Abort => {
             "Runtime error: Your submission exited with error code 6 (abort signal)".into()
@@ -125,6 +269,11 @@ fn get_signal_feedback(signal: &SignalType) -> output::Content {
                 .join("\n")
                 .into()
         }
+        SignalType::Other(_) => format!(
+            "Runtime error: Your submission was terminated by {}",
+            signal
+        )
+        .into(),
     }
 }
 
@@ -141,7 +290,7 @@ where
         let mut points_lost = PointQuantity::zero();
         debug!("running program");
 
-        let executable = ws.join(&self.config.executable);
+        let executable = resolve_in(ws, &self.config.executable)?;
         if !executable.exists() {
             return Err(anyhow!(
                 "Could not find student executable at {:?}",
@@ -149,15 +298,35 @@ where
             ));
         }
 
-        let cmd = self.get_run_command(ws);
+        let cmd = self.get_run_command(ws)?;
         section.add_content(("run command", format!("{}", cmd).code()));
 
         let res = cmd.run_with(&self.executor).await?;
 
-        if !res.status.completed() {
+        if let Some(signals) = &self.config.signals {
+            let sent = res
+                .signals_sent
+                .iter()
+                .map(|sent| sent.to_string())
+                .collect::<Vec<_>>();
+            section.add_content((
+                format!("signals sent ({} of {})", sent.len(), signals.len()),
+                sent.join("\n").code(),
+            ));
+        }
+
+        // any command in a pipeline crashing or hanging fails the run. The program being
+        // terminated by a signal it was sent is how the test was set up to end, so it's graded
+        // like any other exit instead.
+        let failed_status = iter::once(&res.status)
+            .filter(|status| !killed_by_sent_signal(status, &res.signals_sent))
+            .chain(res.pipeline_statuses.iter())
+            .find(|status| !status.completed());
+
+        if let Some(status) = failed_status {
             run_status_updates.add_update(
                 Update::new_fail("Running program", PointQuantity::FullPoints)
-                    .notes(self.get_failed_run_notes(&res)),
+                    .notes(self.get_failed_run_notes(status)),
             );
             section.add_content(run_status_updates);
             return Ok(StageResult::new(
@@ -169,31 +338,66 @@ where
         run_status_updates.add_update(Update::new_pass("Running program"));
 
         if let Some(rc_config) = &self.config.return_code {
-            let rc = res
-                .status
-                .exit_code()
-                .expect("Expected function to get a status from a command which completed");
-
-            if rc != rc_config.expected {
-                run_status_updates.add_update(
-                    Update::new_fail("Checking return code", PointQuantity::FullPoints)
-                        .notes(format!("Expected {}, but found {}", rc_config.expected, rc)),
-                );
-                section.add_content(run_status_updates);
-                points_lost += rc_config.points;
-                return Ok(StageResult::new_continue(points_lost)
-                    .with_output(output::Output::new().section(section)));
-            }
+            let failure = match &res.status {
+                ExitStatus::Signal(signal) => Some(format!(
+                    "Expected {}, but the program was terminated by {}",
+                    rc_config.expected, signal
+                )),
+                status => status
+                    .exit_code()
+                    .filter(|rc| *rc != rc_config.expected)
+                    .map(|rc| format!("Expected {}, but found {}", rc_config.expected, rc)),
+            };
+
+            run_status_updates.add_update(match failure {
+                Some(notes) => {
+                    points_lost += rc_config.points;
+                    Update::new_fail("Checking return code", rc_config.points).notes(notes)
+                }
+                None => Update::new_pass("Checking return code"),
+            });
+        }
 
-            run_status_updates.add_update(Update::new_pass("Checking return code"));
-            section.add_content(run_status_updates);
+        if let Some(signal_config) = &self.config.signal {
+            let expected = SignalType::from(signal_config.expected);
+            let failure = match &res.status {
+                ExitStatus::Signal(signal) if *signal == expected => None,
+                ExitStatus::Signal(signal) => Some(format!(
+                    "Expected the program to be terminated by {}, but it was terminated by {}",
+                    expected, signal
+                )),
+                status => Some(format!(
+                    "Expected the program to be terminated by {}, but it exited with code {}",
+                    expected,
+                    status.exit_code().unwrap_or_default()
+                )),
+            };
+
+            run_status_updates.add_update(match failure {
+                Some(notes) => {
+                    points_lost += signal_config.points;
+                    Update::new_fail("Checking signal", signal_config.points).notes(notes)
+                }
+                None => Update::new_pass("Checking signal"),
+            });
         }
 
+        section.add_content(run_status_updates);
         Ok(StageResult::new_continue(points_lost)
             .with_output(output::Output::new().section(section)))
     }
 }
 
+// whether the program was terminated by one of the signals scheduled for it
+fn killed_by_sent_signal(status: &ExitStatus, sent: &[SentSignal]) -> bool {
+    match status {
+        ExitStatus::Signal(signal) => sent
+            .iter()
+            .any(|sent| SignalType::from(sent.signal) == *signal),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::env;
@@ -201,6 +405,8 @@ mod tests {
     use genos::{
         fs::filepath,
         output::Contains,
+        points::Points,
+        process,
         test_util::{MockDir, MockProcessExecutor},
     };
 
@@ -369,8 +575,8 @@ mod tests {
 
         let run = Run::new(executor, config);
 
-        let cmd = run.get_run_command(ws.path());
-        let expected = Command::new("bin/exec");
+        let cmd = run.get_run_command(ws.path()).unwrap();
+        let expected = Command::new(filepath(&ws.path().join("bin/exec")).unwrap());
         assert_eq!(cmd.to_string(), expected.to_string());
     }
 
@@ -388,8 +594,8 @@ mod tests {
 
         let run = Run::new(executor, config);
 
-        let cmd = run.get_run_command(ws.path());
-        let expected = Command::new("bin/exec")
+        let cmd = run.get_run_command(ws.path()).unwrap();
+        let expected = Command::new(filepath(&ws.path().join("bin/exec")).unwrap())
             .stdout("stdout")
             .stdin(StdinPipe::Path("stdin".into()))
             .stderr("stderr");
@@ -416,15 +622,276 @@ mod tests {
 
         let run = Run::new(executor, config);
 
-        let cmd = run.get_run_command(ws.path());
+        let cmd = run.get_run_command(ws.path()).unwrap();
         let expected = Command::new("valgrind")
             .arg("--log-file=valgrind.log")
             .arg("--malloc-fill=0xFF")
             .arg("--free-fill=0xAA")
-            .arg("bin/exec")
+            .arg(filepath(&ws.path().join("bin/exec")).unwrap())
             .arg("-t")
             .arg("-u");
 
         assert_eq!(cmd.to_string(), expected.to_string());
     }
+
+    #[test]
+    fn get_run_command_pipeline() {
+        let config = RunConfig {
+            executable: "bin/filter".to_string(),
+            stdin: Some("input.txt".to_string()),
+            stdout: Some("stdout".to_string()),
+            pipe_in: Some(vec![vec!["sort".to_string()]]),
+            pipe_out: Some(vec![vec!["uniq".to_string(), "-c".to_string()]]),
+            disable_garbage_memory: Some(true),
+            ..Default::default()
+        };
+        let ws = tempfile::tempdir().unwrap();
+        let executor = MockProcessExecutor::with_responses([]);
+
+        let cmd = Run::new(executor, config)
+            .get_run_command(ws.path())
+            .unwrap();
+        assert_eq!(
+            cmd.to_string(),
+            format!(
+                "sort < input.txt | {}/bin/filter | uniq -c > stdout",
+                filepath(ws.path()).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn get_run_command_vars() {
+        let config: RunConfig = serde_yaml::from_str(
+            r#"
+            args: [--data, "${DATA}", "--out=${WS}/out"]
+            executable: exec
+            stdout: "stdout_${TEST_ID}"
+            env: {LOG: "${WS}/log"}
+            disable_garbage_memory: true
+            "#,
+        )
+        .unwrap();
+        let ws = tempfile::tempdir().unwrap();
+        let ws_path = filepath(ws.path()).unwrap();
+        let vars = Vars::new().var("DATA", "/data").var("TEST_ID", "3");
+
+        let cmd = Run::new(MockProcessExecutor::with_responses([]), config.clone())
+            .vars(vars)
+            .get_run_command(ws.path())
+            .unwrap();
+
+        assert_eq!(
+            cmd.to_string(),
+            format!(
+                "LOG={0}/log {0}/exec --data /data --out={0}/out > stdout_3",
+                ws_path
+            )
+        );
+
+        // the vars are strict, so none of them being given is an error
+        assert!(Run::new(MockProcessExecutor::with_responses([]), config)
+            .get_run_command(ws.path())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn pipeline_crash_is_unrecoverable() {
+        let config = RunConfig {
+            executable: "exec".to_string(),
+            pipe_out: Some(vec![vec!["sort".to_string()]]),
+            ..Default::default()
+        };
+        let ws = MockDir::new().file(("exec", "content"));
+        let mut output = process::Output::from_exit_status(ExitStatus::Ok);
+        output.pipeline_statuses = vec![ExitStatus::Signal(SignalType::SegFault), ExitStatus::Ok];
+        let executor = MockProcessExecutor::with_responses([Ok(output)]);
+
+        let res = Run::new(executor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        assert!(res.output.unwrap().contains("segmentation fault"));
+    }
+
+    #[test]
+    fn get_run_command_signals() {
+        let config: RunConfig = serde_yaml::from_str(
+            r#"
+            args: []
+            executable: exec
+            signals:
+                - signal: SIGTSTP
+                  on_output: "prompt> "
+                - signal: SIGCONT
+                  after_ms: 200
+            "#,
+        )
+        .unwrap();
+        let ws = MockDir::new();
+
+        let cmd = Run::new(MockProcessExecutor::with_responses([]), config)
+            .get_run_command(ws.root.path())
+            .unwrap();
+
+        assert_eq!(cmd.signals.len(), 2);
+        assert_eq!(cmd.signals[1].signal, SignalName::SIGCONT);
+    }
+
+    // the output of a program which was sent SIGINT and then terminated by the given signal
+    fn killed_after_sigint(signal: SignalType) -> process::Output {
+        let mut output = process::Output::from_exit_status(ExitStatus::Signal(signal));
+        output.signals_sent = vec![SentSignal {
+            signal: SignalName::SIGINT,
+            elapsed: Duration::from_millis(501),
+        }];
+        output
+    }
+
+    fn sigint_config() -> RunConfig {
+        RunConfig {
+            executable: "exec".to_string(),
+            signals: Some(vec![ScheduledSignal::after(
+                SignalName::SIGINT,
+                Duration::from_millis(500),
+            )]),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn killed_by_scheduled_signal() {
+        let config = RunConfig {
+            signal: Some(SignalConfig {
+                expected: SignalName::SIGINT,
+                points: PointQuantity::FullPoints,
+            }),
+            ..sigint_config()
+        };
+        let ws = MockDir::new().file(("exec", "content"));
+        let executor =
+            MockProcessExecutor::with_responses([Ok(killed_after_sigint(SignalType::Other(2)))]);
+
+        let res = Run::new(executor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::zero()
+            }
+        );
+        let output = res.output.unwrap();
+        assert!(output.contains("signals sent (1 of 1)"));
+        assert!(output.contains("SIGINT after 501ms"));
+    }
+
+    #[tokio::test]
+    async fn wrong_signal_loses_points() {
+        let config = RunConfig {
+            signal: Some(SignalConfig {
+                expected: SignalName::SIGINT,
+                points: PointQuantity::Partial(Points::new(2)),
+            }),
+            ..sigint_config()
+        };
+        let ws = MockDir::new().file(("exec", "content"));
+        // the program handled the signal and exited on its own
+        let mut output = killed_after_sigint(SignalType::Other(2));
+        output.status = ExitStatus::Ok;
+        let executor = MockProcessExecutor::with_responses([Ok(output)]);
+
+        let res = Run::new(executor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(2))
+            }
+        );
+        assert!(res.output.unwrap().contains(
+            "Expected the program to be terminated by SIGINT, but it exited with code 0"
+        ));
+    }
+
+    #[tokio::test]
+    async fn return_code_of_scheduled_signal_death() {
+        let config = RunConfig {
+            return_code: Some(ReturnCodeConfig {
+                expected: 0,
+                points: PointQuantity::FullPoints,
+            }),
+            ..sigint_config()
+        };
+        let ws = MockDir::new().file(("exec", "content"));
+        let executor =
+            MockProcessExecutor::with_responses([Ok(killed_after_sigint(SignalType::Other(2)))]);
+
+        let res = Run::new(executor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::FullPoints
+            }
+        );
+        assert!(res
+            .output
+            .unwrap()
+            .contains("Expected 0, but the program was terminated by SIGINT"));
+    }
+
+    #[tokio::test]
+    async fn unexpected_signal_is_unrecoverable() {
+        let ws = MockDir::new().file(("exec", "content"));
+        let executor =
+            MockProcessExecutor::with_responses([Ok(killed_after_sigint(SignalType::SegFault))]);
+
+        let res = Run::new(executor, sigint_config())
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        assert!(res.output.unwrap().contains("segmentation fault"));
+    }
+
+    #[tokio::test]
+    async fn forbidden_syscall_is_unrecoverable() {
+        let config: RunConfig = serde_yaml::from_str(
+            r#"
+            args: []
+            executable: exec
+            seccomp: !Deny [fork, clone]
+            "#,
+        )
+        .unwrap();
+        let ws = MockDir::new().file(("exec", "content"));
+        let executor = MockProcessExecutor::with_responses([Ok(
+            process::Output::from_exit_status(ExitStatus::ForbiddenSyscall("clone".to_string())),
+        )]);
+        let inner = executor.inner.clone();
+
+        let res = Run::new(executor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        assert!(res.output.unwrap().contains("made the system call `clone`"));
+
+        let cmd = &inner.lock().unwrap().commands[0];
+        assert_eq!(cmd.program, filepath(&ws.path_from_root("exec")).unwrap());
+        assert!(cmd.seccomp.is_some());
+    }
 }
@@ -0,0 +1,437 @@
+use std::{collections::HashMap, env, path::Path, path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use genos::{
+    fs::{filepath, ResourceLocator},
+    output::{self, Content, RichTextMaker, Section, StatusUpdates, Update},
+    points::PointQuantity,
+    process::{Command, ExitStatus, ProcessExecutor},
+    stage::{StageResult, StageStatus},
+    Executor,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::vars::{self, Vars};
+
+// give a default timeout of 1 minute. Number chosen arbitrarily.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The ws the script is run in is also given in the environment, for scripts which change
+/// directory.
+pub const WS_ENV: &str = "GENOS_WS";
+pub const SUBMISSION_ENV: &str = "GENOS_SUBMISSION";
+pub const TEST_ID_ENV: &str = "GENOS_TEST_ID";
+
+/// Runs an instructor provided script from the test resources. The args and env are templated,
+/// see Vars.
+/// Ex:
+/// script:
+///     path: grade.py
+///     args: [--verbose, "${TEST_ID}"]
+///     env: {EXPECTED_DIR: "${STATIC}/expected"}
+///     timeout_sec: 30
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+pub struct ScriptConfig {
+    pub path: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// added to the environment the script is given
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub timeout_sec: Option<u64>,
+}
+
+impl ScriptConfig {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_sec.map(Duration::from_secs)
+    }
+
+    /// The config with the vars substituted into its templated strings.
+    pub fn expand(&self, vars: &Vars) -> Result<Self, vars::Error> {
+        Ok(Self {
+            args: vars.expand_all(&self.args)?,
+            env: self
+                .env
+                .iter()
+                .map(|(key, val)| Ok((key.clone(), vars.expand(val)?)))
+                .collect::<Result<_, vars::Error>>()?,
+            ..self.clone()
+        })
+    }
+}
+
+/// The JSON document the script prints to stdout, which maps onto a StageResult. Every field is
+/// optional, so a script which prints `{}` passes without any feedback.
+/// Ex:
+/// {
+///     "updates": [
+///         { "description": "Checking output" },
+///         {
+///             "description": "Checking edge cases",
+///             "points_lost": { "Partial": 1.5 },
+///             "notes": ["empty input crashed", { "code": "$ ./a.out < empty" }]
+///         }
+///     ],
+///     "sections": [{ "header": "Script Notes", "content": ["some extra feedback"] }]
+/// }
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptReport {
+    /// stop running the test and lose full points
+    #[serde(default)]
+    pub unrecoverable: bool,
+    /// defaults to the sum of points lost in the updates
+    pub points_lost: Option<PointQuantity>,
+    #[serde(default)]
+    pub updates: Vec<ScriptUpdate>,
+    #[serde(default)]
+    pub sections: Vec<ScriptSection>,
+}
+
+/// Maps to an output::Update. The update passes unless points_lost is given.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptUpdate {
+    pub description: String,
+    pub points_lost: Option<PointQuantity>,
+    #[serde(default)]
+    pub notes: Vec<ScriptContent>,
+}
+
+/// Maps to an output::Section
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptSection {
+    pub header: String,
+    #[serde(default)]
+    pub content: Vec<ScriptContent>,
+}
+
+/// Maps to output::Content. Plain strings are text, `{"code": ...}` is a code block and
+/// `{"header": ..., "content": [...]}` is a subsection.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ScriptContent {
+    Text(String),
+    Code { code: String },
+    SubSection(ScriptSection),
+}
+
+impl From<ScriptContent> for Content {
+    fn from(value: ScriptContent) -> Self {
+        match value {
+            ScriptContent::Text(text) => text.into(),
+            ScriptContent::Code { code } => code.code().into(),
+            ScriptContent::SubSection(section) => Content::SubSection(section.into()),
+        }
+    }
+}
+
+impl From<ScriptSection> for Section {
+    fn from(value: ScriptSection) -> Self {
+        value
+            .content
+            .into_iter()
+            .fold(Section::new(value.header), |section, content| {
+                section.content(content)
+            })
+    }
+}
+
+impl From<ScriptReport> for StageResult {
+    fn from(report: ScriptReport) -> Self {
+        let mut output = output::Output::new();
+        let mut status_updates = StatusUpdates::default();
+        let mut updates_points_lost = PointQuantity::zero();
+        let has_updates = !report.updates.is_empty();
+
+        for script_update in report.updates {
+            let mut update = Update::new_pass(&script_update.description);
+            if let Some(points_lost) = script_update.points_lost {
+                update.set_fail(points_lost);
+                updates_points_lost += points_lost;
+            }
+
+            if !script_update.notes.is_empty() {
+                update.set_notes(Content::Multiline(
+                    script_update.notes.into_iter().map(Content::from).collect(),
+                ));
+            }
+
+            status_updates.add_update(update);
+        }
+
+        if has_updates {
+            output.add_section(Section::new("Test Script").content(status_updates));
+        }
+
+        for script_section in report.sections {
+            output.add_section(script_section);
+        }
+
+        let status = match report.unrecoverable {
+            true => StageStatus::UnrecoverableFailure,
+            false => StageStatus::Continue {
+                points_lost: report.points_lost.unwrap_or(updates_points_lost),
+            },
+        };
+
+        StageResult::new(status, Some(output))
+    }
+}
+
+/// Script is an escape hatch for tests which can't be expressed with the built in stages. The
+/// script is run in the ws and reports the result of the test by printing a ScriptReport as JSON
+/// to stdout.
+///
+/// A script which exits with a non zero code or which prints an invalid report is an error with
+/// the test, not the submission, and is treated as a system error.
+pub struct Script<E> {
+    executor: E,
+    config: ScriptConfig,
+    script: PathBuf,
+    envs: HashMap<String, String>,
+    vars: Vars,
+}
+
+impl<E: ProcessExecutor> Script<E> {
+    pub fn new<F: ResourceLocator>(executor: E, config: ScriptConfig, finder: &F) -> Result<Self> {
+        let script = finder.find(&config.path)?;
+        Ok(Self {
+            executor,
+            config,
+            script,
+            envs: HashMap::new(),
+            vars: Vars::new(),
+        })
+    }
+
+    /// The vars substituted into the args and env of the config, along with the ws.
+    pub fn vars(mut self, vars: Vars) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    /// Add an environment variable which is given to the script when it is run.
+    pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, val: V) -> Self {
+        self.envs.insert(key.into(), val.into());
+        self
+    }
+
+    fn get_script_command(&self, ws: &Path) -> Result<Command> {
+        let config = self.config.expand(&self.vars.with_ws(ws))?;
+        let mut cmd = Command::new(filepath(&self.script)?)
+            .args(&config.args)
+            .env(WS_ENV, filepath(ws)?)
+            .cwd(ws)
+            .timeout(self.config.timeout().unwrap_or(DEFAULT_TIMEOUT));
+
+        // the script is written by course staff, so it gets the same PATH as the grader in order
+        // to find its interpreter.
+        if let Ok(path) = env::var("PATH") {
+            cmd = cmd.env("PATH", path);
+        }
+
+        for (key, val) in self.envs.iter().chain(&config.env) {
+            cmd = cmd.env(key, val);
+        }
+
+        Ok(cmd)
+    }
+}
+
+#[async_trait]
+impl<E: ProcessExecutor> Executor for Script<E> {
+    type Output = StageResult;
+
+    async fn run(&self, ws: &Path) -> Result<Self::Output> {
+        let res = self
+            .get_script_command(ws)?
+            .run_with(&self.executor)
+            .await?;
+
+        match &res.status {
+            ExitStatus::Ok => (),
+            // the script most likely timed out while running student code
+            ExitStatus::Timeout(duration) => {
+                let update = Update::new_fail("Running test script", PointQuantity::FullPoints)
+                    .notes(format!("Test timed out after {:?}", duration));
+                let section =
+                    Section::new("Test Script").content(StatusUpdates::default().update(update));
+
+                return Ok(StageResult::new_unrecoverable_failure()
+                    .with_output(output::Output::new().section(section)));
+            }
+            status => {
+                return Err(anyhow!(
+                    "Test script {} failed with status {:?}: {}",
+                    self.script.display(),
+                    status,
+                    res.stderr
+                ))
+            }
+        }
+
+        let report: ScriptReport = serde_json::from_str(&res.stdout).context(format!(
+            "Test script {} printed an invalid report",
+            self.script.display()
+        ))?;
+
+        Ok(report.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use genos::{
+        output::Contains,
+        points::Points,
+        process::{self, ShellExecutor},
+        test_util::{MockDir, MockProcessExecutor},
+    };
+
+    use super::*;
+
+    #[test]
+    fn report_to_stage_result() {
+        let report: ScriptReport = serde_json::from_str(
+            r#"{
+                "updates": [
+                    { "description": "Checking output" },
+                    {
+                        "description": "Checking edge cases",
+                        "points_lost": { "Partial": 1.5 },
+                        "notes": ["empty input crashed", { "code": "$ ./a.out < empty" }]
+                    },
+                    { "description": "Checking style", "points_lost": { "Partial": 0.25 } }
+                ],
+                "sections": [
+                    {
+                        "header": "Script Notes",
+                        "content": [{ "header": "Details", "content": ["more details"] }]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let res: StageResult = report.into();
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(1.75))
+            }
+        );
+
+        let output = res.output.unwrap();
+        assert!(output.contains("empty input crashed"));
+        assert!(output.contains("$ ./a.out < empty"));
+        assert!(output.contains("more details"));
+    }
+
+    #[test]
+    fn report_points_lost_overrides_updates() {
+        let report: ScriptReport =
+            serde_json::from_str(r#"{ "points_lost": "FullPoints" }"#).unwrap();
+        let res: StageResult = report.into();
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::FullPoints
+            }
+        );
+
+        let report: ScriptReport = serde_json::from_str(r#"{ "unrecoverable": true }"#).unwrap();
+        let res: StageResult = report.into();
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+    }
+
+    #[test]
+    fn report_rejects_unknown_fields() {
+        serde_json::from_str::<ScriptReport>(r#"{ "point_lost": "FullPoints" }"#).unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn script_failure_is_system_error() {
+        let test_dir = MockDir::new().file(("grade.sh", ""));
+        let config = ScriptConfig {
+            path: "grade.sh".to_string(),
+            ..Default::default()
+        };
+        let executor = MockProcessExecutor::with_responses([Ok(process::Output::new(
+            ExitStatus::Failure(1),
+            "",
+            "Traceback",
+        ))]);
+
+        let ws = tempfile::tempdir().unwrap();
+        Script::new(executor, config, &test_dir)
+            .unwrap()
+            .run(ws.path())
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn runs_script_with_env() {
+        let script = r#"#!/bin/sh
+            printf '{"updates": [{"description": "test %s", "notes": ["%s"]}]}' \
+                "$GENOS_TEST_ID" "$GENOS_SUBMISSION"
+        "#;
+        let test_dir = MockDir::new().file(("grade.sh", script));
+        let script_path = test_dir.path_from_root("grade.sh");
+        std::fs::set_permissions(&script_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = ScriptConfig {
+            path: "grade.sh".to_string(),
+            ..Default::default()
+        };
+
+        let ws = tempfile::tempdir().unwrap();
+        let res = Script::new(ShellExecutor, config, &test_dir)
+            .unwrap()
+            .env(TEST_ID_ENV, "4")
+            .env(SUBMISSION_ENV, "/submission")
+            .run(ws.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::zero()
+            }
+        );
+        let output = res.output.unwrap();
+        assert!(output.contains("test 4"));
+        assert!(output.contains("/submission"));
+    }
+
+    #[test]
+    fn get_script_command_vars() {
+        let test_dir = MockDir::new().file(("grade.sh", ""));
+        let config = ScriptConfig {
+            path: "grade.sh".to_string(),
+            args: vec!["--test".to_string(), "${TEST_ID}".to_string()],
+            env: HashMap::from([("OUT".to_string(), "${WS}/out".to_string())]),
+            ..Default::default()
+        };
+        let ws = tempfile::tempdir().unwrap();
+
+        let cmd = Script::new(ShellExecutor, config, &test_dir)
+            .unwrap()
+            .vars(Vars::new().var("TEST_ID", "4"))
+            .get_script_command(ws.path())
+            .unwrap();
+
+        assert_eq!(cmd.args, ["--test", "4"]);
+        assert_eq!(
+            cmd.envs["OUT"],
+            format!("{}/out", filepath(ws.path()).unwrap())
+        );
+    }
+}
@@ -0,0 +1,767 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use genos::{
+    fs::{filepath, resolve_in},
+    output::{self, Content, RichTextMaker, Section, StatusUpdates, Update},
+    points::PointQuantity,
+    process::{self, Command, ExitStatus, ProcessExecutor},
+    stage::{StageResult, StageStatus},
+    Executor,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpStream, UnixStream},
+    time::{sleep, timeout},
+};
+use tracing::debug;
+
+use super::run::get_signal_feedback;
+use crate::vars::{self, Vars, PORT_VAR};
+
+// give a default timeout of 1 minute for the whole server run. Number chosen arbitrarily.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(5);
+// how long to wait between attempts to connect while the server starts up
+const CONNECT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Starts the student server in the background and tests it with client exchanges once it accepts
+/// connections. Every exchange is made over its own connection to the server on localhost. The args
+/// are templated, see Vars, and the server is also given the port it should listen on as PORT.
+/// Ex:
+/// service:
+///     executable: server
+///     args: ["${PORT}"]
+///     address: !Tcp 0
+///     exchanges:
+///         - name: echo a line
+///           points: !Partial 1
+///           request: !Line { send: [hello], expect: [hello] }
+///         - name: get the index page
+///           points: !Partial 2
+///           request: !Http { path: /, expect_status: 200, expect_body: "hello" }
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct ServiceConfig {
+    pub executable: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub address: ServiceAddress,
+    /// how long the server has to start accepting connections
+    pub startup_timeout_sec: Option<u64>,
+    /// the timeout for the whole server run
+    pub timeout_sec: Option<u64>,
+    pub exchanges: Vec<ExchangeConfig>,
+}
+
+impl ServiceConfig {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_sec.map(Duration::from_secs)
+    }
+
+    pub fn startup_timeout(&self) -> Option<Duration> {
+        self.startup_timeout_sec.map(Duration::from_secs)
+    }
+
+    /// The args of the server with the vars substituted in, along with the port it listens on.
+    pub fn server_args(&self, vars: &Vars, port: u16) -> Result<Vec<String>, vars::Error> {
+        vars.clone()
+            .var(PORT_VAR, port.to_string())
+            .expand_all(&self.args)
+    }
+}
+
+/// Where the server listens, a tcp port on localhost or a unix socket relative to the ws. Port 0
+/// is a free port picked for each run, so servers tested at the same time don't collide.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub enum ServiceAddress {
+    Tcp(u16),
+    Unix(PathBuf),
+}
+
+impl Display for ServiceAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(port) => write!(f, "localhost:{}", port),
+            Self::Unix(path) => write!(f, "unix socket {}", path.display()),
+        }
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+impl ServiceAddress {
+    // the address the server listens on for this run
+    fn allocate(&self) -> Result<Self> {
+        match self {
+            Self::Tcp(0) => {
+                let listener = TcpListener::bind(("127.0.0.1", 0))
+                    .context("Unable to find a free port for the server")?;
+                Ok(Self::Tcp(listener.local_addr()?.port()))
+            }
+            address => Ok(address.clone()),
+        }
+    }
+
+    fn port(&self) -> u16 {
+        match self {
+            Self::Tcp(port) => *port,
+            Self::Unix(_) => 0,
+        }
+    }
+
+    async fn connect(&self, ws: &Path) -> std::io::Result<Box<dyn Stream>> {
+        match self {
+            Self::Tcp(port) => Ok(Box::new(TcpStream::connect(("127.0.0.1", *port)).await?)),
+            Self::Unix(path) => {
+                let path = resolve_in(ws, path).map_err(std::io::Error::other)?;
+                Ok(Box::new(UnixStream::connect(path).await?))
+            }
+        }
+    }
+
+    // keep trying to connect until the server accepts a connection
+    async fn wait_until_ready(&self, ws: &Path) {
+        loop {
+            match self.connect(ws).await {
+                Ok(_) => return,
+                Err(e) => {
+                    debug!("server not ready at {}: {}", self, e);
+                    sleep(CONNECT_INTERVAL).await;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct ExchangeConfig {
+    pub name: String,
+    pub points: PointQuantity,
+    pub timeout_sec: Option<u64>,
+    pub request: Exchange,
+}
+
+impl ExchangeConfig {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_sec.map(Duration::from_secs)
+    }
+}
+
+/// A request sent to the server and the response it is expected to give.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub enum Exchange {
+    /// send the bytes as is and expect exactly the given bytes back, reading stops once as many
+    /// bytes as expected have been received or the server closes the connection
+    Raw {
+        send: String,
+        expect: String,
+    },
+    /// send each line followed by a newline, then expect the server to respond with the given
+    /// lines. Line endings aren't compared, so `\r\n` and `\n` are both accepted.
+    Line {
+        send: Vec<String>,
+        expect: Vec<String>,
+    },
+    Http(HttpExchange),
+}
+
+/// An HTTP/1.1 request. The connection is closed by the request, so the server's response is
+/// read until the server closes the connection.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct HttpExchange {
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub path: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<String>,
+    #[serde(default = "default_status")]
+    pub expect_status: u16,
+    pub expect_body: Option<String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+impl Exchange {
+    // returns the notes explaining how the response was wrong, or None if it was as expected
+    async fn run(&self, stream: Box<dyn Stream>) -> std::io::Result<Option<Content>> {
+        match self {
+            Self::Raw { send, expect } => Self::run_raw(stream, send, expect).await,
+            Self::Line { send, expect } => Self::run_line(stream, send, expect).await,
+            Self::Http(http) => http.run(stream).await,
+        }
+    }
+
+    async fn run_raw(
+        mut stream: Box<dyn Stream>,
+        send: &str,
+        expect: &str,
+    ) -> std::io::Result<Option<Content>> {
+        stream.write_all(send.as_bytes()).await?;
+
+        let mut received = Vec::new();
+        let mut chunk = [0; 4096];
+        while received.len() < expect.len() {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&chunk[..n]);
+        }
+
+        Ok(mismatch_notes(expect, &String::from_utf8_lossy(&received)))
+    }
+
+    async fn run_line(
+        mut stream: Box<dyn Stream>,
+        send: &[String],
+        expect: &[String],
+    ) -> std::io::Result<Option<Content>> {
+        for line in send {
+            stream.write_all(format!("{}\n", line).as_bytes()).await?;
+        }
+
+        let mut reader = BufReader::new(stream);
+        let mut received = Vec::new();
+        while received.len() < expect.len() {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? == 0 {
+                break;
+            }
+            received.push(line.trim_end_matches(['\r', '\n']).to_string());
+        }
+
+        Ok(mismatch_notes(&expect.join("\n"), &received.join("\n")))
+    }
+}
+
+impl HttpExchange {
+    fn request(&self) -> String {
+        let body = self.body.clone().unwrap_or_default();
+        let mut request = format!("{} {} HTTP/1.1\r\n", self.method, self.path);
+        request.push_str("Host: localhost\r\nConnection: close\r\n");
+        for (key, val) in &self.headers {
+            request.push_str(&format!("{}: {}\r\n", key, val));
+        }
+        if !body.is_empty() {
+            request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        request.push_str("\r\n");
+        request.push_str(&body);
+        request
+    }
+
+    async fn run(&self, mut stream: Box<dyn Stream>) -> std::io::Result<Option<Content>> {
+        stream.write_all(self.request().as_bytes()).await?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        let response = String::from_utf8_lossy(&response);
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+        let status = head
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|status| status.parse::<u16>().ok());
+
+        let mut notes = Vec::new();
+        match status {
+            Some(status) if status == self.expect_status => (),
+            Some(status) => notes.push(
+                format!(
+                    "Expected status {}, but found {}",
+                    self.expect_status, status
+                )
+                .into(),
+            ),
+            None => notes.push(("Invalid HTTP response", response.to_string().code()).into()),
+        }
+
+        if let Some(expected) = &self.expect_body {
+            if status.is_some() {
+                notes.extend(mismatch_notes(expected, body));
+            }
+        }
+
+        match notes.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(Content::Multiline(notes))),
+        }
+    }
+}
+
+fn mismatch_notes(expected: &str, received: &str) -> Option<Content> {
+    if expected == received {
+        return None;
+    }
+
+    Some(Content::Multiline(vec![
+        ("Expected", expected.code()).into(),
+        ("Received", received.code()).into(),
+    ]))
+}
+
+fn get_exit_notes(res: &process::Output) -> Content {
+    let mut notes = vec![match &res.status {
+        ExitStatus::Timeout(duration) => {
+            format!("Runtime error: server timed out after {:?}", duration).into()
+        }
+        ExitStatus::Signal(signal) => get_signal_feedback(signal),
+        status => format!(
+            "The server exited with return code {}",
+            status.exit_code().unwrap_or_default()
+        )
+        .into(),
+    }];
+
+    if !res.stderr.is_empty() {
+        notes.push(("stderr", res.stderr.clone().code()).into());
+    }
+
+    Content::Multiline(notes)
+}
+
+/// Service runs the student server in the background while making client exchanges with it. The
+/// server is killed once the exchanges are done.
+pub struct Service<E> {
+    executor: E,
+    config: ServiceConfig,
+    vars: Vars,
+}
+
+impl<E: ProcessExecutor> Service<E> {
+    pub fn new(executor: E, config: ServiceConfig) -> Self {
+        Self {
+            executor,
+            config,
+            vars: Vars::new(),
+        }
+    }
+
+    /// The vars substituted into the args, along with the ws and the port.
+    pub fn vars(mut self, vars: Vars) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    // the server runs in its own process group, so anything it starts is killed along with it
+    fn get_server_command(
+        &self,
+        executable: &str,
+        ws: &Path,
+        address: &ServiceAddress,
+    ) -> Result<Command> {
+        let args = self
+            .config
+            .server_args(&self.vars.with_ws(ws), address.port())?;
+        Ok(Command::new(executable)
+            .args(args)
+            .timeout(self.config.timeout().unwrap_or(DEFAULT_TIMEOUT))
+            .cwd(ws)
+            .process_group())
+    }
+
+    // adds an update for each exchange to the updates, returning the points lost
+    async fn run_exchanges(
+        &self,
+        ws: &Path,
+        address: &ServiceAddress,
+        updates: &mut StatusUpdates,
+    ) -> PointQuantity {
+        let mut points_lost = PointQuantity::zero();
+
+        for exchange in &self.config.exchanges {
+            let duration = exchange.timeout().unwrap_or(DEFAULT_EXCHANGE_TIMEOUT);
+            let res = timeout(duration, async {
+                let stream = address.connect(ws).await?;
+                exchange.request.run(stream).await
+            })
+            .await;
+
+            let notes = match res {
+                Ok(Ok(notes)) => notes,
+                Ok(Err(e)) => Some(format!("Connection error: {}", e).into()),
+                Err(_) => Some(format!("No response from the server after {:?}", duration).into()),
+            };
+
+            let mut update = Update::new_pass(&exchange.name);
+            if let Some(notes) = notes {
+                update.set_fail(exchange.points);
+                update.set_notes(notes);
+                points_lost += exchange.points;
+            }
+            updates.add_update(update);
+        }
+
+        points_lost
+    }
+}
+
+#[async_trait]
+impl<E: ProcessExecutor> Executor for Service<E> {
+    type Output = StageResult;
+
+    async fn run(&self, ws: &Path) -> Result<Self::Output> {
+        let mut section = Section::new("Test Server");
+
+        let executable = resolve_in(ws, &self.config.executable)?;
+        if !executable.exists() {
+            return Err(anyhow!(
+                "Could not find student executable at {:?}",
+                executable.display()
+            ));
+        }
+
+        let address = self.config.address.allocate()?;
+        let cmd = self.get_server_command(filepath(&executable)?, ws, &address)?;
+        section.add_content(("run command", cmd.to_string().code()));
+
+        // dropping the server future kills the server, so it is torn down however this returns
+        let server = cmd.run_with(&self.executor);
+        tokio::pin!(server);
+
+        let startup_timeout = self
+            .config
+            .startup_timeout()
+            .unwrap_or(DEFAULT_STARTUP_TIMEOUT);
+        let startup_notes = tokio::select! {
+            res = &mut server => Some(get_exit_notes(&res?)),
+            ready = timeout(startup_timeout, address.wait_until_ready(ws)) => {
+                ready.err().map(|_| format!(
+                    "The server wasn't accepting connections at {} after {:?}",
+                    address, startup_timeout
+                ).into())
+            }
+        };
+
+        if let Some(notes) = startup_notes {
+            section.add_content(StatusUpdates::default().update(
+                Update::new_fail("Starting server", PointQuantity::FullPoints).notes(notes),
+            ));
+            return Ok(StageResult::new(
+                StageStatus::UnrecoverableFailure,
+                Some(output::Output::new().section(section)),
+            ));
+        }
+
+        let mut updates = StatusUpdates::default().update(Update::new_pass("Starting server"));
+        // keep the server running while the exchanges are made, noting if it exits early
+        let mut server_res = None;
+        let points_lost = {
+            let exchanges = self.run_exchanges(ws, &address, &mut updates);
+            tokio::pin!(exchanges);
+            loop {
+                tokio::select! {
+                    res = &mut server, if server_res.is_none() => server_res = Some(res?),
+                    res = &mut exchanges => break res,
+                }
+            }
+        };
+
+        section.add_content(updates);
+        // the exchanges which failed because of it already lost their points
+        if let Some(res) = server_res {
+            section.add_content((
+                "The server exited before the exchanges were done",
+                get_exit_notes(&res),
+            ));
+        }
+
+        Ok(StageResult::new_continue(points_lost)
+            .with_output(output::Output::new().section(section)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use genos::{
+        formatter::MarkdownFormatter,
+        output::Contains,
+        points::Points,
+        process::SignalType,
+        test_util::{MockDir, MockProcessExecutor},
+        writer::Transform,
+    };
+    use tokio::net::{TcpListener as TokioTcpListener, UnixListener};
+
+    use super::*;
+
+    // A server which echoes every connection back, or responds to http requests with the path
+    // which was requested. It runs until it is dropped, like a real server.
+    #[derive(Clone)]
+    struct EchoServer {
+        address: ServiceAddress,
+        ws: PathBuf,
+    }
+
+    async fn handle(mut stream: Box<dyn Stream>) -> std::io::Result<()> {
+        let mut buf = [0; 4096];
+        let n = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..n]).to_string();
+
+        if let Some(path) = request
+            .strip_prefix("GET ")
+            .and_then(|rest| rest.split_whitespace().next())
+        {
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                path.len(),
+                path
+            );
+            return stream.write_all(response.as_bytes()).await;
+        }
+
+        stream.write_all(&buf[..n]).await?;
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        tokio::io::copy(&mut reader, &mut writer).await?;
+        Ok(())
+    }
+
+    #[async_trait]
+    impl ProcessExecutor for EchoServer {
+        async fn run(&self, cmd: &Command) -> Result<process::Output> {
+            match &self.address {
+                // like a real server, it listens on the port it's given
+                ServiceAddress::Tcp(_) => {
+                    let port: u16 = cmd.args[0].parse()?;
+                    let listener = TokioTcpListener::bind(("127.0.0.1", port)).await?;
+                    loop {
+                        let (stream, _) = listener.accept().await?;
+                        tokio::spawn(handle(Box::new(stream)));
+                    }
+                }
+                ServiceAddress::Unix(path) => {
+                    let listener = UnixListener::bind(resolve_in(&self.ws, path)?)?;
+                    loop {
+                        let (stream, _) = listener.accept().await?;
+                        tokio::spawn(handle(Box::new(stream)));
+                    }
+                }
+            }
+        }
+    }
+
+    fn make_config(address: ServiceAddress, exchanges: &str) -> ServiceConfig {
+        ServiceConfig {
+            executable: "server".to_string(),
+            args: vec!["${PORT}".to_string()],
+            address,
+            startup_timeout_sec: Some(1),
+            timeout_sec: None,
+            exchanges: serde_yaml::from_str(exchanges).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn exchanges_with_tcp_server() {
+        let ws = MockDir::new().file(("server", ""));
+        let address = ServiceAddress::Tcp(0);
+        let config = make_config(
+            address.clone(),
+            r#"
+            - name: raw
+              points: !Partial 1
+              request: !Raw { send: "\x01\x02", expect: "\x01\x02" }
+            - name: lines
+              points: !Partial 1
+              request: !Line { send: [hello, world], expect: [hello, world] }
+            - name: http
+              points: !Partial 1
+              request: !Http { path: /index.html, expect_body: /index.html }
+            - name: wrong
+              points: !Partial 2
+              request: !Line { send: [hello], expect: [goodbye] }
+            "#,
+        );
+        let server = EchoServer {
+            address,
+            ws: ws.root.path().to_path_buf(),
+        };
+
+        let res = Service::new(server, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(2))
+            }
+        );
+        let output = res.output.unwrap();
+        assert!(output.contains("goodbye"));
+        assert!(!output.contains("The server exited"));
+    }
+
+    // A server which handles the readiness check and a single exchange, and then crashes
+    #[derive(Clone)]
+    struct OneShotServer {
+        ws: PathBuf,
+    }
+
+    #[async_trait]
+    impl ProcessExecutor for OneShotServer {
+        async fn run(&self, _cmd: &Command) -> Result<process::Output> {
+            let listener = UnixListener::bind(self.ws.join("server.sock"))?;
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await?;
+                handle(Box::new(stream)).await?;
+            }
+            Ok(process::Output::from_exit_status(ExitStatus::Signal(
+                SignalType::SegFault,
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn server_exiting_early_is_noted() {
+        let ws = MockDir::new().file(("server", ""));
+        let config = make_config(
+            ServiceAddress::Unix("server.sock".into()),
+            r#"
+            - name: first
+              points: !Partial 1
+              request: !Raw { send: "hello", expect: "hello" }
+            - name: second
+              points: !Partial 2
+              request: !Raw { send: "hello", expect: "hello" }
+            "#,
+        );
+        let server = OneShotServer {
+            ws: ws.root.path().to_path_buf(),
+        };
+
+        let res = Service::new(server, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(2))
+            }
+        );
+        let output = res.output.unwrap().transform(&MarkdownFormatter);
+        let starting = output.find("Starting server").unwrap();
+        assert!(starting < output.find("first").unwrap(), "{}", output);
+        assert!(output.contains("The server exited"), "{}", output);
+        assert!(output.contains("segmentation fault"), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn exchanges_with_unix_server() {
+        let ws = MockDir::new().file(("server", ""));
+        let address = ServiceAddress::Unix("server.sock".into());
+        let config = make_config(
+            address.clone(),
+            r#"
+            - name: http status
+              points: !Partial 1
+              request: !Http { method: GET, path: /, expect_status: 404 }
+            "#,
+        );
+        let server = EchoServer {
+            address,
+            ws: ws.root.path().to_path_buf(),
+        };
+
+        let res = Service::new(server, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(1))
+            }
+        );
+        assert!(res
+            .output
+            .unwrap()
+            .contains("Expected status 404, but found 200"));
+    }
+
+    #[tokio::test]
+    async fn server_crash_is_unrecoverable() {
+        let ws = MockDir::new().file(("server", ""));
+        let config = make_config(ServiceAddress::Tcp(0), "[]");
+        let executor = MockProcessExecutor::with_responses([Ok(
+            process::Output::from_exit_status(ExitStatus::Signal(SignalType::SegFault)),
+        )]);
+
+        let res = Service::new(executor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        assert!(res.output.unwrap().contains("segmentation fault"));
+    }
+
+    #[tokio::test]
+    async fn server_never_listens() {
+        let ws = MockDir::new().file(("server", ""));
+        let config = make_config(ServiceAddress::Tcp(0), "[]");
+        let server = EchoServer {
+            // listens somewhere other than where the exchanges connect
+            address: ServiceAddress::Unix("other.sock".into()),
+            ws: ws.root.path().to_path_buf(),
+        };
+
+        let res = Service::new(server, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        assert!(res.output.unwrap().contains("wasn't accepting connections"));
+    }
+
+    #[tokio::test]
+    async fn socket_outside_ws_is_not_connected_to() {
+        let ws = MockDir::new().file(("server", ""));
+        let outside = MockDir::new();
+        std::os::unix::fs::symlink(
+            outside.path_from_root("server.sock"),
+            ws.path_from_root("server.sock"),
+        )
+        .unwrap();
+
+        let address = ServiceAddress::Unix("server.sock".into());
+        let config = make_config(address.clone(), "[]");
+        let server = EchoServer {
+            address,
+            ws: outside.root.path().to_path_buf(),
+        };
+
+        let res = Service::new(server, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        assert!(res.output.unwrap().contains("wasn't accepting connections"));
+    }
+}
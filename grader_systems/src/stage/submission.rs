@@ -0,0 +1,437 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use genos::{
+    fs::{files_in, resolve_in},
+    gs::TestDescription,
+    output::{self, Content, Section, StatusUpdates, Update},
+    points::PointQuantity,
+    stage::{StageResult, StageStatus},
+    Executor,
+};
+use glob::Pattern;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+// the number of bytes read from the start of a file to decide if it's a binary, the same amount
+// git looks at
+const BINARY_CHECK_LEN: usize = 8000;
+// the number of bytes read from the start of a file to check that it's text, so a huge file isn't
+// read in whole
+const TEXT_CHECK_LEN: usize = 1024 * 1024;
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+// zip tools on mac add this folder next to the zipped folder
+const MACOS_ZIP_FOLDER: &str = "__MACOSX";
+
+/// Checks the structure of the submission before any test is run. It is run as a setup test, so
+/// a submission which fails any of the checks isn't graded any further.
+/// Ex:
+/// submission:
+///     description:
+///         name: Submission Structure
+///         description: Checks that all the files needed for grading were submitted
+///         test_id: 0
+///         total_points: 0
+///         visibility: Visible
+///     required: [Makefile, main.c]
+///     forbidden: ["*.o", "*.zip", a.out]
+///     reject_binaries: true
+///     max_file_size_kb: 512
+///     text_files: ["*.c", "*.h", Makefile]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct SubmissionConfig {
+    pub description: TestDescription,
+    /// paths from the root of the submission which must exist
+    #[serde(default)]
+    pub required: Vec<String>,
+    /// glob patterns matched against the path of each file from the root of the submission
+    #[serde(default)]
+    pub forbidden: Vec<String>,
+    /// reject executables, object files and anything else which isn't text
+    #[serde(default)]
+    pub reject_binaries: bool,
+    pub max_file_size_kb: Option<u64>,
+    /// glob patterns of the files which must be UTF-8 text
+    #[serde(default)]
+    pub text_files: Vec<String>,
+}
+
+/// The problems found by a check, shown to the student as notes on the check.
+type Problems = Vec<Content>;
+
+/// The problems found with the files of the submission, one list for each check.
+#[derive(Default)]
+struct FileProblems {
+    forbidden: Problems,
+    too_large: Problems,
+    binary: Problems,
+    not_text: Problems,
+}
+
+/// ValidateSubmission runs each of the configured checks on the submission and gives the results
+/// as a checklist. Every check is run even after one fails, so the student can fix everything
+/// at once. The submission is read directly and not from the ws, since nothing should be copied
+/// out of a submission which isn't valid.
+pub struct ValidateSubmission {
+    submission: PathBuf,
+    config: SubmissionConfig,
+}
+
+impl ValidateSubmission {
+    pub fn new<P: Into<PathBuf>>(submission: P, config: SubmissionConfig) -> Self {
+        Self {
+            submission: submission.into(),
+            config,
+        }
+    }
+
+    fn patterns(patterns: &[String]) -> Result<Vec<Pattern>> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                Pattern::new(pattern)
+                    .map_err(|e| anyhow!("Invalid submission pattern {}: {}", pattern, e))
+            })
+            .collect()
+    }
+
+    // whether the file exists in the dir, a path which leads outside of it doesn't
+    fn exists_in(dir: &Path, file: &str) -> bool {
+        resolve_in(dir, file)
+            .map(|path| path.exists())
+            .unwrap_or(false)
+    }
+
+    fn check_required(&self) -> Problems {
+        self.config
+            .required
+            .iter()
+            .filter(|file| !Self::exists_in(&self.submission, file))
+            .map(|file| format!("{} is missing", file).into())
+            .collect()
+    }
+
+    // returns the folder that everything was submitted in, when the student zipped the folder
+    // containing their files instead of the files themselves
+    fn nested_folder(&self) -> Result<Option<String>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.submission)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name != MACOS_ZIP_FOLDER && !name.starts_with('.') {
+                entries.push((name, entry.file_type()?.is_dir()));
+            }
+        }
+
+        let folder = match entries.as_slice() {
+            [(name, true)] => name,
+            _ => return Ok(None),
+        };
+
+        // a submission which is supposed to be a single folder has its files required in it
+        let nested = self.config.required.is_empty()
+            || self.config.required.iter().any(|file| {
+                !Self::exists_in(&self.submission, file)
+                    && Self::exists_in(&self.submission.join(folder), file)
+            });
+
+        Ok(nested.then(|| folder.clone()))
+    }
+
+    // checks the file at the relative path in the submission
+    fn check_file(
+        &self,
+        relative: &Path,
+        forbidden: &[Pattern],
+        text: &[Pattern],
+        problems: &mut FileProblems,
+    ) -> Result<()> {
+        let file = self.submission.join(relative);
+        let name = relative.to_string_lossy();
+
+        if forbidden
+            .iter()
+            .any(|pattern| pattern.matches_path(relative))
+        {
+            problems
+                .forbidden
+                .push(format!("{} isn't allowed in the submission", name).into());
+        }
+
+        let size = fs::symlink_metadata(&file)?.len();
+        if let Some(max_kb) = self.config.max_file_size_kb {
+            if size > max_kb * 1024 {
+                problems.too_large.push(
+                    format!(
+                        "{} is {} KB, over the limit of {} KB",
+                        name,
+                        size.div_ceil(1024),
+                        max_kb
+                    )
+                    .into(),
+                );
+            }
+        }
+
+        let is_text_file = text.iter().any(|pattern| pattern.matches_path(relative));
+        let check_len = match (is_text_file, self.config.reject_binaries) {
+            (true, _) => TEXT_CHECK_LEN,
+            (false, true) => BINARY_CHECK_LEN,
+            (false, false) => return Ok(()),
+        };
+
+        let mut start = Vec::new();
+        fs::File::open(&file)?
+            .take(check_len as u64)
+            .read_to_end(&mut start)?;
+
+        if self.config.reject_binaries {
+            let sniffed = &start[..start.len().min(BINARY_CHECK_LEN)];
+            if sniffed.starts_with(ELF_MAGIC) || sniffed.contains(&0) {
+                problems
+                    .binary
+                    .push(format!("{} is a binary file, submit only source files", name).into());
+            }
+        }
+
+        // a character cut off at the end of what was read isn't a problem with the file
+        if is_text_file {
+            if let Err(e) = std::str::from_utf8(&start) {
+                let truncated = (start.len() as u64) < size;
+                if e.error_len().is_some() || !truncated {
+                    problems.not_text.push(
+                        format!(
+                            "{} isn't UTF-8 text, the first invalid byte is at offset {}",
+                            name,
+                            e.valid_up_to()
+                        )
+                        .into(),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn checklist_item(description: &str, problems: Problems) -> Update {
+    match problems.is_empty() {
+        true => Update::new_pass(description),
+        false => Update::new_fail(description, PointQuantity::FullPoints)
+            .notes(Content::Multiline(problems)),
+    }
+}
+
+#[async_trait]
+impl Executor for ValidateSubmission {
+    type Output = StageResult;
+
+    async fn run(&self, _ws: &Path) -> Result<Self::Output> {
+        let mut section = Section::new("Validate Submission");
+
+        let forbidden = Self::patterns(&self.config.forbidden)?;
+        let text = Self::patterns(&self.config.text_files)?;
+
+        let mut problems = FileProblems::default();
+        for file in files_in(&self.submission).await? {
+            self.check_file(&file, &forbidden, &text, &mut problems)?;
+        }
+
+        let folder_problems: Problems = self
+            .nested_folder()?
+            .map(|folder| {
+                format!(
+                    "Everything was submitted inside of the folder {}. Submit the files in it \
+                     instead of the folder itself.",
+                    folder
+                )
+                .into()
+            })
+            .into_iter()
+            .collect();
+
+        let mut passed = true;
+        let mut checklist = StatusUpdates::default();
+        let mut check = |description: &str, problems: Problems| {
+            passed &= problems.is_empty();
+            checklist.add_update(checklist_item(description, problems));
+        };
+
+        check(
+            "Files are at the top level of the submission",
+            folder_problems,
+        );
+        if !self.config.required.is_empty() {
+            check("All required files are present", self.check_required());
+        }
+        if !forbidden.is_empty() {
+            check("No forbidden files are present", problems.forbidden);
+        }
+        if self.config.reject_binaries {
+            check("No binary files are present", problems.binary);
+        }
+        if let Some(max_kb) = self.config.max_file_size_kb {
+            check(
+                &format!("All files are under {} KB", max_kb),
+                problems.too_large,
+            );
+        }
+        if !text.is_empty() {
+            check("Source files are UTF-8 text", problems.not_text);
+        }
+
+        section.add_content(checklist);
+
+        let status = match passed {
+            true => StageStatus::Continue {
+                points_lost: PointQuantity::zero(),
+            },
+            false => StageStatus::UnrecoverableFailure,
+        };
+
+        Ok(StageResult::new(
+            status,
+            Some(output::Output::new().section(section)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use genos::{output::Contains, points::Points, test_util::MockDir, tid::TestId};
+
+    use super::*;
+
+    fn make_config() -> SubmissionConfig {
+        SubmissionConfig {
+            description: TestDescription {
+                name: "Submission".to_string(),
+                description: "Submission".to_string(),
+                test_id: TestId::new(0),
+                total_points: Points::new(0),
+                visibility: genos::gs::Visibility::Visible,
+                tags: None,
+            },
+            required: vec!["Makefile".to_string(), "main.c".to_string()],
+            forbidden: vec!["*.o".to_string()],
+            reject_binaries: true,
+            max_file_size_kb: Some(1),
+            text_files: vec!["*.c".to_string(), "**/*.h".to_string()],
+        }
+    }
+
+    async fn validate(submission: &MockDir) -> StageResult {
+        let tmp = MockDir::new();
+        ValidateSubmission::new(submission.root.path(), make_config())
+            .run(tmp.root.path())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn valid_submission_passes() {
+        let submission = MockDir::new()
+            .file(("Makefile", "all:"))
+            .file(("main.c", "int main() {}"))
+            .dir("include", MockDir::new().file(("list.h", "// ünïcode")));
+
+        let res = validate(&submission).await;
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::zero()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_every_problem() {
+        let submission = MockDir::new()
+            .file(("Makefile", "all:"))
+            .file(("main.o", "\x7fELF"))
+            .file(("prog", b"\x7fELF\x02\x01".to_vec()))
+            .file(("big.txt", "a".repeat(2048)))
+            .dir(
+                "include",
+                MockDir::new().file(("list.h", b"\xff\xfe".to_vec())),
+            );
+
+        let res = validate(&submission).await;
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        let output = res.output.unwrap();
+        assert!(output.contains("main.c is missing"));
+        assert!(output.contains("main.o isn't allowed"));
+        assert!(output.contains("prog is a binary file"));
+        assert!(output.contains("big.txt is 2 KB, over the limit of 1 KB"));
+        assert!(output.contains("include/list.h isn't UTF-8 text"));
+        assert!(!output.contains("submitted inside of the folder"));
+    }
+
+    #[tokio::test]
+    async fn required_files_must_be_in_submission() {
+        let outside = MockDir::new().file(("main.c", "int main() {}"));
+        let submission = MockDir::new().file(("Makefile", "all:"));
+        std::os::unix::fs::symlink(
+            outside.path_from_root("main.c"),
+            submission.path_from_root("main.c"),
+        )
+        .unwrap();
+
+        let res = validate(&submission).await;
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        assert!(res.output.unwrap().contains("main.c is missing"));
+    }
+
+    #[tokio::test]
+    async fn checks_start_of_large_text_files() {
+        let mut config = make_config();
+        config.max_file_size_kb = None;
+        // the cut off character is past the part of the file which is read
+        let mut large = "a".repeat(TEXT_CHECK_LEN - 1).into_bytes();
+        large.extend("ü\n".as_bytes());
+        let submission = MockDir::new()
+            .file(("Makefile", "all:"))
+            .file(("main.c", large))
+            .file(("list.c", b"int x;\xc3".to_vec()));
+
+        let tmp = MockDir::new();
+        let res = ValidateSubmission::new(submission.root.path(), config)
+            .run(tmp.root.path())
+            .await
+            .unwrap();
+
+        let output = res.output.unwrap();
+        assert!(!output.contains("main.c isn't UTF-8 text"));
+        assert!(output.contains("list.c isn't UTF-8 text"));
+    }
+
+    #[tokio::test]
+    async fn detects_zipped_parent_folder() {
+        let submission = MockDir::new()
+            .dir(
+                "hw1",
+                MockDir::new()
+                    .file(("Makefile", "all:"))
+                    .file(("main.c", "int main() {}")),
+            )
+            .dir(MACOS_ZIP_FOLDER, MockDir::new().file(("._hw1", "")));
+
+        let res = validate(&submission).await;
+
+        assert_eq!(res.status, StageStatus::UnrecoverableFailure);
+        assert!(res
+            .output
+            .unwrap()
+            .contains("Everything was submitted inside of the folder hw1"));
+    }
+}
@@ -0,0 +1,355 @@
+use std::{collections::BTreeSet, path::Path};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use genos::{
+    output::{self, Content, RichTextMaker, Section, StatusUpdates, Update},
+    points::PointQuantity,
+    process::{Command, ProcessExecutor},
+    stage::{StageResult, StageStatus},
+    Executor,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+const NM: &str = "nm";
+
+/// Checks the symbol tables of the compiled object files or executables for symbols which have to
+/// be used or must not be. Unlike searching the source, this sees through macros and whatever
+/// else the code was written with.
+/// Ex:
+/// symbols:
+///     files: [sort, sort.o]
+///     forbidden:
+///         - symbols: [qsort]
+///           points: !Partial 5
+///     required:
+///         - symbols: [my_malloc, my_free]
+///           points: !FullPoints
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+pub struct SymbolsConfig {
+    /// paths in the ws of the files to check, a symbol found in any of them counts
+    pub files: Vec<String>,
+    /// symbols which must not be defined or referenced, ex: library functions which the assignment
+    /// has the students write themselves
+    #[serde(default)]
+    pub forbidden: Vec<SymbolRule>,
+    /// symbols which must be defined by the student code
+    #[serde(default)]
+    pub required: Vec<SymbolRule>,
+}
+
+impl SymbolsConfig {
+    pub fn rules(&self) -> impl Iterator<Item = &SymbolRule> {
+        self.forbidden.iter().chain(self.required.iter())
+    }
+}
+
+/// The points are lost once if any of the symbols breaks the rule.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct SymbolRule {
+    pub symbols: Vec<String>,
+    pub points: PointQuantity,
+}
+
+/// The symbols of a file, as listed by nm.
+#[derive(Debug, Default)]
+struct SymbolTable {
+    defined: BTreeSet<String>,
+    undefined: BTreeSet<String>,
+}
+
+impl SymbolTable {
+    fn references(&self, symbol: &str) -> bool {
+        self.defined.contains(symbol) || self.undefined.contains(symbol)
+    }
+}
+
+// parse the posix output format of nm, where each line is `name type [value size]`. Symbols from
+// shared libraries carry their version, ex: qsort@GLIBC_2.2.5, which is dropped.
+fn parse_nm_output(stdout: &str) -> SymbolTable {
+    let mut table = SymbolTable::default();
+
+    for line in stdout.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(name), Some(kind)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let name = name.split('@').next().unwrap_or(name).to_string();
+
+        // weak undefined symbols (w and v) don't need to be defined for the program to link
+        match kind {
+            "U" | "w" | "v" => table.undefined.insert(name),
+            _ => table.defined.insert(name),
+        };
+    }
+
+    table
+}
+
+/// Symbols reads the symbol tables of the configured files with nm and checks them against each
+/// of the forbidden and required rules. The feedback names the symbols which broke a rule and the
+/// file they were found in.
+pub struct Symbols<E> {
+    executor: E,
+    config: SymbolsConfig,
+}
+
+impl<E: ProcessExecutor> Symbols<E> {
+    pub fn new(executor: E, config: SymbolsConfig) -> Self {
+        Self { executor, config }
+    }
+
+    async fn read_symbols(&self, file: &Path) -> Result<SymbolTable> {
+        let res = Command::new(NM)
+            .args(["-P", "--no-sort"])
+            .arg(file.to_string_lossy())
+            .run_with(&self.executor)
+            .await?;
+
+        if res.status.is_ok() && !res.stdout.is_empty() {
+            return Ok(parse_nm_output(&res.stdout));
+        }
+
+        // a stripped executable only has the dynamic symbol table left, nm lists nothing for it
+        // without -D
+        let res = Command::new(NM)
+            .args(["-P", "--no-sort", "-D"])
+            .arg(file.to_string_lossy())
+            .run_with(&self.executor)
+            .await?;
+
+        match res.status.is_ok() {
+            true => Ok(parse_nm_output(&res.stdout)),
+            false => Err(anyhow!(
+                "Unable to read the symbols of {}: {}",
+                file.display(),
+                res.stderr
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl<E: ProcessExecutor> Executor for Symbols<E> {
+    type Output = StageResult;
+
+    async fn run(&self, ws: &Path) -> Result<Self::Output> {
+        let mut section = Section::new("Check Symbols");
+
+        let mut tables = Vec::new();
+        for file in &self.config.files {
+            let path = ws.join(file);
+            if !path.exists() {
+                return Err(anyhow!(
+                    "Could not find compiled file at {:?}",
+                    path.display()
+                ));
+            }
+            tables.push((file, self.read_symbols(&path).await?));
+        }
+
+        let mut updates = StatusUpdates::default();
+        let mut points_lost = PointQuantity::zero();
+
+        for rule in &self.config.forbidden {
+            let found: Vec<Content> = rule
+                .symbols
+                .iter()
+                .flat_map(|symbol| {
+                    tables
+                        .iter()
+                        .filter(|(_, table)| table.references(symbol))
+                        .map(move |(file, _)| {
+                            format!("`{}` is used in {}, which isn't allowed", symbol, file).into()
+                        })
+                })
+                .collect();
+
+            let description = format!("Not using {}", rule.symbols.join(", "));
+            match found.is_empty() {
+                true => updates.add_update(Update::new_pass(description)),
+                false => {
+                    points_lost += rule.points;
+                    updates.add_update(
+                        Update::new_fail(description, rule.points).notes(Content::Multiline(found)),
+                    );
+                }
+            }
+        }
+
+        for rule in &self.config.required {
+            let missing: Vec<Content> = rule
+                .symbols
+                .iter()
+                .filter(|symbol| {
+                    !tables
+                        .iter()
+                        .any(|(_, table)| table.defined.contains(*symbol))
+                })
+                .map(|symbol| format!("`{}` isn't defined", symbol).into())
+                .collect();
+
+            let description = format!("Defining {}", rule.symbols.join(", "));
+            match missing.is_empty() {
+                true => updates.add_update(Update::new_pass(description)),
+                false => {
+                    points_lost += rule.points;
+                    updates.add_update(
+                        Update::new_fail(description, rule.points)
+                            .notes(Content::Multiline(missing)),
+                    );
+                }
+            }
+        }
+
+        section.add_content(("checked files", self.config.files.join(" ").code()));
+        section.add_content(updates);
+
+        Ok(StageResult::new(
+            StageStatus::Continue { points_lost },
+            Some(output::Output::new().section(section)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use genos::{
+        output::Contains,
+        points::Points,
+        process::{self, ExitStatus, ShellExecutor},
+        test_util::{MockDir, MockProcessExecutor},
+    };
+
+    use super::*;
+
+    const NM_OUTPUT: &str = "\
+main T 0000000000001149 0000000000000041
+my_malloc T 0000000000001190 0000000000000010
+qsort@GLIBC_2.2.5 U
+__gmon_start__ w
+";
+
+    fn make_config(forbidden: &[&str], required: &[&str]) -> SymbolsConfig {
+        let rule = |symbols: &[&str]| SymbolRule {
+            symbols: symbols.iter().map(|s| s.to_string()).collect(),
+            points: PointQuantity::Partial(Points::new(2)),
+        };
+        SymbolsConfig {
+            files: vec!["prog".to_string()],
+            forbidden: vec![rule(forbidden)],
+            required: vec![rule(required)],
+        }
+    }
+
+    fn nm_output(stdout: &str) -> process::Output {
+        let mut output = process::Output::from_exit_status(ExitStatus::Ok);
+        output.stdout = stdout.to_string();
+        output
+    }
+
+    #[test]
+    fn parses_nm_output() {
+        let table = parse_nm_output(NM_OUTPUT);
+
+        assert!(table.defined.contains("main"));
+        assert!(table.defined.contains("my_malloc"));
+        assert!(table.undefined.contains("qsort"));
+        assert!(table.undefined.contains("__gmon_start__"));
+        assert!(table.references("qsort"));
+        assert!(!table.references("bsearch"));
+    }
+
+    #[tokio::test]
+    async fn rules_pass() {
+        let ws = MockDir::new().file(("prog", ""));
+        let executor = MockProcessExecutor::with_responses([Ok(nm_output(NM_OUTPUT))]);
+        let inner = executor.inner.clone();
+
+        let res = Symbols::new(executor, make_config(&["bsearch"], &["my_malloc"]))
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::zero()
+            }
+        );
+
+        let cmd = &inner.lock().unwrap().commands[0];
+        assert_eq!(cmd.program, NM);
+        assert_eq!(
+            cmd.args.last().unwrap(),
+            &ws.path_from_root("prog").to_string_lossy()
+        );
+    }
+
+    #[tokio::test]
+    async fn rules_fail_with_symbol_names() {
+        let ws = MockDir::new().file(("prog", ""));
+        let executor = MockProcessExecutor::with_responses([Ok(nm_output(NM_OUTPUT))]);
+
+        let res = Symbols::new(executor, make_config(&["qsort"], &["my_malloc", "my_free"]))
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(4))
+            }
+        );
+        let output = res.output.unwrap();
+        assert!(output.contains("`qsort` is used in prog"));
+        assert!(output.contains("`my_free` isn't defined"));
+        assert!(!output.contains("`my_malloc` isn't defined"));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_dynamic_symbols() {
+        let ws = MockDir::new().file(("prog", ""));
+        let executor = MockProcessExecutor::with_responses([
+            Ok(process::Output::from_exit_status(ExitStatus::Failure(1))),
+            Ok(nm_output("qsort U\n")),
+        ]);
+        let inner = executor.inner.clone();
+
+        let res = Symbols::new(executor, make_config(&["qsort"], &[]))
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(2))
+            }
+        );
+        assert!(inner.lock().unwrap().commands[1]
+            .args
+            .contains(&"-D".to_string()));
+    }
+
+    #[tokio::test]
+    async fn reads_real_executable() {
+        if !process::is_program_in_path(NM) {
+            return;
+        }
+        let ws = MockDir::new();
+        std::fs::copy("/bin/sh", ws.path_from_root("sh")).unwrap();
+        let mut config = make_config(&["execve"], &[]);
+        config.files = vec!["sh".to_string()];
+
+        let res = Symbols::new(ShellExecutor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        // sh is stripped, so this goes through the dynamic symbols
+        assert!(res.output.unwrap().contains("`execve` is used in sh"));
+    }
+}
@@ -0,0 +1,625 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    os::fd::AsRawFd,
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use genos::{
+    fs::{self, filepath, read_untrusted, resolve_in, ReadLimits},
+    output::{self, Content, RichTextMaker, Section, StatusUpdates, Update},
+    points::PointQuantity,
+    process::{self, Command, ExitStatus, ProcessExecutor},
+    stage::StageResult,
+    Executor,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::debug;
+
+use super::run::get_signal_feedback;
+use crate::vars::{self, Vars};
+
+/// The system header which instructor test drivers are compiled with.
+pub const UNITTEST_HEADER: &str = "genos_unittest.h";
+
+// every line of the protocol emitted by the unittest header starts with this prefix, followed by
+// the nonce of the run
+const PROTOCOL_PREFIX: &str = "GENOS_UNITTEST:";
+
+// the header writes its report to the file given in the env, tagging each record with the nonce,
+// which it reads from a pipe it's given the fd of. The report is in the ws since it's the only
+// place an isolated case can write to.
+const REPORT_ENV: &str = "GENOS_UNITTEST_REPORT";
+const REPORT_FILE: &str = ".genos_unittest_report";
+const REPORT_LIMITS: ReadLimits = ReadLimits {
+    max_size: 1024 * 1024,
+    timeout: Duration::from_secs(5),
+};
+
+// give a default timeout of 10 seconds per case. Unit test cases should be small.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default, Clone, Deserialize, JsonSchema)]
+pub struct UnitTestConfig {
+    /// The instructor test driver found in the test resources. It is imported into the ws along
+    /// with the unittest header so that the compile stage can build it with the student sources.
+    /// Templated, see Vars.
+    pub driver: String,
+    /// The executable produced by the compile stage. Templated, see Vars.
+    pub executable: String,
+    /// Applied to each case individually.
+    pub timeout_sec: Option<u64>,
+    pub cases: Vec<UnitTestCase>,
+}
+
+impl UnitTestConfig {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_sec.map(Duration::from_secs)
+    }
+
+    /// The config with the vars substituted into the driver and executable paths.
+    pub fn expand(&self, vars: &Vars) -> Result<Self, vars::Error> {
+        Ok(Self {
+            driver: vars.expand(&self.driver)?,
+            executable: vars.expand(&self.executable)?,
+            ..self.clone()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct UnitTestCase {
+    pub name: String,
+    pub points: PointQuantity,
+}
+
+/// A failed assertion reported by the unittest header.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct AssertionFailure {
+    file: String,
+    line: u32,
+    message: String,
+}
+
+#[derive(Debug, Default)]
+struct CaseReport {
+    failures: Vec<AssertionFailure>,
+    completed: bool,
+    unknown: bool,
+    // a record without the nonce of the run, which could only have come from the student code
+    forged: bool,
+}
+
+// a random nonce for a run of a case, so the student code can't guess what the header will tag
+// its records with
+fn make_nonce() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// reads the report the header wrote for a case. A case which didn't get far enough to write one
+// has an empty report.
+async fn read_report(ws: &Path) -> Result<String> {
+    match read_untrusted(&ws.join(REPORT_FILE), REPORT_LIMITS).await {
+        Ok(report) => Ok(String::from_utf8_lossy(&report).to_string()),
+        Err(fs::Error::NotFound) => Ok(String::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// parse the protocol lines out of the report of a case
+fn parse_case_report(report_file: &str, nonce: &str) -> CaseReport {
+    let mut report = CaseReport::default();
+    let prefix = format!("{}{}:", PROTOCOL_PREFIX, nonce);
+
+    for line in report_file.lines() {
+        let message = match line.strip_prefix(&prefix) {
+            Some(message) => message,
+            None => {
+                debug!("rejecting unittest report line: {}", line);
+                report.forged = true;
+                continue;
+            }
+        };
+
+        match message.split_once(':') {
+            Some(("FAIL", failure)) => {
+                let mut parts = failure.splitn(3, ':');
+                let (file, line, message) = (parts.next(), parts.next(), parts.next());
+                if let (Some(file), Some(Ok(line)), Some(message)) =
+                    (file, line.map(str::parse), message)
+                {
+                    report.failures.push(AssertionFailure {
+                        file: file.to_string(),
+                        line,
+                        message: message.to_string(),
+                    });
+                }
+            }
+            Some(("UNKNOWN", _)) => report.unknown = true,
+            _ if message == "PASS" || message == "DONE" => report.completed = true,
+            _ => debug!("unrecognized unittest protocol line: {}", line),
+        }
+    }
+
+    report
+}
+
+/// UnitTest runs each configured case of an instructor test driver as its own process, so a crash
+/// in one case doesn't affect any of the others. Each case passes if it runs to completion without
+/// any failed assertions.
+pub struct UnitTest<E> {
+    executor: E,
+    config: UnitTestConfig,
+    vars: Vars,
+}
+
+impl<E: ProcessExecutor> UnitTest<E> {
+    pub fn new(executor: E, config: UnitTestConfig) -> Self {
+        Self {
+            executor,
+            config,
+            vars: Vars::new(),
+        }
+    }
+
+    /// The vars substituted into the executable path, along with the ws.
+    pub fn vars(mut self, vars: Vars) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    // the nonce is given on a pipe which only the case inherits, the header closes it once it's
+    // read so the student code can't read it after
+    fn get_case_command(
+        &self,
+        executable: &str,
+        ws: &Path,
+        case: &UnitTestCase,
+        nonce: &str,
+    ) -> Result<Command> {
+        let (reader, mut writer) = std::io::pipe()?;
+        writer.write_all(nonce.as_bytes())?;
+        drop(writer);
+
+        Ok(Command::new(executable)
+            .arg(&case.name)
+            .arg(reader.as_raw_fd().to_string())
+            .inherit_fd(reader)
+            .cwd(ws)
+            .env(REPORT_ENV, ws.join(REPORT_FILE).to_string_lossy())
+            .timeout(self.config.timeout().unwrap_or(DEFAULT_TIMEOUT)))
+    }
+
+    // returns the notes for a failed case, or None if the case passed
+    fn get_case_feedback(&self, output: &process::Output, report: &CaseReport) -> Option<Content> {
+        let mut notes = Vec::new();
+
+        if report.forged {
+            notes.push(
+                "The results of the test case were tampered with, only the test driver may \
+                 report them"
+                    .into(),
+            );
+        }
+
+        match &output.status {
+            ExitStatus::Timeout(duration) => notes
+                .push(format!("Runtime error: test case timed out after {:?}", duration).into()),
+            ExitStatus::Signal(signal) => notes.push(get_signal_feedback(signal)),
+            _ if !report.completed => notes.push(
+                format!(
+                    "Test case exited before it finished running (exit code {})",
+                    output.status.exit_code().unwrap_or_default()
+                )
+                .into(),
+            ),
+            _ => (),
+        }
+
+        if !report.failures.is_empty() {
+            let failures = report
+                .failures
+                .iter()
+                .map(|failure| format!("{}:{}: {}", failure.file, failure.line, failure.message))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            notes.push(Content::SubSection(
+                Section::new("Failed Assertions").content(failures.code()),
+            ));
+        }
+
+        match notes.is_empty() {
+            true => None,
+            false => Some(Content::Multiline(notes)),
+        }
+    }
+}
+
+#[async_trait]
+impl<E: ProcessExecutor> Executor for UnitTest<E> {
+    type Output = StageResult;
+
+    async fn run(&self, ws: &Path) -> Result<Self::Output> {
+        let mut status_updates = StatusUpdates::default();
+        let mut points_lost = PointQuantity::zero();
+
+        let executable = resolve_in(ws, &self.vars.with_ws(ws).expand(&self.config.executable)?)?;
+        if !executable.exists() {
+            return Err(anyhow!(
+                "Could not find unit test executable at {:?}",
+                executable.display()
+            ));
+        }
+
+        for case in &self.config.cases {
+            // a report left by an earlier case can't be mistaken for this one's
+            match std::fs::remove_file(ws.join(REPORT_FILE)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            }
+
+            let nonce = make_nonce()?;
+            let output = self
+                .get_case_command(filepath(&executable)?, ws, case, &nonce)?
+                .run_with(&self.executor)
+                .await?;
+            let report = parse_case_report(&read_report(ws).await?, &nonce);
+
+            // an unknown case is a problem with the test config, not the submission
+            if report.unknown {
+                return Err(anyhow!(
+                    "Unit test driver has no test case named {}",
+                    case.name
+                ));
+            }
+
+            let mut update = Update::new_pass(format!("Running {}", case.name));
+            if let Some(notes) = self.get_case_feedback(&output, &report) {
+                update.set_fail(case.points);
+                update.set_notes(notes);
+                points_lost += case.points;
+            }
+
+            status_updates.add_update(update);
+        }
+
+        let output =
+            output::Output::new().section(Section::new("Unit Tests").content(status_updates));
+
+        Ok(StageResult::new_continue(points_lost).with_output(output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    use genos::{
+        output::Contains,
+        points::Points,
+        process::{ShellExecutor, SignalType},
+        stage::StageStatus,
+        test_util::{MockDir, MockProcessExecutor},
+    };
+    use tokio::process::Command as TokioCommand;
+
+    use super::*;
+
+    // writes the report of each case the way the header would, tagged with the nonce of the run
+    #[derive(Clone)]
+    struct ReportingExecutor {
+        mock: MockProcessExecutor,
+        reports: Arc<Mutex<VecDeque<&'static str>>>,
+        nonces: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ReportingExecutor {
+        fn new<I: IntoIterator<Item = (&'static str, Result<process::Output>)>>(cases: I) -> Self {
+            let (reports, responses): (VecDeque<_>, Vec<_>) = cases.into_iter().unzip();
+            Self {
+                mock: MockProcessExecutor::with_responses(responses),
+                reports: Arc::new(Mutex::new(reports)),
+                nonces: Arc::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ProcessExecutor for ReportingExecutor {
+        async fn run(&self, cmd: &Command) -> Result<process::Output> {
+            let report = self.reports.lock().unwrap().pop_front().unwrap_or_default();
+            let mut nonce = String::new();
+            File::from(cmd.inherited_fds[0].try_clone()?).read_to_string(&mut nonce)?;
+            assert_eq!(cmd.args[1], cmd.inherited_fds[0].as_raw_fd().to_string());
+            self.nonces.lock().unwrap().push(nonce.clone());
+
+            let records: String = report
+                .lines()
+                .map(|record| format!("{}{}:{}\n", PROTOCOL_PREFIX, nonce, record))
+                .collect();
+            std::fs::write(&cmd.envs[REPORT_ENV], records)?;
+
+            self.mock.run(cmd).await
+        }
+    }
+
+    fn case(name: &str, points: f64) -> UnitTestCase {
+        UnitTestCase {
+            name: name.to_string(),
+            points: PointQuantity::Partial(Points::new(points)),
+        }
+    }
+
+    #[test]
+    fn parses_case_report() {
+        let report = parse_case_report(
+            "GENOS_UNITTEST:abc:FAIL:driver.c:12:expected len to be 1, but found 0\n\
+             GENOS_UNITTEST:abc:FAIL:driver.c:13:assertion failed: x: y\n\
+             GENOS_UNITTEST:abc:DONE\n",
+            "abc",
+        );
+
+        assert!(report.completed);
+        assert!(!report.unknown);
+        assert!(!report.forged);
+        assert_eq!(
+            report.failures,
+            vec![
+                AssertionFailure {
+                    file: "driver.c".to_string(),
+                    line: 12,
+                    message: "expected len to be 1, but found 0".to_string(),
+                },
+                AssertionFailure {
+                    file: "driver.c".to_string(),
+                    line: 13,
+                    message: "assertion failed: x: y".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_records_without_nonce() {
+        let report = parse_case_report(
+            "GENOS_UNITTEST:PASS\n\
+             GENOS_UNITTEST:guess:DONE\n",
+            "abc",
+        );
+
+        assert!(report.forged);
+        assert!(!report.completed);
+    }
+
+    #[tokio::test]
+    async fn each_case_is_graded_separately() {
+        let config = UnitTestConfig {
+            executable: "driver".to_string(),
+            cases: vec![
+                case("passes", 1.0),
+                case("crashes", 2.0),
+                case("fails", 0.5),
+            ],
+            ..Default::default()
+        };
+        let ws = MockDir::new().file(("driver", ""));
+        let executor = ReportingExecutor::new([
+            (
+                "PASS\nDONE",
+                Ok(process::Output::from_exit_status(ExitStatus::Ok)),
+            ),
+            (
+                "",
+                Ok(process::Output::from_exit_status(ExitStatus::Signal(
+                    SignalType::SegFault,
+                ))),
+            ),
+            (
+                "FAIL:driver.c:3:assertion failed: 1 == 2\nDONE",
+                Ok(process::Output::from_exit_status(ExitStatus::Ok)),
+            ),
+        ]);
+        let inner = executor.mock.inner.clone();
+        let nonces = executor.nonces.clone();
+
+        let res = UnitTest::new(executor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(2.5))
+            }
+        );
+
+        let output = res.output.unwrap();
+        assert!(output.contains("segmentation fault"));
+        assert!(output.contains("driver.c:3: assertion failed: 1 == 2"));
+
+        assert_eq!(inner.lock().unwrap().commands[1].args[0], "crashes");
+        let nonces = nonces.lock().unwrap();
+        assert_ne!(nonces[0], nonces[1]);
+    }
+
+    #[tokio::test]
+    async fn case_exiting_early_fails() {
+        let config = UnitTestConfig {
+            executable: "driver".to_string(),
+            cases: vec![case("exits", 1.0)],
+            ..Default::default()
+        };
+        let ws = MockDir::new().file(("driver", ""));
+        let executor = ReportingExecutor::new([(
+            "",
+            Ok(process::Output::from_exit_status(ExitStatus::Failure(1))),
+        )]);
+
+        let res = UnitTest::new(executor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert!(res
+            .output
+            .unwrap()
+            .contains("exited before it finished running (exit code 1)"));
+    }
+
+    #[tokio::test]
+    async fn executable_outside_ws_is_error() {
+        let config = UnitTestConfig {
+            executable: "../driver".to_string(),
+            cases: vec![case("passes", 1.0)],
+            ..Default::default()
+        };
+        let ws = MockDir::new()
+            .dir("ws", MockDir::new())
+            .file(("driver", ""));
+        let executor = MockProcessExecutor::with_responses([]);
+
+        UnitTest::new(executor, config)
+            .run(&ws.path_from_root("ws"))
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn executable_is_expanded() {
+        let config = UnitTestConfig {
+            executable: "${NAME}".to_string(),
+            cases: vec![case("passes", 1.0)],
+            ..Default::default()
+        };
+        let ws = MockDir::new().file(("driver", ""));
+        let executor = ReportingExecutor::new([(
+            "PASS\nDONE",
+            Ok(process::Output::from_exit_status(ExitStatus::Ok)),
+        )]);
+
+        let res = UnitTest::new(executor, config)
+            .vars(Vars::new().var("NAME", "driver"))
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::zero()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_case_is_system_error() {
+        let config = UnitTestConfig {
+            executable: "driver".to_string(),
+            cases: vec![case("typo", 1.0)],
+            ..Default::default()
+        };
+        let ws = MockDir::new().file(("driver", ""));
+        let executor = ReportingExecutor::new([(
+            "UNKNOWN:typo",
+            Ok(process::Output::from_exit_status(ExitStatus::Failure(2))),
+        )]);
+
+        UnitTest::new(executor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn runs_driver_built_with_header() {
+        let mut header = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        header.push("resources/system");
+        header.push(UNITTEST_HEADER);
+
+        let driver = r#"
+            #include <stdio.h>
+            #include <stdlib.h>
+            #include <sys/stat.h>
+            #include "genos_unittest.h"
+
+            GENOS_TEST(passes) {
+                GENOS_ASSERT_EQ_INT(1 + 1, 2);
+                GENOS_ASSERT_EQ_STR("abc", "abc");
+            }
+
+            GENOS_TEST(fails) {
+                GENOS_ASSERT_EQ_INT(1 + 1, 3);
+            }
+
+            GENOS_TEST(crashes) {
+                int *p = NULL;
+                GENOS_ASSERT(*p == 0);
+            }
+
+            // the pipe the nonce was passed on is closed before the case runs
+            GENOS_TEST(finds_no_nonce) {
+                struct stat st;
+                for (int fd = 3; fd < 64; fd++) {
+                    GENOS_ASSERT(fstat(fd, &st) != 0 || !S_ISFIFO(st.st_mode));
+                }
+            }
+
+            GENOS_TEST(forges) {
+                printf("GENOS_UNITTEST:PASS\nGENOS_UNITTEST:DONE\n");
+                exit(0);
+            }
+        "#;
+
+        let ws = MockDir::new().file(("driver.c", driver));
+        std::fs::copy(&header, ws.path_from_root(UNITTEST_HEADER)).unwrap();
+
+        let res = TokioCommand::new("gcc")
+            .args(["driver.c", "-o", "driver"])
+            .current_dir(ws.root.path())
+            .output()
+            .await
+            .unwrap();
+        assert!(res.status.success(), "{:?}", res);
+
+        let config = UnitTestConfig {
+            executable: "driver".to_string(),
+            cases: vec![
+                case("passes", 1.0),
+                case("fails", 1.0),
+                case("crashes", 1.0),
+                case("finds_no_nonce", 1.0),
+                case("forges", 1.0),
+            ],
+            ..Default::default()
+        };
+
+        let res = UnitTest::new(ShellExecutor, config)
+            .run(ws.root.path())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res.status,
+            StageStatus::Continue {
+                points_lost: PointQuantity::Partial(Points::new(3))
+            }
+        );
+
+        let output = res.output.unwrap();
+        assert!(output.contains("expected 1 + 1 to be 3, but found 2"));
+        assert!(output.contains("exited before it finished running (exit code 0)"));
+        assert!(output.contains("segmentation fault"));
+    }
+}
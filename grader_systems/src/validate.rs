@@ -0,0 +1,689 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use genos::{fs::ResourceLocator, tid::TestId};
+use glob::glob;
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+
+use crate::{
+    config::{HwConfig, TestConfig, TEST_CONFIG_NAME},
+    finder::{DirFinder, Finder, TestFileFinder},
+    vars::{Vars, BUILTIN_VARS},
+};
+
+const TEST_DIR_PREFIX: &str = "test_";
+
+/// A problem with the configs of a hw, along with the line it's on when that's known.
+#[derive(Debug)]
+pub struct Problem {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file.display(), line, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+/// Where a problem is in a config, which is found by the line it's on.
+#[derive(Clone, Copy)]
+enum Mention<'a> {
+    /// a key of a mapping
+    Key(&'a str),
+    /// a whole scalar value, on its own or in a flow sequence or mapping
+    Value(&'a str),
+}
+
+/// A config file along with its contents, which are kept to find the line of a problem.
+struct ConfigFile {
+    path: PathBuf,
+    contents: String,
+}
+
+impl ConfigFile {
+    fn read(path: &Path) -> Result<Self, Problem> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Self {
+                path: path.to_path_buf(),
+                contents,
+            }),
+            Err(e) => Err(Problem {
+                file: path.to_path_buf(),
+                line: None,
+                message: format!("Unable to read config: {}", e),
+            }),
+        }
+    }
+
+    // the first line with the key or value, which is where a problem with it is reported
+    fn line_of(&self, mention: Mention) -> Option<usize> {
+        self.contents
+            .lines()
+            .position(|line| {
+                let (key, values) = scalars(line);
+                match mention {
+                    Mention::Key(text) => key == Some(text),
+                    Mention::Value(text) => values.contains(&text),
+                }
+            })
+            .map(|i| i + 1)
+    }
+
+    fn problem<M: Into<String>>(&self, line: Option<usize>, message: M) -> Problem {
+        Problem {
+            file: self.path.clone(),
+            line,
+            message: message.into(),
+        }
+    }
+
+    fn yaml_problem(&self, e: serde_yaml::Error) -> Problem {
+        self.problem(e.location().map(|l| l.line()), e.to_string())
+    }
+
+    fn parse<T: DeserializeOwned>(&self) -> Result<T, Problem> {
+        serde_yaml::from_str(&self.contents).map_err(|e| self.yaml_problem(e))
+    }
+}
+
+// The key and the scalar values of a line of yaml, without their quotes. This only understands the
+// forms configs are written in, `key: value`, `- value` and flow collections on a single line.
+fn scalars(line: &str) -> (Option<&str>, Vec<&str>) {
+    let mut line = line.trim();
+    while let Some(rest) = line.strip_prefix("- ") {
+        line = rest.trim_start();
+    }
+
+    let (key, value) = match line.split_once(": ") {
+        Some((key, value)) => (Some(unquote(key)), value),
+        None => match line.strip_suffix(':') {
+            Some(key) => (Some(unquote(key)), ""),
+            None => (None, line),
+        },
+    };
+
+    let value = value.trim();
+    let flow = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .or(value.strip_prefix('{').and_then(|v| v.strip_suffix('}')));
+    let values = match flow {
+        Some(items) => items
+            .split(',')
+            .map(|item| item.split_once(": ").map_or(item, |(_, value)| value))
+            .map(unquote)
+            .collect(),
+        None if value.is_empty() => Vec::new(),
+        None => vec![unquote(value)],
+    };
+
+    (key, values)
+}
+
+fn unquote(s: &str) -> &str {
+    let s = s.trim();
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .or(s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')))
+        .unwrap_or(s)
+}
+
+/// Checks everything about the configs of a hw which can be checked without a submission: the hw
+/// config, each test config merged over the defaults, the resources the test configs refer to,
+/// the test ids and the groups. Every problem is found instead of stopping at the first one, so
+/// they can all be fixed at once.
+pub fn validate(hw_config: &Path) -> Vec<Problem> {
+    let hw_file = match ConfigFile::read(hw_config) {
+        Ok(file) => file,
+        Err(problem) => return vec![problem],
+    };
+    let hw: HwConfig = match hw_file.parse() {
+        Ok(hw) => hw,
+        Err(problem) => return vec![problem],
+    };
+    let finder = match Finder::from_hw_config_path(hw_config) {
+        Ok(finder) => Arc::new(finder),
+        Err(e) => {
+            return vec![hw_file.problem(None, format!("Unable to find the hw resources: {}", e))]
+        }
+    };
+
+    let mut validator = Validator {
+        hw_file,
+        hw,
+        finder,
+        generated: BTreeMap::new(),
+        problems: Vec::new(),
+    };
+    validator.validate();
+    validator.problems
+}
+
+struct Validator {
+    hw_file: ConfigFile,
+    hw: HwConfig,
+    finder: Arc<Finder>,
+    // the ids of the tests generated from each parametrized test
+    generated: BTreeMap<TestId, Vec<TestId>>,
+    problems: Vec<Problem>,
+}
+
+impl Validator {
+    fn validate(&mut self) {
+        for name in BUILTIN_VARS {
+            if self.hw.vars.contains_key(name) {
+                self.problems.push(self.hw_file.problem(
+                    self.hw_file.line_of(Mention::Key(name)),
+                    format!("vars can't set {}, since it's set by the grader", name),
+                ));
+            }
+        }
+
+        // the files of each configured test id, which should only ever be one
+        let mut tests: BTreeMap<TestId, Vec<PathBuf>> = BTreeMap::new();
+        if let Some(submission) = &self.hw.submission {
+            tests
+                .entry(submission.description.test_id)
+                .or_default()
+                .push(self.hw_file.path.clone());
+        }
+
+        for (tid, dir) in self.test_dirs() {
+            for id in self.check_test(tid, &dir) {
+                tests.entry(id).or_default().push(dir.clone());
+            }
+        }
+
+        for (tid, files) in &tests {
+            if files.len() > 1 {
+                let files: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+                self.problems.push(Problem {
+                    file: self.hw_file.path.clone(),
+                    line: None,
+                    message: format!(
+                        "Test id {} is used by more than one test: {}",
+                        tid,
+                        files.join(", ")
+                    ),
+                });
+            }
+        }
+
+        self.check_groups(&tests);
+    }
+
+    // the test dirs in the hw root along with their ids. A dir which doesn't end with a number is
+    // a problem, since the finder skips it.
+    fn test_dirs(&mut self) -> Vec<(TestId, PathBuf)> {
+        let Some(hw_root) = self.hw_file.path.parent() else {
+            return Vec::new();
+        };
+        let pattern = hw_root.join(format!("{}*", TEST_DIR_PREFIX));
+        let Ok(paths) = glob(&pattern.to_string_lossy()) else {
+            return Vec::new();
+        };
+
+        let mut dirs = Vec::new();
+        for dir in paths
+            .filter_map(|path| path.ok())
+            .filter(|path| path.is_dir())
+        {
+            let name = dir.file_name().unwrap_or_default().to_string_lossy();
+            let suffix = name.trim_start_matches(TEST_DIR_PREFIX);
+
+            match suffix.parse() {
+                Ok(id) => dirs.push((TestId::new(id), dir)),
+                Err(_) => self.problems.push(Problem {
+                    file: dir.clone(),
+                    line: None,
+                    message: format!(
+                        "Test dir is skipped since its id {:?} isn't a number",
+                        suffix
+                    ),
+                }),
+            }
+        }
+
+        dirs
+    }
+
+    // a problem with something in the test config, which may have come from the hw defaults
+    fn problem_in<M: Into<String>>(
+        &self,
+        file: &ConfigFile,
+        mention: Mention,
+        message: M,
+    ) -> Problem {
+        match (file.line_of(mention), self.hw_file.line_of(mention)) {
+            (None, Some(line)) => self.hw_file.problem(Some(line), message),
+            (line, _) => file.problem(line, message),
+        }
+    }
+
+    // checks the test config in the dir and the resources it refers to, returning the ids of the
+    // tests it's run as
+    fn check_test(&mut self, tid: TestId, dir: &Path) -> Vec<TestId> {
+        let path = dir.join(TEST_CONFIG_NAME);
+        if !path.is_file() {
+            self.problems.push(Problem {
+                file: dir.to_path_buf(),
+                line: None,
+                message: format!("Test dir has no {}", TEST_CONFIG_NAME),
+            });
+            return Vec::new();
+        }
+
+        let file = match ConfigFile::read(&path) {
+            Ok(file) => file,
+            Err(problem) => {
+                self.problems.push(problem);
+                return Vec::new();
+            }
+        };
+        let config = file
+            .parse::<Value>()
+            .map(|config| self.hw.defaults.apply(config))
+            .and_then(|config| {
+                // the config is validated separately below, so the problem can be located
+                TestConfig::deserialize(config).map_err(|e| file.yaml_problem(e))
+            });
+        let config = match config {
+            Ok(config) => config,
+            Err(problem) => {
+                self.problems.push(problem);
+                return Vec::new();
+            }
+        };
+
+        let configured = config.description.test_id;
+        if configured != tid {
+            self.problems.push(file.problem(
+                file.line_of(Mention::Key("test_id")),
+                format!(
+                    "test_id {} doesn't match the id of its test dir, {}",
+                    configured, tid
+                ),
+            ));
+        }
+
+        let Some(params) = config.params.clone() else {
+            self.check_config(tid, &file, &config, "");
+            return vec![configured];
+        };
+
+        // a parametrized test is run as the tests generated from its rows, which are each checked
+        let rows = match params.rows(&DirFinder::new(dir.to_path_buf())) {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.problems.push(self.problem_in(
+                    &file,
+                    Mention::Key("params"),
+                    format!("{:#}", e),
+                ));
+                Vec::new()
+            }
+        };
+
+        let mut ids = Vec::new();
+        for (row, i) in rows.iter().zip(1..) {
+            let name = format!("Row {} ({})", i, row.name);
+            match row.apply(config.clone(), i) {
+                Ok(config) => {
+                    let id = config.description.test_id;
+                    self.check_config(id, &file, &config, &format!("{}: ", name));
+                    ids.push(id);
+                }
+                Err(e) => self.problems.push(self.problem_in(
+                    &file,
+                    Mention::Value(&row.name),
+                    format!("{}: {}", name, e),
+                )),
+            }
+        }
+
+        self.generated.insert(configured, ids.clone());
+        ids
+    }
+
+    // checks a test config which is run as a test, where the prefix says which row of a
+    // parametrized test it is
+    fn check_config(&mut self, tid: TestId, file: &ConfigFile, config: &TestConfig, prefix: &str) {
+        if let Err(e) = config.validate() {
+            self.problems.push(self.problem_in(
+                file,
+                Mention::Key("total_points"),
+                format!("{}{}", prefix, e),
+            ));
+        }
+
+        // the submission is only known when the grader runs
+        let vars = Vars::for_test(&self.hw, &self.hw_file.path, tid, Path::new(""));
+        if let Err(e) = config.check_vars(&vars) {
+            self.problems.push(self.problem_in(
+                file,
+                Mention::Value(e.template()),
+                format!("{}{}", prefix, e),
+            ));
+        }
+
+        self.check_resources(tid, file, config);
+    }
+
+    fn check_resources(&mut self, tid: TestId, file: &ConfigFile, config: &TestConfig) {
+        let Ok(layers) = self.finder.test_layers(tid) else {
+            return;
+        };
+        let mut problems = Vec::new();
+
+        let expected = config
+            .compare_files
+            .iter()
+            .flat_map(|compares| &compares.compares)
+            .flat_map(|compare| &compare.expected);
+        for expected in expected {
+            if layers.find(expected).is_err() {
+                problems.push(self.problem_in(
+                    file,
+                    Mention::Value(expected),
+                    format!("Expected file {} isn't a resource of the test", expected),
+                ));
+            }
+        }
+
+        let test_finder = TestFileFinder::new(tid, self.finder.clone());
+
+        // stdin is read from the ws, where it's either imported or copied from the basefiles
+        if let Some(stdin) = config.run.as_ref().and_then(|run| run.stdin.as_ref()) {
+            let in_basefiles = self
+                .finder
+                .basefiles_dir()
+                .is_some_and(|dir| DirFinder::new(dir.to_path_buf()).find(stdin).is_ok());
+            let imported = config
+                .import_files
+                .as_ref()
+                .is_some_and(|import_files| import_files.imports(&test_finder, stdin));
+            if !in_basefiles && !imported {
+                problems.push(self.problem_in(
+                    file,
+                    Mention::Value(stdin),
+                    format!("stdin file {} isn't imported or a basefile", stdin),
+                ));
+            }
+        }
+
+        if let Some(import_files) = &config.import_files {
+            for (src, e) in import_files.unresolved(&test_finder) {
+                problems.push(self.problem_in(
+                    file,
+                    Mention::Value(&src),
+                    format!("Unable to import {}: {}", src, e),
+                ));
+            }
+        }
+
+        self.problems.extend(problems);
+    }
+
+    fn check_groups(&mut self, tests: &BTreeMap<TestId, Vec<PathBuf>>) {
+        let mut grouped = BTreeSet::new();
+        let mut problems = Vec::new();
+
+        for group in &self.hw.groups {
+            for tid in &group.tests {
+                // a group with a parametrized test has all the tests generated from it
+                match self.generated.get(tid) {
+                    Some(ids) => grouped.extend(ids),
+                    None => {
+                        grouped.insert(*tid);
+                    }
+                }
+                if !tests.contains_key(tid) && !self.generated.contains_key(tid) {
+                    problems.push(self.hw_file.problem(
+                        self.hw_file.line_of(Mention::Value(&group.name)),
+                        format!("Group {} has test {}, which doesn't exist", group.name, tid),
+                    ));
+                }
+            }
+        }
+
+        // the submission test is a setup test, which is run no matter the group
+        let setup = self.hw.submission.as_ref().map(|s| s.description.test_id);
+        for (tid, files) in tests {
+            if !grouped.contains(tid) && Some(*tid) != setup {
+                problems.push(Problem {
+                    file: files[0].clone(),
+                    line: None,
+                    message: format!("Test {} isn't in any group, so it's never run", tid),
+                });
+            }
+        }
+
+        self.problems.extend(problems);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use genos::test_util::MockDir;
+
+    use super::*;
+
+    const HW_CONFIG: &str = r#"
+class: 2022-winter
+name: hw1
+groups:
+    - name: all
+      tests: [1, 2, 5]
+vars:
+    DATA: /data
+defaults:
+    test_type: Diff
+    compile:
+        make_args: [all]
+    run:
+        args: []
+        executable: prog
+        stdout: stdout
+"#;
+
+    fn test_config(test_id: u32, total_points: u32, rest: &str) -> String {
+        format!(
+            r#"
+description:
+    name: test
+    description: test
+    test_id: {}
+    total_points: {}
+    visibility: Hidden
+compare_files:
+    compares:
+        - expected: [expected_stdout]
+          student_file: stdout
+          compare_type: Diff
+          points: !Partial 2
+          show_output: true
+{}"#,
+            test_id, total_points, rest
+        )
+    }
+
+    fn make_data_dir(tests: MockDir) -> MockDir {
+        MockDir::new()
+            .dir("system", MockDir::new())
+            .dir("2022-winter", MockDir::new().dir("hw1", tests))
+    }
+
+    fn messages(data: &MockDir) -> Vec<String> {
+        validate(&data.path_from_root("2022-winter/hw1/hw.yaml"))
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn valid_hw_has_no_problems() {
+        let data = make_data_dir(
+            MockDir::new()
+                .file(("hw.yaml", HW_CONFIG.replace("[1, 2, 5]", "[1]")))
+                .dir("static", MockDir::new().file(("input", "")))
+                .dir(
+                    "test_1",
+                    MockDir::new()
+                        .file((
+                            "config.yaml",
+                            test_config(
+                                1,
+                                2,
+                                "run:\n    stdin: input\n    args: [\"${DATA}/${TEST_ID}\"]\n\
+                                 import_files:\n    files: [input]\n",
+                            ),
+                        ))
+                        .file(("expected_stdout", "")),
+                ),
+        );
+
+        assert!(messages(&data).is_empty(), "{:?}", messages(&data));
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let data = make_data_dir(
+            MockDir::new()
+                .file((
+                    "hw.yaml",
+                    HW_CONFIG.replace("DATA: /data", "DATA: /data\n    WS: /tmp"),
+                ))
+                .dir(
+                    "test_1",
+                    MockDir::new().file((
+                        "config.yaml",
+                        test_config(1, 2, "import_files:\n    files: [helper.sh]\n"),
+                    )),
+                )
+                .dir(
+                    "test_2",
+                    MockDir::new()
+                        .file((
+                            "config.yaml",
+                            test_config(4, 3, "run:\n    stdin: input.txt\n"),
+                        ))
+                        .file(("expected_stdout", ""))
+                        .file(("input.txt", "")),
+                )
+                .dir(
+                    "test_4",
+                    MockDir::new()
+                        .file((
+                            "config.yaml",
+                            test_config(4, 2, "run:\n    args: [\"${DAT}\"]\n"),
+                        ))
+                        .file(("expected_stdout", "")),
+                )
+                .dir(
+                    "test_6",
+                    MockDir::new().file(("config.yaml", "description: [unclosed")),
+                )
+                .dir("test_old", MockDir::new()),
+        );
+
+        let messages = messages(&data);
+        let has = |file: &str, message: &str| {
+            assert!(
+                messages
+                    .iter()
+                    .any(|m| m.contains(file) && m.contains(message)),
+                "expected {} in {}, found {:#?}",
+                message,
+                file,
+                messages
+            )
+        };
+
+        has(
+            "test_1/config.yaml:10:",
+            "Expected file expected_stdout isn't a resource",
+        );
+        has("test_1/config.yaml:16:", "Unable to import helper.sh");
+        has("test_2/config.yaml:5:", "test_id 4 doesn't match");
+        has("test_2/config.yaml:6:", "Configured points need to add up");
+        has(
+            "test_2/config.yaml:16:",
+            "stdin file input.txt isn't imported or a basefile",
+        );
+        has("hw.yaml", "Test id 4 is used by more than one test");
+        has("test_6/config.yaml:2:", "did not find expected");
+        has("test_old", "its id \"old\" isn't a number");
+        has("hw.yaml:5:", "Group all has test 2, which doesn't exist");
+        has("hw.yaml:5:", "Group all has test 5, which doesn't exist");
+        has("test_2", "Test 4 isn't in any group");
+        has("test_4/config.yaml:16:", "Unknown variable ${DAT}");
+        has("hw.yaml:9:", "vars can't set WS");
+    }
+
+    #[test]
+    fn finds_the_line_of_keys_and_values() {
+        let file = ConfigFile {
+            path: "config.yaml".into(),
+            contents: "description: reads input.txt for test_id\n\
+                       run:\n    \
+                       stdin: input.txt\n    \
+                       args: [\"-n\", '${N}']\n\
+                       test_id: 3\n"
+                .to_string(),
+        };
+
+        assert_eq!(file.line_of(Mention::Value("input.txt")), Some(3));
+        assert_eq!(file.line_of(Mention::Key("stdin")), Some(3));
+        assert_eq!(file.line_of(Mention::Value("${N}")), Some(4));
+        assert_eq!(file.line_of(Mention::Key("test_id")), Some(5));
+        assert_eq!(file.line_of(Mention::Value("3")), Some(5));
+        assert_eq!(file.line_of(Mention::Key("total_points")), None);
+    }
+
+    #[test]
+    fn checks_generated_tests() {
+        let params = "params: !Rows\n    \
+                      - name: first\n      expected: [missing.out]\n    \
+                      - name: second\n      expected: [a.out, b.out]\n";
+        let data = make_data_dir(
+            MockDir::new()
+                .file(("hw.yaml", HW_CONFIG.replace("[1, 2, 5]", "[3, 3001]")))
+                .dir(
+                    "test_3",
+                    MockDir::new().file(("config.yaml", test_config(3, 2, params))),
+                )
+                .dir(
+                    "test_3001",
+                    MockDir::new()
+                        .file(("config.yaml", test_config(3001, 2, "")))
+                        .file(("expected_stdout", "")),
+                ),
+        );
+
+        let messages = messages(&data);
+        let has = |message: &str| {
+            assert!(
+                messages.iter().any(|m| m.contains(message)),
+                "expected {}, found {:#?}",
+                message,
+                messages
+            )
+        };
+
+        has("Expected file missing.out isn't a resource");
+        has("Row 2 (second): Expected one expected file for each of the 1 compares, found 2");
+        has("Test id 3001 is used by more than one test");
+        assert_eq!(messages.len(), 3, "{:#?}", messages);
+    }
+}
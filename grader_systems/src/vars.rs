@@ -0,0 +1,170 @@
+use std::{collections::HashMap, path::Path};
+
+use genos::tid::TestId;
+use thiserror::Error;
+
+use crate::config::HwConfig;
+
+/// The vars which are set by the grader, and can't be set in the hw config. PORT is only set for
+/// the server of a service test, the rest are given to every test.
+pub const TEST_ID_VAR: &str = "TEST_ID";
+pub const WS_VAR: &str = "WS";
+pub const SUBMISSION_VAR: &str = "SUBMISSION";
+pub const STATIC_VAR: &str = "STATIC";
+pub const PORT_VAR: &str = "PORT";
+pub const BUILTIN_VARS: [&str; 5] = [TEST_ID_VAR, WS_VAR, SUBMISSION_VAR, STATIC_VAR, PORT_VAR];
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("Unknown variable ${{{name}}} in {template:?}, the known variables are {known}")]
+    UnknownVar {
+        name: String,
+        template: String,
+        known: String,
+    },
+
+    #[error("Variable in {template:?} is missing its closing brace")]
+    Unterminated { template: String },
+}
+
+impl Error {
+    /// The config string the error was found in.
+    pub fn template(&self) -> &str {
+        match self {
+            Self::UnknownVar { template, .. } | Self::Unterminated { template } => template,
+        }
+    }
+}
+
+/// Vars are substituted into the config strings which are templated, written as `${NAME}`. Every
+/// var used must be known, so a typo in a name is a config error instead of an empty string.
+/// `$${` is a literal `${`, for args which need one.
+/// Ex:
+/// vars:
+///     DATA: /usr/share/dict
+/// ...
+/// run:
+///     args: [--words, "${DATA}/words", --out, "${WS}/out_${TEST_ID}.txt"]
+#[derive(Debug, Default, Clone)]
+pub struct Vars {
+    values: HashMap<String, String>,
+}
+
+impl Vars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The vars of a test, which are the vars of the hw config along with the builtin vars. The
+    /// ws is only known when the test runs, so it's added by the stages with `with_ws`.
+    pub fn for_test(hw: &HwConfig, hw_config: &Path, tid: TestId, submission: &Path) -> Self {
+        let mut vars = Self::new();
+        for (name, value) in &hw.vars {
+            vars.add_var(name, value);
+        }
+
+        let hw_root = hw_config.parent().unwrap_or(Path::new(""));
+        vars.var(TEST_ID_VAR, tid.to_string())
+            .var(SUBMISSION_VAR, submission.to_string_lossy())
+            .var(STATIC_VAR, hw_root.join("static").to_string_lossy())
+    }
+
+    pub fn var<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.add_var(name, value);
+        self
+    }
+
+    pub fn add_var<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) {
+        self.values.insert(name.into(), value.into());
+    }
+
+    pub fn with_ws(&self, ws: &Path) -> Self {
+        self.clone().var(WS_VAR, ws.to_string_lossy())
+    }
+
+    pub fn expand(&self, template: &str) -> Result<String, Error> {
+        let mut expanded = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find("${") {
+            if rest[..start].ends_with('$') {
+                expanded.push_str(&rest[..start - 1]);
+                expanded.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+
+            expanded.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after.find('}').ok_or_else(|| Error::Unterminated {
+                template: template.to_string(),
+            })?;
+
+            let name = &after[..end];
+            let value = self.values.get(name).ok_or_else(|| Error::UnknownVar {
+                name: name.to_string(),
+                template: template.to_string(),
+                known: self.known(),
+            })?;
+            expanded.push_str(value);
+            rest = &after[end + 1..];
+        }
+
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    pub fn expand_all(&self, templates: &[String]) -> Result<Vec<String>, Error> {
+        templates
+            .iter()
+            .map(|template| self.expand(template))
+            .collect()
+    }
+
+    fn known(&self) -> String {
+        let mut names: Vec<&str> = self.values.keys().map(String::as_str).collect();
+        names.sort();
+        names.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vars {
+        Vars::new()
+            .var(TEST_ID_VAR, "3")
+            .var("DATA", "/data")
+            .with_ws(Path::new("/tmp/ws"))
+    }
+
+    #[test]
+    fn expands_vars() {
+        assert_eq!(
+            vars().expand("${DATA}/in_${TEST_ID}.txt").unwrap(),
+            "/data/in_3.txt"
+        );
+        assert_eq!(vars().expand("--out=${WS}").unwrap(), "--out=/tmp/ws");
+        assert_eq!(vars().expand("$HOME $1 {}").unwrap(), "$HOME $1 {}");
+        assert_eq!(vars().expand("$${DATA} $$").unwrap(), "${DATA} $$");
+    }
+
+    #[test]
+    fn unknown_vars_are_errors() {
+        assert_eq!(
+            vars().expand("${DTA}/in").unwrap_err(),
+            Error::UnknownVar {
+                name: "DTA".to_string(),
+                template: "${DTA}/in".to_string(),
+                known: "DATA, TEST_ID, WS".to_string(),
+            }
+        );
+        assert_eq!(
+            vars().expand("${DATA").unwrap_err(),
+            Error::Unterminated {
+                template: "${DATA".to_string()
+            }
+        );
+    }
+}